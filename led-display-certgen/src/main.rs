@@ -0,0 +1,92 @@
+use led_display_certgen::{
+    format_pin, generate, hashes, identity, load, load_all, load_public_key, parse_pin, store,
+};
+use std::{env, path::PathBuf, process};
+
+const USAGE: &str = "usage:
+  led-display-certgen table <out.rs> [--key <public-key.pem>] [--pin <cert|spki>:<sha256>]... <cert.pem>...
+  led-display-certgen store <out.bin> --key <signing-key.pem> --version <n> [--pin <cert|spki>:<sha256>]... <cert.pem>...
+  led-display-certgen hashes <cert.pem>...
+  led-display-certgen identity <out.bin> <panel.pem> <panel-key.pem>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (command, mut args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args.iter()),
+        None => usage(),
+    };
+
+    let out = match command {
+        "table" | "store" | "identity" => {
+            Some(PathBuf::from(args.next().unwrap_or_else(|| usage())))
+        }
+        "hashes" => None,
+        _ => usage(),
+    };
+
+    let mut key = None;
+    let mut version = None;
+    let mut pins = Vec::new();
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" => key = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--version" => version = Some(args.next().unwrap_or_else(|| usage()).parse::<u32>()?),
+            "--pin" => pins.push(parse_pin(args.next().unwrap_or_else(|| usage()))?),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        usage();
+    }
+
+    if let ("identity", Some(out)) = (command, &out) {
+        let (certificate, key) = match paths.as_slice() {
+            [certificate, key] => (certificate, key),
+            _ => usage(),
+        };
+
+        identity(&load(certificate)?[0], key, out)?;
+        println!("wrote {}", out.display());
+        return Ok(());
+    }
+
+    let roots = load_all(&paths)?;
+    match (command, out) {
+        ("table", Some(out)) => {
+            let key = key.map(|key| load_public_key(&key)).transpose()?;
+            generate(&roots, &pins, key.as_ref(), &out)?;
+            println!("wrote {}", out.display());
+        }
+        ("store", Some(out)) => {
+            let key = key.ok_or("a trust store needs the --key matching the firmware's")?;
+            let version = version
+                .ok_or("a trust store needs a --version newer than the one on the panels")?;
+            store(&roots, &pins, version, &key, &out)?;
+            println!("wrote {}", out.display());
+        }
+        _ => {
+            for root in &roots {
+                println!("{}", root.path.display());
+                for pin in hashes(root).iter() {
+                    println!("  {}", format_pin(pin));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,127 @@
+//! Writes trust anchors out as Rust source for the firmware to include
+
+use crate::{Error, Root};
+use led_display_core::{
+    trust::{Pin, TrustError, MAX_ANCHORS, MAX_PINS, PUBLIC_KEY_LEN},
+    x509::PublicKey,
+};
+use std::fmt::Write;
+
+pub fn render(
+    roots: &[Root],
+    pins: &[Pin],
+    update_key: Option<&[u8; PUBLIC_KEY_LEN]>,
+) -> Result<String, Error> {
+    // the firmware has room for the same number as a trust store
+    if roots.is_empty() {
+        return Err(TrustError::NoAnchors.into());
+    } else if roots.len() > MAX_ANCHORS {
+        return Err(TrustError::TooManyAnchors.into());
+    } else if pins.len() > MAX_PINS {
+        return Err(TrustError::TooManyPins.into());
+    }
+
+    let mut names: Vec<String> = roots
+        .iter()
+        .map(|root| {
+            let name = root.path.file_name().unwrap_or_default();
+            name.to_string_lossy().into_owned()
+        })
+        .collect();
+    names.dedup();
+
+    let mut out = String::new();
+
+    // writing to a String cannot fail
+    writeln!(
+        out,
+        "// Generated by led-display-certgen from {}, do not edit",
+        names.join(", ")
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(
+        out,
+        "static ANCHORS: [Anchor<'static>; {}] = [",
+        roots.len()
+    )
+    .unwrap();
+    for root in roots {
+        let anchor = root.anchor();
+        writeln!(out, "    Anchor {{").unwrap();
+        writeln!(out, "        subject: &{},", bytes(anchor.subject, 8)).unwrap();
+        match anchor.key {
+            PublicKey::Rsa { n, e } => {
+                writeln!(out, "        key: PublicKey::Rsa {{").unwrap();
+                writeln!(out, "            n: &{},", bytes(n, 12)).unwrap();
+                writeln!(out, "            e: &{},", bytes(e, 12)).unwrap();
+            }
+            PublicKey::Ec { curve, q } => {
+                writeln!(out, "        key: PublicKey::Ec {{").unwrap();
+                writeln!(out, "            curve: {},", curve).unwrap();
+                writeln!(out, "            q: &{},", bytes(q, 12)).unwrap();
+            }
+        }
+        writeln!(out, "        }},").unwrap();
+        writeln!(out, "        ca: {},", anchor.ca).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "static PINS: [Pin; {}] = [", pins.len()).unwrap();
+    for pin in pins {
+        let (kind, hash) = match pin {
+            Pin::Certificate(hash) => ("Certificate", hash),
+            Pin::Spki(hash) => ("Spki", hash),
+        };
+        writeln!(out, "    Pin::{}({}),", kind, bytes(hash, 4)).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    match update_key {
+        Some(key) => writeln!(
+            out,
+            "static UPDATE_KEY: Option<[u8; {}]> = Some({});",
+            PUBLIC_KEY_LEN,
+            bytes(key, 0)
+        ),
+        None => writeln!(
+            out,
+            "static UPDATE_KEY: Option<[u8; {}]> = None;",
+            PUBLIC_KEY_LEN
+        ),
+    }
+    .unwrap();
+
+    Ok(out)
+}
+
+// an array literal for a line indented by indent spaces, 12 bytes to a line
+fn bytes(data: &[u8], indent: usize) -> String {
+    const PER_LINE: usize = 12;
+    if data.len() <= PER_LINE {
+        let bytes: Vec<String> = data.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+        return format!("[{}]", bytes.join(", "));
+    }
+
+    let mut out = String::from("[\n");
+    for chunk in data.chunks(PER_LINE) {
+        let bytes: Vec<String> = chunk
+            .iter()
+            .map(|byte| format!("0x{:02X},", byte))
+            .collect();
+        writeln!(
+            out,
+            "{:indent$}{}",
+            "",
+            bytes.join(" "),
+            indent = indent + 4
+        )
+        .unwrap();
+    }
+    write!(out, "{:indent$}]", "", indent = indent).unwrap();
+    out
+}
@@ -0,0 +1,15 @@
+use std::{env, path::PathBuf};
+
+// the font used by both firmware crates
+const FONT: &str = "../fonts/panel.bdf";
+
+fn main() {
+    // converts the font into a glyph table for src/display/font.rs to include
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("font.rs");
+    if let Err(err) = led_display_fontgen::generate(FONT.as_ref(), &out) {
+        panic!("failed to generate the font table: {}", err);
+    }
+
+    println!("cargo:rerun-if-changed=../fonts");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,185 @@
+use crate::{
+    brightness::Brightness,
+    calendar::DateTime,
+    display::{Effect, LedPanel, LedPanelError},
+    idle::IdleScreen,
+    protocol::{self, Addressed, Binary, Command, Frame},
+    trust::{Summary, TrustError},
+};
+use core::fmt::{self, Write as _};
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+use heapless::String;
+
+// What the panel does with the frames it receives
+// Messages and commands from the server go in through on_text() and on_binary() and poll() is
+// called from the main loop to keep the panel moving and the brightness and idle screen up to
+// date. Anything the board has to provide comes through the Board trait.
+
+// how often the brightness and idle screen are brought up to date
+const CONTROLS_INTERVAL_MS: u32 = 1_000;
+
+pub const MAX_REPORT_LEN: usize = 64;
+
+// a text frame for the server starting with protocol::REPORT_PREFIX
+pub type Report = String<MAX_REPORT_LEN>;
+
+// What the application needs from the board apart from the panel itself
+pub trait Board {
+    // milliseconds since startup, wrapping after about 49 days
+    fn now_ms(&self) -> u32;
+
+    // a raw 12 bit reading from the light sensor if one is fitted
+    fn ambient_light(&mut self) -> Option<u16> {
+        None
+    }
+
+    // the local date and time if known
+    fn local_time(&self) -> Option<DateTime> {
+        None
+    }
+
+    // checks and saves a trust store sent by the server, it is used from the next connection
+    fn update_trust_store(&mut self, _store: &[u8]) -> Result<Summary, TrustError> {
+        Err(TrustError::Unsupported)
+    }
+}
+
+pub struct App<'a, SPI, CS> {
+    panel: LedPanel<'a, SPI, CS>,
+    brightness: Brightness,
+    idle_screen: IdleScreen,
+    // None to update the controls on the next poll
+    controls_updated_ms: Option<u32>,
+    // a report waiting to be sent to the server, see report()
+    report: Option<Report>,
+}
+
+impl<'a, SPI, CS, SpiError, PinError> App<'a, SPI, CS>
+where
+    SPI: Write<u8, Error = SpiError>,
+    CS: OutputPin<Error = PinError>,
+    SpiError: fmt::Debug,
+    PinError: fmt::Debug,
+{
+    pub fn new(panel: LedPanel<'a, SPI, CS>) -> Self {
+        Self {
+            panel,
+            brightness: Brightness::new(),
+            idle_screen: IdleScreen::new(),
+            controls_updated_ms: None,
+            report: None,
+        }
+    }
+
+    pub fn panel(&self) -> &LedPanel<'a, SPI, CS> {
+        &self.panel
+    }
+
+    pub fn panel_mut(&mut self) -> &mut LedPanel<'a, SPI, CS> {
+        &mut self.panel
+    }
+
+    // a text frame from the server, either a message to show or a command
+    pub fn on_text(&mut self, text: &str, now_ms: u32) {
+        match protocol::parse(text) {
+            Ok(Addressed {
+                zone,
+                frame: Frame::Message(message),
+            }) => self.panel.queue_message(zone, Effect::ScrollLeft, message),
+            Ok(Addressed {
+                zone,
+                frame: Frame::Command(command),
+            }) => self.handle_command(zone, command, now_ms),
+            Err(error) => rprintln!("[WRN] Invalid command: {:?}", error),
+        }
+    }
+
+    // a binary frame from the server
+    pub fn on_binary(&mut self, data: &[u8], board: &mut impl Board) {
+        match protocol::parse_binary(data) {
+            Ok(Binary::Bitmap { zone, bitmap }) => self.panel.queue_bitmap(zone, &bitmap),
+            Ok(Binary::TrustStore(store)) => match board.update_trust_store(store) {
+                Ok(summary) => self.report(format_args!(
+                    "Trust store {} updated: {} anchors, {} pins",
+                    summary.version, summary.anchors, summary.pins
+                )),
+                Err(error) => self.report(format_args!("Trust store not updated: {:?}", error)),
+            },
+            Err(error) => rprintln!("[WRN] Invalid binary frame: {:?}", error),
+        }
+    }
+
+    // draws the next frame, call this as often as possible
+    pub fn poll(
+        &mut self,
+        board: &mut impl Board,
+    ) -> Result<(), LedPanelError<SpiError, PinError>> {
+        let now_ms = board.now_ms();
+        match self.controls_updated_ms {
+            Some(updated_ms) if now_ms.wrapping_sub(updated_ms) < CONTROLS_INTERVAL_MS => {}
+            _ => {
+                self.controls_updated_ms = Some(now_ms);
+                self.update_controls(board)?;
+            }
+        }
+
+        self.panel.poll(now_ms)
+    }
+
+    // Logs something and tells the server on the next chance, e.g. how a trust store update or
+    // the TLS handshake went. A report that has not been sent yet is replaced.
+    pub fn report(&mut self, args: fmt::Arguments) {
+        rprintln!("[INF] {}", args);
+        let mut text = Report::new();
+        let _ = write!(text, "{}{}", protocol::REPORT_PREFIX, args);
+        self.report = Some(text);
+    }
+
+    // something to tell the server, e.g. the result of a self test
+    pub fn take_report(&mut self) -> Option<Report> {
+        if let Some(report) = self.report.take() {
+            return Some(report);
+        }
+
+        let report = self.panel.take_self_test_report()?;
+        let mut text = Report::new();
+        let _ = write!(text, "{}{}", protocol::REPORT_PREFIX, report);
+        Some(text)
+    }
+
+    fn handle_command(&mut self, zone: Option<&str>, command: Command, now_ms: u32) {
+        rprintln!("[INF] Command: {:?} zone: {:?}", command, zone);
+        match command {
+            Command::Speed(cols_per_sec) => self.panel.set_speed(zone, cols_per_sec, now_ms),
+            Command::Show { effect, text } => self.panel.queue_message(zone, effect, text),
+            Command::Zone { name, first, last } => {
+                self.panel.define_zone(name, first as usize, last as usize)
+            }
+            // the whole panel shares one brightness so the zone does not matter
+            Command::Brightness(level) => {
+                self.brightness.set_override(level);
+                self.controls_updated_ms = None;
+            }
+            Command::Idle(idle) => {
+                self.idle_screen.set(idle);
+                self.controls_updated_ms = None;
+            }
+            Command::SelfTest => self.panel.start_self_test(now_ms),
+        }
+    }
+
+    fn update_controls(
+        &mut self,
+        board: &mut impl Board,
+    ) -> Result<(), LedPanelError<SpiError, PinError>> {
+        if let Some(raw) = board.ambient_light() {
+            self.brightness.on_ambient(raw);
+        }
+
+        let now = board.local_time();
+        self.panel
+            .set_idle_screen(&self.idle_screen.text(now.as_ref()));
+        self.panel
+            .set_brightness(self.brightness.level(now.map(|now| now.minutes_of_day())))
+    }
+}
@@ -0,0 +1,161 @@
+// Panel brightness
+// The MAX7219 intensity register (0-15) is picked from, in order of priority:
+//   a level sent with "#brightness <0-15>" until "#brightness auto" hands control back
+//   the ambient light sensor, if one is fitted and has been read
+//   the SCHEDULE below, once the local time is known
+//   DEFAULT_LEVEL
+
+pub const MAX_LEVEL: u8 = 15;
+pub const DEFAULT_LEVEL: u8 = 10;
+
+// (minutes since midnight, level) sorted by time. The last entry that has started applies,
+// before the first one of the day the last one from the day before is still in force.
+const SCHEDULE: [(u32, u8); 4] = [(6 * 60 + 30, 6), (8 * 60, 12), (19 * 60, 6), (22 * 60, 2)];
+
+// the ADC is 12 bits and reads higher the more light falls on the sensor
+const AMBIENT_MAX: u32 = 4095;
+
+// readings are smoothed so that a passing shadow does not make the panel flicker, the
+// average is kept with 4 extra bits of precision and every reading moves it 1/8 of the way
+const AMBIENT_SHIFT: u32 = 4;
+const AMBIENT_WEIGHT: u32 = 8;
+
+// the smoothed reading has to move this far past the edge of a level before it changes
+const AMBIENT_HYSTERESIS: u32 = 64;
+
+pub struct Brightness {
+    override_level: Option<u8>,
+    ambient: Option<u32>,
+    ambient_level: u8,
+}
+
+impl Default for Brightness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Brightness {
+    pub fn new() -> Self {
+        Self {
+            override_level: None,
+            ambient: None,
+            ambient_level: DEFAULT_LEVEL,
+        }
+    }
+
+    // a fixed level, None to go back to the sensor or schedule
+    pub fn set_override(&mut self, level: Option<u8>) {
+        self.override_level = level.map(|level| level.min(MAX_LEVEL));
+    }
+
+    // a raw reading from the light sensor (0-4095)
+    pub fn on_ambient(&mut self, raw: u16) {
+        let raw = (raw as u32).min(AMBIENT_MAX) << AMBIENT_SHIFT;
+        let ambient = match self.ambient {
+            Some(ambient) => ambient - ambient / AMBIENT_WEIGHT + raw / AMBIENT_WEIGHT,
+            None => {
+                self.ambient_level = ambient_level(raw >> AMBIENT_SHIFT);
+                raw
+            }
+        };
+        self.ambient = Some(ambient);
+
+        // only move to the next level once the reading is well inside it
+        let ambient = ambient >> AMBIENT_SHIFT;
+        let level = ambient_level(ambient);
+        let step = (AMBIENT_MAX + 1) / (MAX_LEVEL as u32 + 1);
+        let current = self.ambient_level as u32;
+        let up = level > self.ambient_level && ambient >= (current + 1) * step + AMBIENT_HYSTERESIS;
+        let down = level < self.ambient_level && ambient + AMBIENT_HYSTERESIS < current * step;
+        if up || down {
+            self.ambient_level = level;
+        }
+    }
+
+    // the level the panel should be at, minutes_of_day is the local time if known
+    pub fn level(&self, minutes_of_day: Option<u32>) -> u8 {
+        if let Some(level) = self.override_level {
+            return level;
+        }
+
+        if self.ambient.is_some() {
+            return self.ambient_level;
+        }
+
+        match minutes_of_day {
+            Some(minutes) => scheduled_level(minutes),
+            None => DEFAULT_LEVEL,
+        }
+    }
+}
+
+fn ambient_level(ambient: u32) -> u8 {
+    (ambient * (MAX_LEVEL as u32 + 1) / (AMBIENT_MAX + 1)) as u8
+}
+
+fn scheduled_level(minutes_of_day: u32) -> u8 {
+    SCHEDULE
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= minutes_of_day)
+        .or_else(|| SCHEDULE.last())
+        .map(|(_, level)| *level)
+        .unwrap_or(DEFAULT_LEVEL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_without_time_or_sensor() {
+        assert_eq!(Brightness::new().level(None), DEFAULT_LEVEL);
+    }
+
+    #[test]
+    fn schedule() {
+        let brightness = Brightness::new();
+        assert_eq!(brightness.level(Some(0)), 2);
+        assert_eq!(brightness.level(Some(6 * 60 + 30)), 6);
+        assert_eq!(brightness.level(Some(12 * 60)), 12);
+        assert_eq!(brightness.level(Some(21 * 60)), 6);
+        assert_eq!(brightness.level(Some(23 * 60)), 2);
+    }
+
+    #[test]
+    fn override_wins_until_auto() {
+        let mut brightness = Brightness::new();
+        brightness.on_ambient(4095);
+        brightness.set_override(Some(3));
+        assert_eq!(brightness.level(Some(12 * 60)), 3);
+
+        brightness.set_override(Some(99));
+        assert_eq!(brightness.level(None), MAX_LEVEL);
+
+        brightness.set_override(None);
+        assert_eq!(brightness.level(Some(12 * 60)), MAX_LEVEL);
+    }
+
+    #[test]
+    fn ambient_is_smoothed_with_hysteresis() {
+        let mut brightness = Brightness::new();
+        brightness.on_ambient(0);
+        assert_eq!(brightness.level(None), 0);
+
+        // a single bright reading only moves it part of the way
+        brightness.on_ambient(4095);
+        assert_eq!(brightness.level(None), 1);
+
+        // settling just below the edge of the level is not enough to go back down
+        for _ in 0..100 {
+            brightness.on_ambient(250);
+        }
+        assert_eq!(brightness.level(None), 1);
+
+        for _ in 0..100 {
+            brightness.on_ambient(2200);
+        }
+        assert_eq!(brightness.level(None), 8);
+    }
+}
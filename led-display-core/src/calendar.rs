@@ -0,0 +1,202 @@
+// Local date and time from unix time
+// There is no real time clock we can rely on (see time.rs in the firmware) so the time comes
+// from NTP and the millisecond clock in between. This works out the local calendar date and time
+// of day for a fixed offset from UTC plus one of the common daylight saving rules.
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const SECS_PER_HOUR: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dst {
+    // no daylight saving
+    None,
+    // European Union and UK: +1 hour from 01:00 UTC on the last Sunday in March to 01:00 UTC on
+    // the last Sunday in October
+    Eu,
+    // United States and Canada: +1 hour from 02:00 local time on the second Sunday in March to
+    // 02:00 local time on the first Sunday in November
+    Us,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimeZone {
+    // standard (winter) time offset from UTC
+    pub offset_secs: i32,
+    pub dst: Dst,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i32,
+    // 1-12
+    pub month: u8,
+    // 1-31
+    pub day: u8,
+    // 0 is Monday
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl TimeZone {
+    pub fn local(&self, unix_time: i64) -> DateTime {
+        let mut offset = self.offset_secs as i64;
+        if self.is_dst(unix_time) {
+            offset += SECS_PER_HOUR;
+        }
+
+        DateTime::from_unix(unix_time + offset)
+    }
+
+    fn is_dst(&self, unix_time: i64) -> bool {
+        let offset = self.offset_secs as i64;
+        let year = DateTime::from_unix(unix_time + offset).year;
+        let (start, end) = match self.dst {
+            Dst::None => return false,
+            Dst::Eu => (
+                last_sunday(year, 3) * SECS_PER_DAY + SECS_PER_HOUR,
+                last_sunday(year, 10) * SECS_PER_DAY + SECS_PER_HOUR,
+            ),
+            // the change back happens at 02:00 daylight time which is 01:00 standard time
+            Dst::Us => (
+                nth_sunday(year, 3, 2) * SECS_PER_DAY + 2 * SECS_PER_HOUR - offset,
+                nth_sunday(year, 11, 1) * SECS_PER_DAY + SECS_PER_HOUR - offset,
+            ),
+        };
+
+        unix_time >= start && unix_time < end
+    }
+}
+
+impl DateTime {
+    pub fn from_unix(time: i64) -> Self {
+        let days = time.div_euclid(SECS_PER_DAY);
+        let secs = time.rem_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+
+        DateTime {
+            year,
+            month,
+            day,
+            weekday: weekday(days),
+            hour: (secs / SECS_PER_HOUR) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    pub fn minutes_of_day(&self) -> u32 {
+        self.hour as u32 * 60 + self.minute as u32
+    }
+}
+
+// 1970-01-01 was a Thursday
+fn weekday(days: i64) -> u8 {
+    (days + 3).rem_euclid(7) as u8
+}
+
+// days since 1970-01-01 of the last Sunday in the month
+fn last_sunday(year: i32, month: u8) -> i64 {
+    let (year, month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+
+    let last_day = days_from_civil(year, month, 1) - 1;
+    last_day - (weekday(last_day) as i64 + 1) % 7
+}
+
+// days since 1970-01-01 of the nth (from 1) Sunday in the month
+fn nth_sunday(year: i32, month: u8, n: i64) -> i64 {
+    let first_day = days_from_civil(year, month, 1);
+    let first_sunday = first_day + (6 - weekday(first_day) as i64);
+    first_sunday + (n - 1) * 7
+}
+
+// Conversions between days since 1970-01-01 and the proleptic Gregorian calendar, see
+// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year } as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unix(year: i32, month: u8, day: u8, hour: i64) -> i64 {
+        days_from_civil(year, month, day) * SECS_PER_DAY + hour * SECS_PER_HOUR
+    }
+
+    #[test]
+    fn from_unix() {
+        let time = DateTime::from_unix(unix(2024, 2, 29, 13) + 125);
+        assert_eq!(
+            time,
+            DateTime {
+                year: 2024,
+                month: 2,
+                day: 29,
+                weekday: 3,
+                hour: 13,
+                minute: 2,
+                second: 5,
+            }
+        );
+        assert_eq!(time.minutes_of_day(), 13 * 60 + 2);
+        assert_eq!(DateTime::from_unix(0).weekday, 3);
+        assert_eq!(DateTime::from_unix(-1).year, 1969);
+    }
+
+    #[test]
+    fn eu_daylight_saving() {
+        let uk = TimeZone {
+            offset_secs: 0,
+            dst: Dst::Eu,
+        };
+
+        // 2026: from 01:00 UTC on 29 March to 01:00 UTC on 25 October
+        assert_eq!(uk.local(unix(2026, 3, 29, 1) - 1).hour, 0);
+        assert_eq!(uk.local(unix(2026, 3, 29, 1)).hour, 2);
+        assert_eq!(uk.local(unix(2026, 10, 25, 1) - 1).hour, 1);
+        assert_eq!(uk.local(unix(2026, 10, 25, 1)).hour, 1);
+        assert_eq!(uk.local(unix(2026, 12, 31, 23)).hour, 23);
+    }
+
+    #[test]
+    fn us_daylight_saving() {
+        let new_york = TimeZone {
+            offset_secs: -5 * 3600,
+            dst: Dst::Us,
+        };
+
+        // 2026: from 02:00 EST on 8 March to 02:00 EDT on 1 November
+        assert_eq!(new_york.local(unix(2026, 3, 8, 7) - 1).hour, 1);
+        assert_eq!(new_york.local(unix(2026, 3, 8, 7)).hour, 3);
+        assert_eq!(new_york.local(unix(2026, 11, 1, 6) - 1).hour, 1);
+        assert_eq!(new_york.local(unix(2026, 11, 1, 6)).hour, 1);
+        assert_eq!(new_york.local(unix(2026, 11, 1, 7)).hour, 2);
+    }
+}
@@ -0,0 +1,537 @@
+use crate::{brightness::DEFAULT_LEVEL, idle::IdleText};
+use core::{cell::RefCell, fmt};
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+use heapless::Vec;
+
+mod bitmap;
+mod effects;
+mod font;
+mod framebuffer;
+mod max7219;
+mod selftest;
+mod shadow;
+mod zone;
+
+pub use bitmap::Bitmap;
+pub use effects::Effect;
+use framebuffer::{Columns, FrameBuffer, Window};
+use max7219::{Command, Max7219Chain};
+use selftest::Step;
+use shadow::Shadow;
+pub use zone::MAX_NAME_LEN as MAX_ZONE_NAME_LEN;
+use zone::{Zone, ZoneName};
+
+// number of daisy chained MAX7219 modules (8x8 pixels each)
+pub const NUM_DEVICES: usize = 20;
+const WIDTH: usize = NUM_DEVICES * 8;
+
+// largest bitmap (all frames) that can be shown, only one bitmap is kept at a time
+pub const MAX_BITMAP_LEN: usize = 512;
+
+// the panel starts out as a single zone with this name, messages that are not addressed to a
+// zone go here. If the zones are redefined without it they go to the oldest zone instead.
+const DEFAULT_ZONE: &str = "main";
+const MAX_ZONES: usize = 3;
+
+// A glitch on the supply or a noisy clock line can knock a module into shutdown or display test
+// mode or scramble its rows. Every so often the settings are written again and the whole frame
+// is resent. Unlike init() this does not blank the panel so it cannot be seen.
+const REFRESH_INTERVAL_MS: u32 = 10_000;
+
+#[derive(Debug)]
+pub enum LedPanelError<SpiError, PinError> {
+    Max7219(max7219::Error<SpiError, PinError>),
+}
+
+// The panel is a state machine that is advanced by calling poll() regularly from the main loop.
+// Each call renders every zone into an off-screen frame buffer and only sends the rows that
+// changed to the panel so that the network can be serviced in between.
+// The SPI bus is shared with the network card, hence the RefCell.
+pub struct LedPanel<'a, SPI, CS> {
+    max7219: Max7219Chain<'a, CS>,
+    spi: &'a RefCell<SPI>,
+    fb: Columns<WIDTH>,
+    shadow: Shadow<NUM_DEVICES>,
+    zones: Vec<Zone, MAX_ZONES>,
+    bitmap: StoredBitmap,
+    status: Option<&'static str>,
+    idle_screen: IdleText,
+    brightness: u8,
+    // None until the devices have been initialised
+    refreshed_ms: Option<u32>,
+    self_test: Option<SelfTest>,
+    self_test_report: Option<SelfTestReport>,
+}
+
+struct SelfTest {
+    start_ms: u32,
+    // None until the first step has been drawn
+    step: Option<Step>,
+    spi_errors: u32,
+}
+
+// what we know after a self test, whether every module lit up has to be checked by eye
+#[derive(Debug)]
+pub struct SelfTestReport {
+    pub modules: usize,
+    pub spi_errors: u32,
+}
+
+struct StoredBitmap {
+    width: u8,
+    frames: u8,
+    timed: bool,
+    data: Vec<u8, MAX_BITMAP_LEN>,
+}
+
+impl<SpiError, PinError> From<max7219::Error<SpiError, PinError>>
+    for LedPanelError<SpiError, PinError>
+{
+    fn from(err: max7219::Error<SpiError, PinError>) -> Self {
+        LedPanelError::Max7219(err)
+    }
+}
+
+impl<'a, SPI, CS, SpiError, PinError> LedPanel<'a, SPI, CS>
+where
+    SPI: Write<u8, Error = SpiError>,
+    CS: OutputPin<Error = PinError>,
+    SpiError: fmt::Debug,
+    PinError: fmt::Debug,
+{
+    pub fn new(cs: &'a mut CS, spi: &'a RefCell<SPI>) -> Self {
+        let mut zones = Vec::new();
+        let _ = zones.push(Zone::new(ZoneName::from(DEFAULT_ZONE), 0, NUM_DEVICES));
+
+        LedPanel {
+            max7219: Max7219Chain::new(cs, NUM_DEVICES),
+            spi,
+            fb: Columns::new(),
+            shadow: Shadow::new(),
+            zones,
+            bitmap: StoredBitmap {
+                width: 0,
+                frames: 0,
+                timed: false,
+                data: Vec::new(),
+            },
+            status: None,
+            idle_screen: IdleText::new(),
+            brightness: DEFAULT_LEVEL,
+            refreshed_ms: None,
+            self_test: None,
+            self_test_report: None,
+        }
+    }
+
+    // Creates (or moves) a zone covering modules first to last, numbered from 1 at the left.
+    // Any zones it overlaps are removed along with their messages.
+    pub fn define_zone(&mut self, name: &str, first: usize, last: usize) {
+        if first < 1 || last < first || last > NUM_DEVICES {
+            rprintln!(
+                "[WRN] Zone {} must be within modules 1-{}",
+                name,
+                NUM_DEVICES
+            );
+            return;
+        }
+
+        let mut zone_name = ZoneName::new();
+        if zone_name.push_str(name).is_err() {
+            rprintln!("[WRN] Zone name {} is too long", name);
+            return;
+        }
+
+        let first = first - 1;
+        let count = last - first;
+        let fb = &mut self.fb;
+        self.zones.retain(|zone| {
+            let keep = zone.name() != name && !zone.overlaps(first, count);
+            if !keep {
+                let (x, width) = zone.columns();
+                Window::new(fb, x, width).clear();
+            }
+            keep
+        });
+
+        if self.zones.push(Zone::new(zone_name, first, count)).is_err() {
+            rprintln!("[WRN] Too many zones, {} not created", name);
+        }
+
+        if !self.zones.iter().any(|zone| zone.name() == DEFAULT_ZONE) {
+            let default = self.default_zone();
+            rprintln!(
+                "[INF] No zone called {}, messages without a zone go to {}",
+                DEFAULT_ZONE,
+                self.zones[default].name()
+            );
+            self.zones[default].invalidate_idle();
+        }
+    }
+
+    // adds a message to the end of the zone's queue (the default zone if None)
+    pub fn queue_message(&mut self, zone: Option<&str>, effect: Effect, message: &str) {
+        if let Some(zone) = self.zone_mut(zone) {
+            zone.queue_message(effect, message);
+        }
+    }
+
+    // adds a bitmap to the end of the zone's queue. There is only room for one bitmap so it
+    // replaces any bitmap that is waiting or on the panel.
+    pub fn queue_bitmap(&mut self, zone: Option<&str>, bitmap: &Bitmap) {
+        let data = match Vec::from_slice(bitmap.data()) {
+            Ok(data) => data,
+            Err(()) => {
+                rprintln!(
+                    "[WRN] Bitmap too large ({} bytes, max {}), ignoring",
+                    bitmap.data().len(),
+                    MAX_BITMAP_LEN
+                );
+                return;
+            }
+        };
+
+        if self.zone_mut(zone).is_none() {
+            return;
+        }
+
+        for zone in self.zones.iter_mut() {
+            zone.drop_bitmap();
+        }
+
+        self.bitmap = StoredBitmap {
+            width: bitmap.width(),
+            frames: bitmap.frames(),
+            timed: bitmap.timed(),
+            data,
+        };
+
+        if let Some(zone) = self.zone_mut(zone) {
+            zone.queue_bitmap();
+        }
+    }
+
+    // sets the scroll speed of the zone in columns per second
+    pub fn set_speed(&mut self, zone: Option<&str>, cols_per_sec: u32, now_ms: u32) {
+        if let Some(zone) = self.zone_mut(zone) {
+            zone.set_speed(cols_per_sec, now_ms);
+        }
+    }
+
+    // a short static message (e.g. while offline) shown by zones that have nothing else to display
+    pub fn set_status(&mut self, status: Option<&'static str>) {
+        if self.status != status {
+            self.status = status;
+            for zone in self.zones.iter_mut() {
+                zone.invalidate_idle();
+            }
+        }
+    }
+
+    // what the default zone shows once it has been idle for a while (e.g. the time), empty for nothing
+    pub fn set_idle_screen(&mut self, text: &IdleText) {
+        if self.idle_screen != *text {
+            self.idle_screen = text.clone();
+            let default = self.default_zone();
+            self.zones[default].invalidate_idle();
+        }
+    }
+
+    // sets the MAX7219 intensity (0-15), the devices are only written to when it changes
+    pub fn set_brightness(&mut self, level: u8) -> Result<(), LedPanelError<SpiError, PinError>> {
+        if level == self.brightness {
+            return Ok(());
+        }
+
+        rprintln!("[INF] Brightness {}", level);
+        self.brightness = level;
+        let spi = &mut *self.spi.borrow_mut();
+        self.max7219
+            .write_command_all(spi, Command::Intensity, level)?;
+        Ok(())
+    }
+
+    // Takes over the whole panel for a sequence of test patterns (see selftest.rs), messages keep
+    // queueing up in the meantime. Errors talking to the panel are counted rather than returned.
+    pub fn start_self_test(&mut self, now_ms: u32) {
+        rprintln!("[INF] Self test started");
+        self.self_test = Some(SelfTest {
+            start_ms: now_ms,
+            step: None,
+            spi_errors: 0,
+        });
+        self.self_test_report = None;
+    }
+
+    pub fn self_test_running(&self) -> bool {
+        self.self_test.is_some()
+    }
+
+    // the result of the last self test, only returned once
+    pub fn take_self_test_report(&mut self) -> Option<SelfTestReport> {
+        self.self_test_report.take()
+    }
+
+    // draws the next frame if anything changed
+    pub fn poll(&mut self, now_ms: u32) -> Result<(), LedPanelError<SpiError, PinError>> {
+        if self.refreshed_ms.is_none() {
+            self.init()?;
+            self.refreshed_ms = Some(now_ms);
+        }
+
+        if self.self_test.is_some() {
+            self.poll_self_test(now_ms);
+            return Ok(());
+        }
+
+        let refreshed_ms = self.refreshed_ms.unwrap_or(now_ms);
+        if now_ms.wrapping_sub(refreshed_ms) >= REFRESH_INTERVAL_MS {
+            self.refresh()?;
+            self.refreshed_ms = Some(now_ms);
+        }
+
+        let bitmap = self.bitmap.as_bitmap();
+        let default = self.default_zone();
+        for (index, zone) in self.zones.iter_mut().enumerate() {
+            let (x, width) = zone.columns();
+            let mut window = Window::new(&mut self.fb, x, width);
+            let idle_screen = if index == default && !self.idle_screen.is_empty() {
+                Some(self.idle_screen.as_str())
+            } else {
+                None
+            };
+            zone.poll(
+                &mut window,
+                now_ms,
+                bitmap.as_ref(),
+                self.status,
+                idle_screen,
+            );
+        }
+
+        self.flush()
+    }
+
+    fn poll_self_test(&mut self, now_ms: u32) {
+        let (start_ms, current) = match &self.self_test {
+            Some(test) => (test.start_ms, test.step),
+            None => return,
+        };
+
+        let step = selftest::step(now_ms.wrapping_sub(start_ms), NUM_DEVICES);
+        if step == current {
+            return;
+        }
+
+        if let Some(step) = step {
+            rprintln!("[INF] Self test: {:?}", step);
+        }
+
+        let result = self.draw_self_test(step);
+        let test = match &mut self.self_test {
+            Some(test) => test,
+            None => return,
+        };
+
+        test.step = step;
+        if let Err(error) = result {
+            rprintln!("[ERR] Self test: {:?}", error);
+            test.spi_errors += 1;
+        }
+
+        if step.is_none() {
+            let report = SelfTestReport {
+                modules: NUM_DEVICES,
+                spi_errors: test.spi_errors,
+            };
+
+            rprintln!("[INF] {}", report);
+            self.self_test = None;
+            self.self_test_report = Some(report);
+            for zone in self.zones.iter_mut() {
+                zone.invalidate_idle();
+            }
+        }
+    }
+
+    // None puts the panel back to normal
+    fn draw_self_test(
+        &mut self,
+        step: Option<Step>,
+    ) -> Result<(), LedPanelError<SpiError, PinError>> {
+        {
+            let spi = &mut *self.spi.borrow_mut();
+            let display_test = (step == Some(Step::DisplayTest)) as u8;
+            self.max7219
+                .write_command_all(spi, Command::DisplayTest, display_test)?;
+        }
+
+        match step {
+            Some(step) => selftest::draw(step, &mut self.fb),
+            None => self.fb.clear(),
+        }
+
+        self.flush()
+    }
+
+    // the index of the zone that gets messages without a zone and shows the idle screen
+    // There is always at least one zone because defining a zone only removes the ones it replaces.
+    fn default_zone(&self) -> usize {
+        self.zones
+            .iter()
+            .position(|zone| zone.name() == DEFAULT_ZONE)
+            .unwrap_or(0)
+    }
+
+    fn zone_mut(&mut self, name: Option<&str>) -> Option<&mut Zone> {
+        let name = match name {
+            Some(name) => name,
+            None => {
+                let default = self.default_zone();
+                return self.zones.get_mut(default);
+            }
+        };
+        let zone = self.zones.iter_mut().find(|zone| zone.name() == name);
+        if zone.is_none() {
+            rprintln!("[WRN] No zone called {}", name);
+        }
+
+        zone
+    }
+
+    // sends the rows of the frame buffer that differ from what the panel is showing
+    fn flush(&mut self) -> Result<(), LedPanelError<SpiError, PinError>> {
+        let spi = &mut *self.spi.borrow_mut();
+        self.shadow.flush(&self.fb, &mut self.max7219, spi)?;
+        Ok(())
+    }
+
+    // puts the devices into a known state with a blank display, only needed once at startup
+    fn init(&mut self) -> Result<(), LedPanelError<SpiError, PinError>> {
+        let spi = &mut *self.spi.borrow_mut();
+        let max7219 = &mut self.max7219;
+        let brightness = self.brightness;
+
+        // clear the display and set defaults
+        max7219.write_command_all(spi, Command::OnOff, 0)?;
+        max7219.write_command_all(spi, Command::ScanLimit, 7)?;
+        max7219.write_command_all(spi, Command::Intensity, brightness)?;
+        max7219.write_command_all(spi, Command::DecodeMode, 0)?;
+        max7219.write_command_all(spi, Command::DisplayTest, 0)?;
+        max7219.clear_all(spi)?;
+        max7219.write_command_all(spi, Command::OnOff, 1)?;
+
+        self.shadow.clear();
+        Ok(())
+    }
+
+    // writes the settings again without blanking the display and has the next flush send every row
+    fn refresh(&mut self) -> Result<(), LedPanelError<SpiError, PinError>> {
+        let spi = &mut *self.spi.borrow_mut();
+        let max7219 = &mut self.max7219;
+
+        max7219.write_command_all(spi, Command::DisplayTest, 0)?;
+        max7219.write_command_all(spi, Command::ScanLimit, 7)?;
+        max7219.write_command_all(spi, Command::DecodeMode, 0)?;
+        max7219.write_command_all(spi, Command::Intensity, self.brightness)?;
+        max7219.write_command_all(spi, Command::OnOff, 1)?;
+
+        self.shadow.invalidate();
+        Ok(())
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Self test done: {} modules, {} SPI errors",
+            self.modules, self.spi_errors
+        )
+    }
+}
+
+impl StoredBitmap {
+    fn as_bitmap(&self) -> Option<Bitmap<'_>> {
+        Bitmap::new(self.width, self.frames, self.timed, &self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    struct Spi;
+
+    impl Write<u8> for Spi {
+        type Error = Infallible;
+
+        fn write(&mut self, _words: &[u8]) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct Pin;
+
+    impl OutputPin for Pin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    // the modules (numbered from 1) with something drawn on them
+    fn lit_modules(panel: &LedPanel<Spi, Pin>) -> std::vec::Vec<usize> {
+        (0..NUM_DEVICES)
+            .filter(|module| (0..8).any(|x| panel.fb.column((module * 8 + x) as i32) != 0))
+            .map(|module| module + 1)
+            .collect()
+    }
+
+    #[test]
+    fn redefined_default_zone() {
+        let spi = RefCell::new(Spi);
+        let mut pin = Pin;
+        let mut panel = LedPanel::new(&mut pin, &spi);
+        panel.define_zone("clock", 1, 5);
+        panel.define_zone("main", 6, 20);
+
+        panel.queue_message(None, Effect::Static, "Hi");
+        panel.poll(0).unwrap();
+        assert!(lit_modules(&panel).iter().all(|module| *module >= 6));
+        assert!(!lit_modules(&panel).is_empty());
+    }
+
+    #[test]
+    fn oldest_zone_is_the_default_without_main() {
+        let spi = RefCell::new(Spi);
+        let mut pin = Pin;
+        let mut panel = LedPanel::new(&mut pin, &spi);
+        panel.define_zone("left", 1, 10);
+        panel.define_zone("right", 11, 20);
+        assert_eq!(panel.default_zone(), 0);
+
+        panel.queue_message(None, Effect::Static, "Hi");
+        panel.poll(0).unwrap();
+        let lit = lit_modules(&panel);
+        assert!(!lit.is_empty() && lit.iter().all(|module| *module <= 10));
+
+        // the idle screen moves along with the messages
+        panel.set_idle_screen(&IdleText::from("12:30"));
+        for now_ms in [effects::HOLD_MS, effects::HOLD_MS + 1] {
+            panel.poll(now_ms).unwrap();
+        }
+        assert!(lit_modules(&panel).is_empty());
+        panel
+            .poll(effects::HOLD_MS + 1 + zone::IDLE_DELAY_MS)
+            .unwrap();
+        let lit = lit_modules(&panel);
+        assert!(!lit.is_empty() && lit.iter().all(|module| *module <= 10));
+    }
+}
@@ -0,0 +1,103 @@
+use super::{effects::HOLD_MS, framebuffer::FrameBuffer};
+
+// Packed 1 bit images, optionally animated
+// Every column is one byte with bit 0 at the top, the same as the frame buffer, so drawing a
+// frame is a copy. An animation is a list of frames of the same width, each optionally preceded
+// by how long it stays up in ms (u16 little endian). Frames without a duration are shown for
+// DEFAULT_FRAME_MS, or HOLD_MS if there is only one.
+
+const DEFAULT_FRAME_MS: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bitmap<'a> {
+    width: u8,
+    frames: u8,
+    timed: bool,
+    data: &'a [u8],
+}
+
+impl<'a> Bitmap<'a> {
+    // returns None if the data does not hold exactly the given number of frames
+    pub fn new(width: u8, frames: u8, timed: bool, data: &'a [u8]) -> Option<Self> {
+        let bitmap = Self {
+            width,
+            frames,
+            timed,
+            data,
+        };
+
+        if width == 0 || frames == 0 || data.len() != bitmap.frame_len() * frames as usize {
+            None
+        } else {
+            Some(bitmap)
+        }
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn frames(&self) -> u8 {
+        self.frames
+    }
+
+    pub fn timed(&self) -> bool {
+        self.timed
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn frame_len(&self) -> usize {
+        let duration_len = if self.timed { 2 } else { 0 };
+        duration_len + self.width as usize
+    }
+
+    // returns how long the frame stays up and its columns
+    fn frame(&self, index: u8) -> (u32, &'a [u8]) {
+        let frame = &self.data[index as usize * self.frame_len()..][..self.frame_len()];
+        if self.timed {
+            let duration_ms = u16::from_le_bytes([frame[0], frame[1]]) as u32;
+            (duration_ms.max(1), &frame[2..])
+        } else if self.frames == 1 {
+            (HOLD_MS, frame)
+        } else {
+            (DEFAULT_FRAME_MS, frame)
+        }
+    }
+
+    fn duration_ms(&self) -> u32 {
+        (0..self.frames).map(|index| self.frame(index).0).sum()
+    }
+}
+
+// Renders the frame of the bitmap at elapsed_ms centred on the panel.
+// Short animations are repeated until they have been up for at least HOLD_MS, then finish at
+// the end of a pass. Returns true once finished.
+pub fn render(bitmap: &Bitmap, fb: &mut impl FrameBuffer, elapsed_ms: u32) -> bool {
+    fb.clear();
+
+    let duration_ms = bitmap.duration_ms();
+    let passes = HOLD_MS.div_ceil(duration_ms).max(1);
+    if elapsed_ms >= duration_ms.saturating_mul(passes) {
+        return true;
+    }
+
+    let mut at_ms = elapsed_ms % duration_ms;
+    let mut index = 0;
+    loop {
+        let (frame_ms, columns) = bitmap.frame(index);
+        if at_ms < frame_ms || index + 1 == bitmap.frames {
+            let x = (fb.width() - bitmap.width as i32) / 2;
+            for (i, bits) in columns.iter().enumerate() {
+                fb.set_column(x + i as i32, *bits);
+            }
+
+            return false;
+        }
+
+        at_ms -= frame_ms;
+        index += 1;
+    }
+}
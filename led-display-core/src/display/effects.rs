@@ -0,0 +1,398 @@
+use super::{
+    font::{draw_text, text_width},
+    framebuffer::{FrameBuffer, HEIGHT},
+};
+use core::str::FromStr;
+
+// Display effects
+// Every effect is a pure function of the time elapsed since it started and the scroll speed (in
+// columns per second) so that frames can be rendered whenever the main loop gets around to it
+// and the result only depends on the clock. Effects that keep the text still fall back to
+// scrolling left if the text does not fit on the panel.
+
+// how long text stays up once an effect has finished moving it into place
+pub const HOLD_MS: u32 = 3_000;
+
+// the text is shown for one period and hidden for the next
+const BLINK_PERIOD_MS: u32 = 500;
+const BLINK_COUNT: u32 = 5;
+
+// the typewriter types one character in the time it takes to scroll this many columns
+const KEYSTROKE_COLUMNS: i32 = 6;
+
+// vertical movement is slower than horizontal because there are only 8 rows
+const ROWS_PER_COLUMN: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    // centred text that stays up for a while
+    Static,
+    ScrollLeft,
+    ScrollRight,
+    // centred text that scrolls in from below
+    ScrollUp,
+    Blink,
+    // centred text revealed from left to right
+    Wipe,
+    // one character at a time
+    Typewriter,
+    // keeps scrolling left until another message is waiting
+    Loop,
+}
+
+// the names used to pick an effect in the protocol
+const NAMES: [(&str, Effect); 8] = [
+    ("static", Effect::Static),
+    ("left", Effect::ScrollLeft),
+    ("right", Effect::ScrollRight),
+    ("up", Effect::ScrollUp),
+    ("blink", Effect::Blink),
+    ("wipe", Effect::Wipe),
+    ("type", Effect::Typewriter),
+    ("loop", Effect::Loop),
+];
+
+impl Effect {
+    pub fn name(self) -> &'static str {
+        // every effect is in NAMES
+        NAMES
+            .iter()
+            .find(|(_, effect)| *effect == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+}
+
+impl FromStr for Effect {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, effect)| *effect)
+            .ok_or(())
+    }
+}
+
+// Renders the frame of the effect at elapsed_ms into the frame buffer.
+// Returns true once the effect has finished (for Loop, once a pass has finished).
+pub fn render(
+    effect: Effect,
+    text: &str,
+    fb: &mut impl FrameBuffer,
+    elapsed_ms: u32,
+    speed: u32,
+) -> bool {
+    fb.clear();
+    let width = fb.width();
+    let full_width = text_width(text);
+    let cols = columns(elapsed_ms, speed);
+
+    let fits = full_width <= width;
+    let centre = (width - full_width) / 2;
+
+    match effect {
+        Effect::ScrollLeft | Effect::Loop => scroll_left(text, fb, cols),
+        Effect::Static | Effect::ScrollUp | Effect::Blink | Effect::Wipe if !fits => {
+            scroll_left(text, fb, cols)
+        }
+        Effect::ScrollRight => {
+            let x = cols - full_width;
+            draw_text(fb, x, 0, text);
+            x > width
+        }
+        Effect::Static => {
+            draw_text(fb, centre, 0, text);
+            elapsed_ms >= HOLD_MS
+        }
+        Effect::ScrollUp => {
+            let rows = (cols / ROWS_PER_COLUMN as i32).min(HEIGHT);
+            draw_text(fb, centre, HEIGHT - rows, text);
+            rows == HEIGHT && hold_elapsed(elapsed_ms, HEIGHT * ROWS_PER_COLUMN as i32, speed)
+        }
+        Effect::Blink => {
+            if (elapsed_ms / BLINK_PERIOD_MS).is_multiple_of(2) {
+                draw_text(fb, centre, 0, text);
+            }
+
+            elapsed_ms >= BLINK_PERIOD_MS * BLINK_COUNT * 2
+        }
+        Effect::Wipe => {
+            draw_text(fb, centre, 0, text);
+            let revealed = cols.min(full_width);
+            for x in centre + revealed..width {
+                fb.set_column(x, 0);
+            }
+
+            revealed == full_width && hold_elapsed(elapsed_ms, full_width, speed)
+        }
+        Effect::Typewriter => {
+            let num_chars = text.chars().count() as i32;
+            let typed = (cols / KEYSTROKE_COLUMNS + 1).min(num_chars);
+            let typed_text = match text.char_indices().nth(typed as usize) {
+                Some((index, _)) => &text[..index],
+                None => text,
+            };
+
+            // keep the last character typed on screen if the text is wider than the panel
+            let typed_width = text_width(typed_text);
+            let x = if typed_width > width {
+                width - typed_width
+            } else {
+                0
+            };
+
+            draw_text(fb, x, 0, typed_text);
+            typed == num_chars && hold_elapsed(elapsed_ms, num_chars * KEYSTROKE_COLUMNS, speed)
+        }
+    }
+}
+
+// number of columns moved at the given speed
+fn columns(elapsed_ms: u32, speed: u32) -> i32 {
+    let cols = elapsed_ms as u64 * speed as u64 / 1000;
+    cols.min(i32::MAX as u64) as i32
+}
+
+fn scroll_left(text: &str, fb: &mut impl FrameBuffer, cols: i32) -> bool {
+    let x = fb.width() - cols;
+    draw_text(fb, x, 0, text);
+    x < -text_width(text)
+}
+
+// true once the text has been still for HOLD_MS after moving the given number of columns
+fn hold_elapsed(elapsed_ms: u32, moving_cols: i32, speed: u32) -> bool {
+    let moving_ms = moving_cols as u64 * 1000 / speed.max(1) as u64;
+    elapsed_ms as u64 >= moving_ms + HOLD_MS as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{
+        framebuffer::Columns,
+        zone::{Zone, ZoneName},
+    };
+
+    const WIDTH: i32 = 40;
+    // one column per millisecond keeps the arithmetic simple
+    const SPEED: u32 = 1000;
+
+    type Panel = Columns<{ WIDTH as usize }>;
+
+    fn pixels(fb: &Panel) -> Vec<u8> {
+        (0..WIDTH).map(|x| fb.column(x)).collect()
+    }
+
+    // the frame of the effect and whether it has finished
+    fn frame(effect: Effect, text: &str, elapsed_ms: u32) -> (Vec<u8>, bool) {
+        let mut fb = Panel::new();
+        let done = render(effect, text, &mut fb, elapsed_ms, SPEED);
+        (pixels(&fb), done)
+    }
+
+    // the text drawn at (x, y) and nothing else
+    fn drawn(text: &str, x: i32, y: i32) -> Vec<u8> {
+        let mut fb = Panel::new();
+        draw_text(&mut fb, x, y, text);
+        pixels(&fb)
+    }
+
+    fn centre(text: &str) -> i32 {
+        (WIDTH - text_width(text)) / 2
+    }
+
+    fn blank() -> Vec<u8> {
+        vec![0; WIDTH as usize]
+    }
+
+    #[test]
+    fn static_is_centred() {
+        assert_eq!(
+            frame(Effect::Static, "Hi", 0),
+            (drawn("Hi", centre("Hi"), 0), false)
+        );
+        assert!(!frame(Effect::Static, "Hi", HOLD_MS - 1).1);
+        assert!(frame(Effect::Static, "Hi", HOLD_MS).1);
+    }
+
+    #[test]
+    fn text_too_wide_scrolls() {
+        let text = "Much too wide for the panel";
+        assert!(text_width(text) > WIDTH);
+        for effect in [
+            Effect::Static,
+            Effect::ScrollUp,
+            Effect::Blink,
+            Effect::Wipe,
+        ] {
+            assert_eq!(frame(effect, text, 10), frame(Effect::ScrollLeft, text, 10));
+        }
+    }
+
+    #[test]
+    fn scroll_left() {
+        let width = text_width("Hi");
+
+        // starts just off the right edge and ends just off the left edge
+        assert_eq!(frame(Effect::ScrollLeft, "Hi", 0), (blank(), false));
+        assert_eq!(
+            frame(Effect::ScrollLeft, "Hi", 1).0,
+            drawn("Hi", WIDTH - 1, 0)
+        );
+        assert_eq!(
+            frame(Effect::ScrollLeft, "Hi", WIDTH as u32).0,
+            drawn("Hi", 0, 0)
+        );
+        let end_ms = (WIDTH + width) as u32;
+        assert_eq!(frame(Effect::ScrollLeft, "Hi", end_ms), (blank(), false));
+        assert_eq!(frame(Effect::ScrollLeft, "Hi", end_ms + 1), (blank(), true));
+    }
+
+    #[test]
+    fn scroll_right() {
+        let width = text_width("Hi");
+
+        assert_eq!(frame(Effect::ScrollRight, "Hi", 0), (blank(), false));
+        assert_eq!(
+            frame(Effect::ScrollRight, "Hi", 1).0,
+            drawn("Hi", 1 - width, 0)
+        );
+        assert_eq!(
+            frame(Effect::ScrollRight, "Hi", width as u32).0,
+            drawn("Hi", 0, 0)
+        );
+        let end_ms = (WIDTH + width) as u32;
+        assert_eq!(frame(Effect::ScrollRight, "Hi", end_ms), (blank(), false));
+        assert_eq!(
+            frame(Effect::ScrollRight, "Hi", end_ms + 1),
+            (blank(), true)
+        );
+    }
+
+    #[test]
+    fn scroll_up() {
+        let x = centre("Hi");
+        let step_ms = ROWS_PER_COLUMN;
+
+        assert_eq!(frame(Effect::ScrollUp, "Hi", 0), (blank(), false));
+        assert_eq!(
+            frame(Effect::ScrollUp, "Hi", 3 * step_ms).0,
+            drawn("Hi", x, HEIGHT - 3)
+        );
+
+        // in place after 8 rows, then held
+        let in_place_ms = HEIGHT as u32 * step_ms;
+        assert_eq!(
+            frame(Effect::ScrollUp, "Hi", in_place_ms),
+            (drawn("Hi", x, 0), false)
+        );
+        assert!(frame(Effect::ScrollUp, "Hi", in_place_ms + HOLD_MS).1);
+    }
+
+    #[test]
+    fn blink() {
+        let shown = drawn("Hi", centre("Hi"), 0);
+
+        assert_eq!(frame(Effect::Blink, "Hi", 0).0, shown);
+        assert_eq!(frame(Effect::Blink, "Hi", BLINK_PERIOD_MS - 1).0, shown);
+        assert_eq!(frame(Effect::Blink, "Hi", BLINK_PERIOD_MS).0, blank());
+        assert_eq!(frame(Effect::Blink, "Hi", 2 * BLINK_PERIOD_MS).0, shown);
+
+        let end_ms = BLINK_PERIOD_MS * BLINK_COUNT * 2;
+        assert!(!frame(Effect::Blink, "Hi", end_ms - 1).1);
+        assert!(frame(Effect::Blink, "Hi", end_ms).1);
+    }
+
+    #[test]
+    fn wipe() {
+        let x = centre("Hello");
+        let width = text_width("Hello");
+        let full = drawn("Hello", x, 0);
+
+        // nothing is revealed at first, then one more column every step
+        assert_eq!(frame(Effect::Wipe, "Hello", 0), (blank(), false));
+        let (half, _) = frame(Effect::Wipe, "Hello", 5);
+        for column in 0..WIDTH {
+            let expected = if column < x + 5 {
+                full[column as usize]
+            } else {
+                0
+            };
+            assert_eq!(half[column as usize], expected, "column {}", column);
+        }
+
+        assert_eq!(frame(Effect::Wipe, "Hello", width as u32), (full, false));
+        assert!(frame(Effect::Wipe, "Hello", width as u32 + HOLD_MS).1);
+    }
+
+    #[test]
+    fn typewriter() {
+        let keystroke_ms = KEYSTROKE_COLUMNS as u32;
+
+        // the first character is there straight away and one more follows every keystroke
+        assert_eq!(
+            frame(Effect::Typewriter, "abc", 0),
+            (drawn("a", 0, 0), false)
+        );
+        assert_eq!(
+            frame(Effect::Typewriter, "abc", keystroke_ms - 1).0,
+            drawn("a", 0, 0)
+        );
+        assert_eq!(
+            frame(Effect::Typewriter, "abc", keystroke_ms).0,
+            drawn("ab", 0, 0)
+        );
+        assert_eq!(
+            frame(Effect::Typewriter, "abc", 2 * keystroke_ms).0,
+            drawn("abc", 0, 0)
+        );
+
+        let end_ms = 3 * keystroke_ms + HOLD_MS;
+        assert!(!frame(Effect::Typewriter, "abc", end_ms - 1).1);
+        assert!(frame(Effect::Typewriter, "abc", end_ms).1);
+    }
+
+    #[test]
+    fn typewriter_keeps_the_last_character_on_screen() {
+        let text = "Much too wide for the panel";
+        let (pixels, _) = frame(Effect::Typewriter, text, u32::MAX);
+        assert_eq!(pixels, drawn(text, WIDTH - text_width(text), 0));
+    }
+
+    #[test]
+    fn loop_goes_round_again() {
+        // a pass of a loop ends like a scroll
+        let end_ms = (WIDTH + text_width("Hi")) as u32 + 1;
+        assert!(!frame(Effect::Loop, "Hi", end_ms - 1).1);
+        assert!(frame(Effect::Loop, "Hi", end_ms).1);
+
+        // and the zone starts it again while nothing else is waiting
+        let mut zone = Zone::new(ZoneName::from("main"), 0, WIDTH as usize / 8);
+        zone.set_speed(SPEED, 0);
+        zone.queue_message(Effect::Loop, "Hi");
+        let mut fb = Panel::new();
+        zone.poll(&mut fb, 0, None, None, None);
+        zone.poll(&mut fb, end_ms, None, None, None);
+        zone.poll(&mut fb, end_ms + WIDTH as u32, None, None, None);
+        assert_eq!(pixels(&fb), drawn("Hi", 0, 0));
+
+        // until another message comes along
+        zone.queue_message(Effect::Static, "Yo");
+        zone.poll(&mut fb, 2 * end_ms, None, None, None);
+        zone.poll(&mut fb, 2 * end_ms + 1, None, None, None);
+        assert_eq!(pixels(&fb), drawn("Yo", centre("Yo"), 0));
+    }
+
+    #[test]
+    fn names() {
+        for (name, effect) in NAMES {
+            assert_eq!(effect.name(), name);
+            assert_eq!(name.parse(), Ok(effect));
+        }
+
+        assert_eq!("sideways".parse::<Effect>(), Err(()));
+        assert_eq!("".parse::<Effect>(), Err(()));
+    }
+}
@@ -0,0 +1,127 @@
+use super::framebuffer::{FrameBuffer, HEIGHT};
+use core::convert::TryFrom;
+
+// Proportional font for the panel
+// The glyph table is generated at build time by led-display-fontgen from fonts/panel.bdf (see
+// build.rs) and covers printable ASCII, Latin-1, arrows, the euro sign and a few icons.
+// Glyphs are stored as columns with bit 0 at the top, back to back in COLUMNS. Characters that
+// are not in the font are drawn as '?'.
+
+struct Glyph {
+    code: u16,
+    offset: u16,
+    width: u8,
+    advance: u8,
+}
+
+struct Kern {
+    left: u16,
+    right: u16,
+    adjust: i8,
+}
+
+include!(concat!(env!("OUT_DIR"), "/font.rs"));
+
+const REPLACEMENT: char = '?';
+
+fn find(c: char) -> Option<&'static Glyph> {
+    let code = u16::try_from(c as u32).ok()?;
+    GLYPHS
+        .binary_search_by_key(&code, |glyph| glyph.code)
+        .ok()
+        .map(|index| &GLYPHS[index])
+}
+
+fn glyph(c: char) -> &'static Glyph {
+    // the font generator makes sure the replacement character is in the table
+    find(c).or_else(|| find(REPLACEMENT)).unwrap()
+}
+
+fn columns(glyph: &Glyph) -> &'static [u8] {
+    let offset = glyph.offset as usize;
+    &COLUMNS[offset..offset + glyph.width as usize]
+}
+
+// columns to move the right character by when it follows the left one
+fn kerning(left: &Glyph, right: &Glyph) -> i32 {
+    KERNING
+        .binary_search_by_key(&(left.code, right.code), |kern| (kern.left, kern.right))
+        .map(|index| KERNING[index].adjust as i32)
+        .unwrap_or(0)
+}
+
+// width in pixels of the text when drawn with draw_text
+pub fn text_width(text: &str) -> i32 {
+    let mut width = 0;
+    let mut end = 0;
+    let mut previous = None;
+    for c in text.chars() {
+        let glyph = glyph(c);
+        if let Some(previous) = previous {
+            width += kerning(previous, glyph);
+        }
+
+        end = width + glyph.width as i32;
+        width += glyph.advance as i32;
+        previous = Some(glyph);
+    }
+
+    // the spacing after the last glyph is not part of the text
+    end
+}
+
+// draws text with its top left corner at (x, y) where y may be negative or larger than zero to
+// draw the text partly above or below the panel. Returns the x position after the last glyph.
+pub fn draw_text(fb: &mut impl FrameBuffer, x: i32, y: i32, text: &str) -> i32 {
+    let mut x = x;
+    let mut previous = None;
+    for c in text.chars() {
+        let glyph = glyph(c);
+        if let Some(previous) = previous {
+            x += kerning(previous, glyph);
+        }
+
+        for (i, bits) in columns(glyph).iter().enumerate() {
+            fb.or_column(x + i as i32, shift(*bits, y));
+        }
+
+        x += glyph.advance as i32;
+        previous = Some(glyph);
+    }
+
+    x
+}
+
+fn shift(bits: u8, y: i32) -> u8 {
+    if y >= HEIGHT || y <= -HEIGHT {
+        0
+    } else if y >= 0 {
+        bits << y
+    } else {
+        bits >> -y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs() {
+        // Latin-1 and an icon are in the table, anything else is drawn as '?'
+        assert_eq!(glyph('é').code, 0xE9);
+        assert!(glyph('é').width > 0);
+        assert_eq!(glyph('♥').code, 0x2665);
+        assert!(glyph('♥').width > 0);
+        assert_eq!(glyph('中').code, '?' as u16);
+        assert_eq!(glyph('😀').code, '?' as u16);
+    }
+
+    #[test]
+    fn kerned_pairs_are_closer() {
+        let (l, t) = (glyph('L'), glyph('T'));
+        assert_eq!(kerning(l, t), -1);
+        assert_eq!(text_width("LT"), l.advance as i32 - 1 + t.width as i32);
+        assert_eq!(kerning(t, l), 0);
+    }
+}
@@ -0,0 +1,99 @@
+// Off-screen pixel storage for the led panel
+// Pixels are stored as columns of 8 bits with bit 0 at the top. This suits the font (which is
+// stored the same way) and horizontal scrolling, which is what the panel mostly does.
+// Effects draw through the FrameBuffer trait so they do not need to know about the hardware.
+
+pub const HEIGHT: i32 = 8;
+
+pub trait FrameBuffer {
+    fn width(&self) -> i32;
+
+    // returns 0 for columns outside the buffer
+    fn column(&self, x: i32) -> u8;
+
+    // columns outside the buffer are ignored so that callers can draw partly off screen
+    fn set_column(&mut self, x: i32, bits: u8);
+
+    fn clear(&mut self) {
+        for x in 0..self.width() {
+            self.set_column(x, 0);
+        }
+    }
+
+    fn or_column(&mut self, x: i32, bits: u8) {
+        let bits = self.column(x) | bits;
+        self.set_column(x, bits);
+    }
+}
+
+pub struct Columns<const WIDTH: usize> {
+    columns: [u8; WIDTH],
+}
+
+impl<const WIDTH: usize> Columns<WIDTH> {
+    pub fn new() -> Self {
+        Self {
+            columns: [0; WIDTH],
+        }
+    }
+}
+
+impl<const WIDTH: usize> FrameBuffer for Columns<WIDTH> {
+    fn width(&self) -> i32 {
+        WIDTH as i32
+    }
+
+    fn column(&self, x: i32) -> u8 {
+        if x < 0 {
+            return 0;
+        }
+
+        self.columns.get(x as usize).copied().unwrap_or(0)
+    }
+
+    fn set_column(&mut self, x: i32, bits: u8) {
+        if x < 0 {
+            return;
+        }
+
+        if let Some(column) = self.columns.get_mut(x as usize) {
+            *column = bits;
+        }
+    }
+}
+
+// A vertical strip of another frame buffer so that a zone of the panel can be drawn as if it
+// were the whole panel. Drawing outside the strip is clipped.
+pub struct Window<'a, FB> {
+    fb: &'a mut FB,
+    x: i32,
+    width: i32,
+}
+
+impl<'a, FB: FrameBuffer> Window<'a, FB> {
+    pub fn new(fb: &'a mut FB, x: i32, width: i32) -> Self {
+        Self { fb, x, width }
+    }
+}
+
+impl<'a, FB: FrameBuffer> FrameBuffer for Window<'a, FB> {
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn column(&self, x: i32) -> u8 {
+        if x < 0 || x >= self.width {
+            return 0;
+        }
+
+        self.fb.column(self.x + x)
+    }
+
+    fn set_column(&mut self, x: i32, bits: u8) {
+        if x < 0 || x >= self.width {
+            return;
+        }
+
+        self.fb.set_column(self.x + x, bits);
+    }
+}
@@ -0,0 +1,112 @@
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+
+// Minimal driver for a daisy chain of MAX7219 led matrix modules
+// Data shifts through the chain so the first bytes sent in a transaction end up in the device
+// furthest from the microcontroller. We treat that device as the leftmost one on the panel and
+// the most significant bit of a row as its leftmost pixel. If your panel looks mirrored then
+// this is the place to fix it.
+
+#[derive(Debug)]
+pub enum Error<SpiError, PinError> {
+    Spi(SpiError),
+    Pin(PinError),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Command {
+    DecodeMode = 0x09,
+    Intensity = 0x0A,
+    ScanLimit = 0x0B,
+    OnOff = 0x0C,
+    DisplayTest = 0x0F,
+}
+
+// register of the first row, the other 7 rows follow on
+const ROW_0: u8 = 0x01;
+
+pub struct Max7219Chain<'a, CS> {
+    cs: &'a mut CS,
+    num_devices: usize,
+}
+
+impl<'a, CS, PinError> Max7219Chain<'a, CS>
+where
+    CS: OutputPin<Error = PinError>,
+{
+    pub fn new(cs: &'a mut CS, num_devices: usize) -> Self {
+        Self { cs, num_devices }
+    }
+
+    pub fn write_command_all<SPI, SpiError>(
+        &mut self,
+        spi: &mut SPI,
+        command: Command,
+        data: u8,
+    ) -> Result<(), Error<SpiError, PinError>>
+    where
+        SPI: Write<u8, Error = SpiError>,
+    {
+        let num_devices = self.num_devices;
+        self.transaction(spi, |spi| {
+            for _ in 0..num_devices {
+                spi.write(&[command as u8, data])?;
+            }
+            Ok(())
+        })
+    }
+
+    // writes one row (0-7) of every device, data[0] is the leftmost device
+    pub fn write_row<SPI, SpiError>(
+        &mut self,
+        spi: &mut SPI,
+        row: u8,
+        data: &[u8],
+    ) -> Result<(), Error<SpiError, PinError>>
+    where
+        SPI: Write<u8, Error = SpiError>,
+    {
+        let register = ROW_0 + row;
+        self.transaction(spi, |spi| {
+            for device_data in data {
+                spi.write(&[register, *device_data])?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn clear_all<SPI, SpiError>(
+        &mut self,
+        spi: &mut SPI,
+    ) -> Result<(), Error<SpiError, PinError>>
+    where
+        SPI: Write<u8, Error = SpiError>,
+    {
+        let num_devices = self.num_devices;
+        for row in 0..8 {
+            self.transaction(spi, |spi| {
+                for _ in 0..num_devices {
+                    spi.write(&[ROW_0 + row, 0])?;
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    // the devices latch the data when chip select goes high again
+    fn transaction<SPI, SpiError, F>(
+        &mut self,
+        spi: &mut SPI,
+        f: F,
+    ) -> Result<(), Error<SpiError, PinError>>
+    where
+        SPI: Write<u8, Error = SpiError>,
+        F: FnOnce(&mut SPI) -> Result<(), SpiError>,
+    {
+        self.cs.set_low().map_err(Error::Pin)?;
+        let result = f(spi).map_err(Error::Spi);
+        self.cs.set_high().map_err(Error::Pin)?;
+        result
+    }
+}
@@ -0,0 +1,100 @@
+use super::framebuffer::{FrameBuffer, HEIGHT};
+
+// Self test patterns for finding dead modules and bad connections
+// The MAX7219 has no way of reading anything back so the test cannot tell on its own whether a
+// module works. Instead it steps through patterns that make a fault obvious to whoever is looking
+// at the panel:
+//   every led on using the display test register, which bypasses the row data entirely
+//   each module in turn showing its number (counting from 1 at the left), a module that shows
+//     nothing or the wrong number points at a dead module or a break in the data line before it
+//   each row across the whole panel, then each column of every module
+
+const DISPLAY_TEST_MS: u32 = 2_000;
+const MODULE_MS: u32 = 400;
+const LINE_MS: u32 = 250;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    DisplayTest,
+    Module(u8),
+    Row(u8),
+    Column(u8),
+}
+
+// 3x5 digits so that two of them fit on one module, bit 0 at the top
+const DIGITS: [[u8; 3]; 10] = [
+    [0x1F, 0x11, 0x1F],
+    [0x12, 0x1F, 0x10],
+    [0x1D, 0x15, 0x17],
+    [0x15, 0x15, 0x1F],
+    [0x07, 0x04, 0x1F],
+    [0x17, 0x15, 0x1D],
+    [0x1F, 0x15, 0x1D],
+    [0x01, 0x01, 0x1F],
+    [0x1F, 0x15, 0x1F],
+    [0x17, 0x15, 0x1F],
+];
+
+// the step to show elapsed_ms after the test started, None once it has finished
+pub fn step(elapsed_ms: u32, num_devices: usize) -> Option<Step> {
+    let mut elapsed_ms = elapsed_ms;
+    if elapsed_ms < DISPLAY_TEST_MS {
+        return Some(Step::DisplayTest);
+    }
+    elapsed_ms -= DISPLAY_TEST_MS;
+
+    let modules_ms = MODULE_MS * num_devices as u32;
+    if elapsed_ms < modules_ms {
+        return Some(Step::Module((elapsed_ms / MODULE_MS) as u8));
+    }
+    elapsed_ms -= modules_ms;
+
+    let lines_ms = LINE_MS * HEIGHT as u32;
+    if elapsed_ms < lines_ms {
+        return Some(Step::Row((elapsed_ms / LINE_MS) as u8));
+    }
+    elapsed_ms -= lines_ms;
+
+    if elapsed_ms < lines_ms {
+        return Some(Step::Column((elapsed_ms / LINE_MS) as u8));
+    }
+
+    None
+}
+
+pub fn draw(step: Step, fb: &mut impl FrameBuffer) {
+    fb.clear();
+    match step {
+        // the register does the work
+        Step::DisplayTest => {}
+        Step::Module(module) => {
+            let x = module as i32 * 8;
+            let number = module + 1;
+            if number >= 10 {
+                draw_digit(fb, x + 1, number / 10 % 10);
+            }
+            draw_digit(fb, x + 4, number % 10);
+
+            // the bottom row shows that the whole module is there
+            for i in 0..8 {
+                fb.or_column(x + i, 0x80);
+            }
+        }
+        Step::Row(row) => {
+            for x in 0..fb.width() {
+                fb.set_column(x, 1 << row);
+            }
+        }
+        Step::Column(column) => {
+            for x in (column as i32..fb.width()).step_by(8) {
+                fb.set_column(x, 0xFF);
+            }
+        }
+    }
+}
+
+fn draw_digit(fb: &mut impl FrameBuffer, x: i32, digit: u8) {
+    for (i, bits) in DIGITS[digit as usize].iter().enumerate() {
+        fb.set_column(x + i as i32, bits << 1);
+    }
+}
@@ -0,0 +1,205 @@
+use super::{
+    framebuffer::{FrameBuffer, HEIGHT},
+    max7219::{Error, Max7219Chain},
+};
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+
+// Copy of what the MAX7219 row registers currently hold
+// Rendering a frame only touches RAM. flush() works out which rows differ from what the devices
+// are showing and sends just those. Every row costs 2 bytes per device in the chain (all of them
+// have to be clocked through even if only one changed) so skipping unchanged rows is where the
+// savings are: a frame that did not move costs nothing and the bottom row, which only accents
+// and descenders below the baseline use, is rarely sent at all.
+
+const ROWS: usize = HEIGHT as usize;
+
+pub struct Shadow<const NUM_DEVICES: usize> {
+    rows: [[u8; NUM_DEVICES]; ROWS],
+    // the devices may not be showing what we think, send every row next time
+    stale: bool,
+}
+
+impl<const NUM_DEVICES: usize> Shadow<NUM_DEVICES> {
+    pub fn new() -> Self {
+        Self {
+            rows: [[0; NUM_DEVICES]; ROWS],
+            stale: false,
+        }
+    }
+
+    // call this after the devices have been cleared
+    pub fn clear(&mut self) {
+        self.rows = [[0; NUM_DEVICES]; ROWS];
+        self.stale = false;
+    }
+
+    // call this if the devices could have lost or scrambled their rows
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    // sends the rows of the frame buffer that differ from what the devices are showing
+    pub fn flush<CS, PinError, SPI, SpiError>(
+        &mut self,
+        fb: &impl FrameBuffer,
+        max7219: &mut Max7219Chain<CS>,
+        spi: &mut SPI,
+    ) -> Result<(), Error<SpiError, PinError>>
+    where
+        CS: OutputPin<Error = PinError>,
+        SPI: Write<u8, Error = SpiError>,
+    {
+        for (row, shown) in self.rows.iter_mut().enumerate() {
+            let mut data = [0; NUM_DEVICES];
+            for (device, byte) in data.iter_mut().enumerate() {
+                *byte = row_byte(fb, device, row);
+            }
+
+            if self.stale || data != *shown {
+                max7219.write_row(spi, row as u8, &data)?;
+                *shown = data;
+            }
+        }
+
+        self.stale = false;
+        Ok(())
+    }
+}
+
+// the bits of one row of one device, the leftmost pixel in the most significant bit
+fn row_byte(fb: &impl FrameBuffer, device: usize, row: usize) -> u8 {
+    let x = device as i32 * 8;
+    (0..8).fold(0, |byte, i| {
+        if fb.column(x + i) & (1 << row) != 0 {
+            byte | (0x80 >> i)
+        } else {
+            byte
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{
+        effects::{self, Effect},
+        font,
+        framebuffer::Columns,
+    };
+    use core::convert::Infallible;
+
+    const NUM_DEVICES: usize = 20;
+    const FULL_FRAME_BYTES: usize = ROWS * NUM_DEVICES * 2;
+
+    #[derive(Default)]
+    struct CountingSpi {
+        bytes: usize,
+    }
+
+    impl Write<u8> for CountingSpi {
+        type Error = Infallible;
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.bytes += words.len();
+            Ok(())
+        }
+    }
+
+    struct Pin;
+
+    impl OutputPin for Pin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    // renders the effect one frame per millisecond, the way the main loop polls the panel,
+    // and returns the number of frames and bytes sent
+    fn run(effect: Effect, text: &str, speed: u32) -> (usize, usize) {
+        let mut pin = Pin;
+        let mut max7219 = Max7219Chain::new(&mut pin, NUM_DEVICES);
+        let mut spi = CountingSpi::default();
+        let mut shadow = Shadow::<NUM_DEVICES>::new();
+        let mut fb = Columns::<{ NUM_DEVICES * 8 }>::new();
+
+        let mut frames = 0;
+        for elapsed_ms in 0.. {
+            let finished = effects::render(effect, text, &mut fb, elapsed_ms, speed);
+            shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+            frames += 1;
+            if finished {
+                break;
+            }
+        }
+
+        (frames, spi.bytes)
+    }
+
+    #[test]
+    fn unchanged_frame_sends_nothing() {
+        let mut pin = Pin;
+        let mut max7219 = Max7219Chain::new(&mut pin, NUM_DEVICES);
+        let mut spi = CountingSpi::default();
+        let mut shadow = Shadow::<NUM_DEVICES>::new();
+        let mut fb = Columns::<{ NUM_DEVICES * 8 }>::new();
+
+        shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+        assert_eq!(spi.bytes, 0);
+
+        effects::render(Effect::Static, "Hello", &mut fb, 0, 60);
+        shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+        let first = spi.bytes;
+        assert!(first > 0 && first < FULL_FRAME_BYTES);
+
+        shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+        assert_eq!(spi.bytes, first);
+    }
+
+    #[test]
+    fn scroll_sends_one_frame_per_column() {
+        let text = "Hello world";
+        let speed = 60;
+        let (frames, bytes) = run(Effect::ScrollLeft, text, speed);
+
+        // the text moves one column at a time and the bottom row is blank for plain ascii so every step
+        // costs at most 7 rows, however often the panel is polled
+        let steps = NUM_DEVICES * 8 + font::text_width(text) as usize;
+        assert!(frames > steps);
+        assert!(bytes <= (steps + 1) * (ROWS - 1) * NUM_DEVICES * 2);
+        assert!(bytes < frames * FULL_FRAME_BYTES / 10);
+    }
+
+    #[test]
+    fn invalidate_resends_every_row() {
+        let mut pin = Pin;
+        let mut max7219 = Max7219Chain::new(&mut pin, NUM_DEVICES);
+        let mut spi = CountingSpi::default();
+        let mut shadow = Shadow::<NUM_DEVICES>::new();
+        let mut fb = Columns::<{ NUM_DEVICES * 8 }>::new();
+
+        effects::render(Effect::Static, "Hello", &mut fb, 0, 60);
+        shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+
+        shadow.invalidate();
+        let before = spi.bytes;
+        shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+        assert_eq!(spi.bytes - before, FULL_FRAME_BYTES);
+
+        shadow.flush(&fb, &mut max7219, &mut spi).unwrap();
+        assert_eq!(spi.bytes - before, FULL_FRAME_BYTES);
+    }
+
+    #[test]
+    fn static_text_is_sent_once() {
+        let (frames, bytes) = run(Effect::Static, "Hello", 60);
+
+        assert!(frames > 1000);
+        assert!(bytes <= FULL_FRAME_BYTES);
+    }
+}
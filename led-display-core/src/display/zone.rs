@@ -0,0 +1,253 @@
+use super::{
+    bitmap::{self, Bitmap},
+    effects::{self, Effect},
+    font,
+    framebuffer::FrameBuffer,
+};
+use heapless::{Deque, String};
+
+// An independent region of the panel made up of a range of modules
+// Every zone has its own queue of messages, scroll speed and animation clock so that, for
+// example, a clock on the left can tick over while messages scroll past on the right.
+
+// longer messages are truncated
+const MAX_MESSAGE_LEN: usize = 128;
+
+// number of messages waiting to be shown (not counting the one currently on the panel)
+const QUEUE_LEN: usize = 4;
+
+// scroll speed in columns per second
+const DEFAULT_SPEED: u32 = 60;
+const MAX_SPEED: u32 = 1000;
+
+pub const MAX_NAME_LEN: usize = 8;
+
+// how long a zone has to have had nothing to show before the idle screen comes up
+pub const IDLE_DELAY_MS: u32 = 30_000;
+
+type Message = String<MAX_MESSAGE_LEN>;
+pub type ZoneName = String<MAX_NAME_LEN>;
+
+enum Item {
+    Text { effect: Effect, text: Message },
+    // the bitmap itself is kept by the panel, there is only room for one
+    Bitmap,
+}
+
+struct Animation {
+    item: Item,
+    start_ms: u32,
+}
+
+pub struct Zone {
+    name: ZoneName,
+    // modules, 0 is the leftmost
+    first: usize,
+    count: usize,
+    queue: Deque<Item, QUEUE_LEN>,
+    animation: Option<Animation>,
+    speed: u32,
+    idle_dirty: bool,
+    // when the zone last ran out of things to show, None while it is busy
+    idle_since_ms: Option<u32>,
+    idle_screen_due: bool,
+}
+
+impl Zone {
+    pub fn new(name: ZoneName, first: usize, count: usize) -> Self {
+        Self {
+            name,
+            first,
+            count,
+            queue: Deque::new(),
+            animation: None,
+            speed: DEFAULT_SPEED,
+            idle_dirty: true,
+            idle_since_ms: None,
+            idle_screen_due: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // the first column and width of the zone on the panel
+    pub fn columns(&self) -> (i32, i32) {
+        (self.first as i32 * 8, self.count as i32 * 8)
+    }
+
+    pub fn overlaps(&self, first: usize, count: usize) -> bool {
+        first < self.first + self.count && self.first < first + count
+    }
+
+    // adds a message to the end of the queue, dropping the oldest waiting message if the queue is full
+    pub fn queue_message(&mut self, effect: Effect, message: &str) {
+        self.push(Item::Text {
+            effect,
+            text: truncate(message),
+        });
+    }
+
+    // adds the panel's bitmap to the end of the queue
+    pub fn queue_bitmap(&mut self) {
+        self.push(Item::Bitmap);
+    }
+
+    // stops showing the panel's bitmap and takes it off the queue, call this before replacing it
+    pub fn drop_bitmap(&mut self) {
+        if let Some(Animation {
+            item: Item::Bitmap, ..
+        }) = self.animation
+        {
+            self.animation = None;
+            self.idle_dirty = true;
+        }
+
+        for _ in 0..self.queue.iter().count() {
+            match self.queue.pop_front() {
+                Some(Item::Bitmap) | None => {}
+                Some(item) => {
+                    let _ = self.queue.push_back(item);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, item: Item) {
+        if self.queue.is_full() {
+            rprintln!(
+                "[WRN] Display queue full for {}, dropping oldest message",
+                self.name
+            );
+            self.queue.pop_front();
+        }
+
+        // the queue has room because we just made some
+        let _ = self.queue.push_back(item);
+    }
+
+    // sets the scroll speed in columns per second, a message that is already on the panel speeds
+    // up or slows down from where it is now
+    pub fn set_speed(&mut self, cols_per_sec: u32, now_ms: u32) {
+        let old_speed = self.speed;
+        self.speed = cols_per_sec.clamp(1, MAX_SPEED);
+
+        if let Some(Animation {
+            item: Item::Text { .. },
+            start_ms,
+        }) = &mut self.animation
+        {
+            let elapsed_ms = now_ms.wrapping_sub(*start_ms) as u64;
+            let scaled_ms = elapsed_ms * old_speed as u64 / self.speed as u64;
+            *start_ms = now_ms.wrapping_sub(scaled_ms.min(u32::MAX as u64) as u32);
+        }
+    }
+
+    // redraws the zone next time round if it has nothing to show (e.g. because the status or idle
+    // screen changed)
+    pub fn invalidate_idle(&mut self) {
+        self.idle_dirty = true;
+    }
+
+    // Draws the current frame of the zone into fb, which covers just this zone. When there is
+    // nothing to show the status is drawn if there is one, otherwise the idle screen once the zone
+    // has been idle for IDLE_DELAY_MS.
+    pub fn poll(
+        &mut self,
+        fb: &mut impl FrameBuffer,
+        now_ms: u32,
+        bitmap: Option<&Bitmap>,
+        status: Option<&str>,
+        idle_screen: Option<&str>,
+    ) {
+        if self.animation.is_none() {
+            match self.queue.pop_front() {
+                Some(item) => {
+                    self.idle_since_ms = None;
+                    self.idle_screen_due = false;
+                    self.animation = Some(Animation {
+                        item,
+                        start_ms: now_ms,
+                    });
+                }
+                None => return self.draw_idle(fb, now_ms, status, idle_screen),
+            }
+        }
+
+        if let Some(animation) = &mut self.animation {
+            let elapsed_ms = now_ms.wrapping_sub(animation.start_ms);
+            let finished = match &animation.item {
+                Item::Text { effect, text } => {
+                    effects::render(*effect, text, fb, elapsed_ms, self.speed)
+                }
+                Item::Bitmap => match bitmap {
+                    Some(bitmap) => bitmap::render(bitmap, fb, elapsed_ms),
+                    None => true,
+                },
+            };
+
+            if finished {
+                let looping = matches!(
+                    animation.item,
+                    Item::Text {
+                        effect: Effect::Loop,
+                        ..
+                    }
+                );
+
+                if looping && self.queue.is_empty() {
+                    // go round again
+                    animation.start_ms = now_ms;
+                } else {
+                    self.animation = None;
+                    self.idle_dirty = true;
+                }
+            }
+        }
+    }
+
+    fn draw_idle(
+        &mut self,
+        fb: &mut impl FrameBuffer,
+        now_ms: u32,
+        status: Option<&str>,
+        idle_screen: Option<&str>,
+    ) {
+        let idle_since_ms = *self.idle_since_ms.get_or_insert(now_ms);
+        if !self.idle_screen_due && now_ms.wrapping_sub(idle_since_ms) >= IDLE_DELAY_MS {
+            self.idle_screen_due = true;
+            self.idle_dirty = true;
+        }
+
+        if !self.idle_dirty {
+            return;
+        }
+
+        fb.clear();
+        match (status, idle_screen) {
+            (Some(status), _) => {
+                font::draw_text(fb, 0, 0, status);
+            }
+            (None, Some(idle_screen)) if self.idle_screen_due => {
+                let x = (fb.width() - font::text_width(idle_screen)) / 2;
+                font::draw_text(fb, x.max(0), 0, idle_screen);
+            }
+            _ => {}
+        }
+
+        self.idle_dirty = false;
+    }
+}
+
+// copies as much of the message as fits without splitting a utf8 character
+fn truncate(message: &str) -> Message {
+    let mut truncated = Message::new();
+    for c in message.chars() {
+        if truncated.push(c).is_err() {
+            break;
+        }
+    }
+
+    truncated
+}
@@ -0,0 +1,295 @@
+use sha2::{Digest, Sha256};
+
+// Entropy for TLS
+// The board has no hardware random number generator so noisy readings (ADC noise on the
+// temperature sensor and VREFINT, cycle counter jitter, network timing) are mixed into a SHA-256
+// pool. Each source is health tested as its samples arrive (the repetition count and adaptive
+// proportion tests from NIST SP 800-90B) and only samples from healthy sources count towards the
+// 256 bits the pool needs before it is trusted. A seed kept in flash is mixed in on each boot so
+// that a weak start still differs from every previous one. That seed is never credited.
+
+// the number of bits of entropy the pool needs before its output is trusted
+pub const READY_BITS: u32 = 256;
+
+// the length of an output and of the seed kept in flash
+pub const SEED_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    // raw 12 bit ADC readings of the internal temperature sensor
+    Temperature,
+    // raw 12 bit ADC readings of the internal reference voltage
+    Vref,
+    // the cycle counter, everything on the board runs from the same clock so it is not credited
+    CycleCounter,
+    // the cycle counter when the server answers, the network is outside the board
+    Network,
+}
+
+const SOURCES: usize = 4;
+
+impl Source {
+    fn index(self) -> usize {
+        match self {
+            Source::Temperature => 0,
+            Source::Vref => 1,
+            Source::CycleCounter => 2,
+            Source::Network => 3,
+        }
+    }
+
+    // conservative min-entropy of one sample in bits, the health test cutoffs are based on it
+    fn credit_bits(self) -> u32 {
+        match self {
+            Source::Temperature | Source::Vref => 1,
+            Source::CycleCounter => 0,
+            Source::Network => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HealthTest {
+    // the same sample too many times in a row
+    RepetitionCount,
+    // the same sample too often in a window
+    AdaptiveProportion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthError {
+    pub source: Source,
+    pub test: HealthTest,
+}
+
+// SP 800-90B section 4.4 with a false positive rate of 2^-20 for 1 bit of entropy per sample
+const REPETITION_CUTOFF: u32 = 21;
+const PROPORTION_WINDOW: u32 = 512;
+const PROPORTION_CUTOFF: u32 = 410;
+
+#[derive(Default)]
+struct Health {
+    last: Option<u32>,
+    repeats: u32,
+    // the first sample of the current window, how many samples of the window we have seen and
+    // how many of them matched it
+    window_sample: u32,
+    window_len: u32,
+    window_matches: u32,
+    // a source that failed once is not credited again until the next boot
+    failed: bool,
+}
+
+impl Health {
+    fn check(&mut self, sample: u32) -> Result<(), HealthTest> {
+        if self.last == Some(sample) {
+            self.repeats += 1;
+        } else {
+            self.last = Some(sample);
+            self.repeats = 1;
+        }
+
+        if self.window_len == 0 || self.window_len == PROPORTION_WINDOW {
+            self.window_sample = sample;
+            self.window_len = 0;
+            self.window_matches = 0;
+        }
+
+        self.window_len += 1;
+        if sample == self.window_sample {
+            self.window_matches += 1;
+        }
+
+        if self.repeats >= REPETITION_CUTOFF {
+            Err(HealthTest::RepetitionCount)
+        } else if self.window_matches >= PROPORTION_CUTOFF {
+            Err(HealthTest::AdaptiveProportion)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub struct EntropyPool {
+    // everything mixed in so far
+    hasher: Sha256,
+    credited_bits: u32,
+    // outputs handed out so far, so that no two are the same
+    outputs: u32,
+    health: [Health; SOURCES],
+}
+
+impl Default for EntropyPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntropyPool {
+    pub fn new() -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"led-display entropy pool");
+        Self {
+            hasher,
+            credited_bits: 0,
+            outputs: 0,
+            health: Default::default(),
+        }
+    }
+
+    // Mixes in a raw sample, it only counts towards READY_BITS while its source passes the
+    // health tests. The sample is mixed in either way since that can never make the pool worse.
+    pub fn add(&mut self, source: Source, sample: u32) -> Result<(), HealthError> {
+        self.hasher.update([source.index() as u8]);
+        self.hasher.update(sample.to_le_bytes());
+
+        let health = &mut self.health[source.index()];
+        if health.failed {
+            return Ok(());
+        }
+
+        match health.check(sample) {
+            Ok(()) => {
+                self.credited_bits = self.credited_bits.saturating_add(source.credit_bits());
+                Ok(())
+            }
+            Err(test) => {
+                health.failed = true;
+                Err(HealthError { source, test })
+            }
+        }
+    }
+
+    // mixes in the seed saved on the last boot, it is not credited
+    pub fn mix_seed(&mut self, seed: &[u8]) {
+        self.hasher.update(b"seed");
+        self.hasher.update(seed);
+    }
+
+    pub fn credited_bits(&self) -> u32 {
+        self.credited_bits
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.credited_bits >= READY_BITS
+    }
+
+    pub fn is_healthy(&self, source: Source) -> bool {
+        !self.health[source.index()].failed
+    }
+
+    // entropy for one TLS connection, every call returns something different
+    pub fn output(&mut self) -> [u8; SEED_LEN] {
+        self.outputs = self.outputs.wrapping_add(1);
+        self.finish(b"output")
+    }
+
+    // the seed to save in flash for the next boot, it does not give away any output
+    pub fn next_seed(&self) -> [u8; SEED_LEN] {
+        self.finish(b"next seed")
+    }
+
+    fn finish(&self, label: &[u8]) -> [u8; SEED_LEN] {
+        let mut hasher = self.hasher.clone();
+        hasher.update(label);
+        hasher.update(self.outputs.to_le_bytes());
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, good enough to stand in for a noisy ADC
+    fn noise(state: &mut u32) -> u32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        *state
+    }
+
+    fn noisy_pool(seed: u32) -> EntropyPool {
+        let mut pool = EntropyPool::new();
+        let mut state = seed;
+        for _ in 0..128 {
+            pool.add(Source::Temperature, 1800 + noise(&mut state) % 8)
+                .unwrap();
+            pool.add(Source::Vref, 1490 + noise(&mut state) % 8)
+                .unwrap();
+            pool.add(Source::CycleCounter, noise(&mut state)).unwrap();
+        }
+
+        pool
+    }
+
+    #[test]
+    fn ready_after_enough_healthy_samples() {
+        let pool = noisy_pool(1);
+        assert_eq!(pool.credited_bits(), 256);
+        assert!(pool.is_ready());
+    }
+
+    #[test]
+    fn outputs_differ() {
+        let mut pool = noisy_pool(1);
+        let first = pool.output();
+        let second = pool.output();
+        let seed = pool.next_seed();
+        assert_ne!(first, second);
+        assert_ne!(seed, first);
+        assert_ne!(seed, second);
+
+        // the same samples give the same output, different ones do not
+        let mut same = noisy_pool(1);
+        assert_eq!(same.output(), first);
+        assert_ne!(noisy_pool(2).output(), first);
+    }
+
+    #[test]
+    fn seed_changes_output_but_is_not_credited() {
+        let mut with_seed = noisy_pool(1);
+        with_seed.mix_seed(&[7; SEED_LEN]);
+        assert_eq!(with_seed.credited_bits(), 256);
+        assert_ne!(with_seed.output(), noisy_pool(1).output());
+    }
+
+    #[test]
+    fn stuck_source_fails_repetition_count() {
+        let mut pool = EntropyPool::new();
+        let errors: Vec<_> = (0..100)
+            .filter_map(|_| pool.add(Source::Vref, 4095).err())
+            .collect();
+
+        assert_eq!(
+            errors,
+            [HealthError {
+                source: Source::Vref,
+                test: HealthTest::RepetitionCount
+            }]
+        );
+        assert!(!pool.is_healthy(Source::Vref));
+        assert!(pool.is_healthy(Source::Temperature));
+        assert_eq!(pool.credited_bits(), REPETITION_CUTOFF - 1);
+    }
+
+    #[test]
+    fn biased_source_fails_adaptive_proportion() {
+        // never more than 20 in a row but mostly the same value
+        let mut pool = EntropyPool::new();
+        let mut result = Ok(());
+        for i in 0..PROPORTION_WINDOW {
+            let sample = if i % 10 == 9 { 1801 } else { 1800 };
+            result = result.and(pool.add(Source::Temperature, sample));
+        }
+
+        assert_eq!(
+            result,
+            Err(HealthError {
+                source: Source::Temperature,
+                test: HealthTest::AdaptiveProportion
+            })
+        );
+        assert!(!pool.is_healthy(Source::Temperature));
+    }
+}
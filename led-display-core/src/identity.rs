@@ -0,0 +1,179 @@
+use crate::reader::{Reader, Truncated};
+use crate::x509::{Certificate, PublicKey, X509Error, SECP256R1, SECP384R1, SECP521R1};
+
+// The client certificate a panel proves who it is with (mutual TLS)
+// Each panel gets its own certificate and EC private key, issued by a certificate authority the
+// server trusts for panels. led-display-certgen packs them into an identity that is written to a
+// page of flash of its own when the panel is set up, the firmware then hands them to BearSSL for
+// every connection and the server maps the certificate to the panel's name.
+//
+// Layout of an identity, numbers are little endian:
+//   "PNID" then the version, 1
+//   curve (u8, as in x509::SECP256R1 etc.), private key length (u8), private key (big endian)
+//   certificate length (u16), certificate (DER)
+
+// an identity and its length (u16) fit in one page of flash
+pub const MAX_LEN: usize = 1022;
+
+const MAGIC: &[u8] = b"PNID";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentityError {
+    TooLarge,
+    InvalidFormat,
+    // the certificate is not for an EC key on the curve of the private key
+    KeyMismatch,
+    X509(X509Error),
+}
+
+impl From<Truncated> for IdentityError {
+    fn from(_: Truncated) -> Self {
+        IdentityError::InvalidFormat
+    }
+}
+
+impl From<X509Error> for IdentityError {
+    fn from(err: X509Error) -> Self {
+        IdentityError::X509(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Identity<'a> {
+    pub curve: u8,
+    pub key: &'a [u8],
+    pub certificate: &'a [u8],
+}
+
+impl<'a> Identity<'a> {
+    // Checks that the certificate holds a public key on the same curve as the private key
+    // Whether they are actually a pair only shows when the server checks the handshake.
+    pub fn new(curve: u8, key: &'a [u8], certificate: &'a [u8]) -> Result<Self, IdentityError> {
+        let max_key_len = match curve {
+            SECP256R1 => 32,
+            SECP384R1 => 48,
+            SECP521R1 => 66,
+            _ => return Err(X509Error::UnsupportedKey.into()),
+        };
+
+        if key.is_empty() || key.len() > max_key_len {
+            return Err(IdentityError::InvalidFormat);
+        }
+
+        match Certificate::parse(certificate)?.key {
+            PublicKey::Ec { curve: found, .. } if found == curve => Ok(Self {
+                curve,
+                key,
+                certificate,
+            }),
+            _ => Err(IdentityError::KeyMismatch),
+        }
+    }
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, IdentityError> {
+        if data.len() > MAX_LEN {
+            return Err(IdentityError::TooLarge);
+        }
+
+        let mut reader = Reader::new(data);
+        if reader.bytes(MAGIC.len())? != MAGIC || reader.u8()? != VERSION {
+            return Err(IdentityError::InvalidFormat);
+        }
+
+        let curve = reader.u8()?;
+        let key_len = reader.u8()? as usize;
+        let key = reader.bytes(key_len)?;
+        let certificate_len = reader.u16()? as usize;
+        let certificate = reader.bytes(certificate_len)?;
+        if !reader.rest().is_empty() {
+            return Err(IdentityError::InvalidFormat);
+        }
+
+        Self::new(curve, key, certificate)
+    }
+
+    // Writes the identity to out and returns its length
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, IdentityError> {
+        let len = MAGIC.len() + 3 + self.key.len() + 2 + self.certificate.len();
+        if len > MAX_LEN || len > out.len() {
+            return Err(IdentityError::TooLarge);
+        }
+
+        let mut at = 0;
+        for part in [
+            MAGIC,
+            &[VERSION, self.curve, self.key.len() as u8],
+            self.key,
+            &(self.certificate.len() as u16).to_le_bytes(),
+            self.certificate,
+        ] {
+            out[at..at + part.len()].copy_from_slice(part);
+            at += part.len();
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x509;
+
+    const PANEL: &str = include_str!("../../certs/test/panel.pem");
+    const PANEL_KEY: &str = include_str!("../../certs/test/panel-key.pem");
+    const ROOT: &str = include_str!("../../certs/isrg-root-x2.pem");
+
+    fn with_identity<T>(certificate: &str, f: impl FnOnce(&[u8], &[u8]) -> T) -> T {
+        let mut certificate_buf = [0; 1024];
+        let (certificate, _) = x509::decode_pem(certificate, &mut certificate_buf).unwrap();
+        let mut key_buf = [0; 256];
+        let (key, _) = x509::decode_pem_block(PANEL_KEY, "EC PRIVATE KEY", &mut key_buf).unwrap();
+        let (_, key) = x509::ec_private_key(key).unwrap();
+        f(key, certificate)
+    }
+
+    #[test]
+    fn round_trip() {
+        with_identity(PANEL, |key, certificate| {
+            let identity = Identity::new(SECP256R1, key, certificate).unwrap();
+            let mut buf = [0; MAX_LEN];
+            let len = identity.encode(&mut buf).unwrap();
+
+            assert_eq!(Identity::parse(&buf[..len]).unwrap(), identity);
+            assert_eq!(
+                Identity::parse(&buf[..len - 1]).unwrap_err(),
+                IdentityError::InvalidFormat
+            );
+
+            buf[0] = b'X';
+            assert_eq!(
+                Identity::parse(&buf[..len]).unwrap_err(),
+                IdentityError::InvalidFormat
+            );
+        });
+    }
+
+    #[test]
+    fn key_must_match_certificate() {
+        // ISRG Root X2 is a P-384 key
+        with_identity(ROOT, |key, certificate| {
+            assert_eq!(
+                Identity::new(SECP256R1, key, certificate).unwrap_err(),
+                IdentityError::KeyMismatch
+            );
+        });
+
+        with_identity(PANEL, |key, certificate| {
+            assert_eq!(
+                Identity::new(SECP256R1, &[], certificate).unwrap_err(),
+                IdentityError::InvalidFormat
+            );
+            assert_eq!(
+                Identity::new(99, key, certificate).unwrap_err(),
+                IdentityError::X509(X509Error::UnsupportedKey)
+            );
+        });
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{calendar::DateTime, protocol::Idle};
+use core::fmt::Write;
+use heapless::String;
+
+// What the panel shows once it has had nothing else to show for a while
+// Chosen with "#idle clock|date|off" or "#idle text <message>". The clock and date need the local
+// time, until it is known (or without NTP) nothing is shown.
+
+pub const MAX_IDLE_LEN: usize = 32;
+
+pub type IdleText = String<MAX_IDLE_LEN>;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+enum Mode {
+    Off,
+    Clock,
+    Date,
+    Text,
+}
+
+pub struct IdleScreen {
+    mode: Mode,
+    text: IdleText,
+}
+
+impl Default for IdleScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IdleScreen {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Clock,
+            text: IdleText::new(),
+        }
+    }
+
+    pub fn set(&mut self, idle: Idle) {
+        self.mode = match idle {
+            Idle::Off => Mode::Off,
+            Idle::Clock => Mode::Clock,
+            Idle::Date => Mode::Date,
+            Idle::Text(text) => {
+                self.text.clear();
+                for c in text.chars() {
+                    if self.text.push(c).is_err() {
+                        break;
+                    }
+                }
+
+                Mode::Text
+            }
+        };
+    }
+
+    // the text to show, empty for a blank panel
+    pub fn text(&self, now: Option<&DateTime>) -> IdleText {
+        let mut text = IdleText::new();
+
+        // the clock and date are well within MAX_IDLE_LEN and the message is already truncated
+        let _ = match (&self.mode, now) {
+            (Mode::Off, _) | (Mode::Clock, None) | (Mode::Date, None) => Ok(()),
+            (Mode::Clock, Some(now)) => write!(text, "{:02}:{:02}", now.hour, now.minute),
+            (Mode::Date, Some(now)) => write!(
+                text,
+                "{} {} {}",
+                WEEKDAYS[now.weekday as usize % 7],
+                now.day,
+                MONTHS[(now.month as usize + 11) % 12]
+            ),
+            (Mode::Text, _) => write!(text, "{}", self.text),
+        };
+
+        text
+    }
+}
@@ -0,0 +1,93 @@
+// Websocket keepalive
+// A half open TCP connection (e.g. the server was rebooted or a router dropped its NAT entry) is
+// never noticed by simply waiting for the next message. Instead we send a ping whenever the
+// connection has been quiet for a while and give up on the connection if nothing comes back.
+// All times are in milliseconds as returned by Board::now_ms()
+
+// how long the connection may be quiet before we send a ping
+const PING_INTERVAL_MS: u32 = 15_000;
+
+// how long we wait for a reply to a ping before closing the connection
+const PONG_TIMEOUT_MS: u32 = 10_000;
+
+#[derive(Debug, PartialEq)]
+pub enum KeepAliveAction {
+    None,
+    SendPing,
+    TimedOut,
+}
+
+pub struct KeepAlive {
+    last_received_ms: u32,
+    ping_sent_ms: Option<u32>,
+}
+
+impl KeepAlive {
+    pub fn new(now_ms: u32) -> Self {
+        Self {
+            last_received_ms: now_ms,
+            ping_sent_ms: None,
+        }
+    }
+
+    // call this whenever a frame (including a pong) is received
+    pub fn on_received(&mut self, now_ms: u32) {
+        self.last_received_ms = now_ms;
+        self.ping_sent_ms = None;
+    }
+
+    pub fn on_ping_sent(&mut self, now_ms: u32) {
+        self.ping_sent_ms = Some(now_ms);
+    }
+
+    // call this regularly (e.g. whenever a read times out) to find out what to do next
+    pub fn poll(&self, now_ms: u32) -> KeepAliveAction {
+        match self.ping_sent_ms {
+            Some(ping_sent_ms) if now_ms.wrapping_sub(ping_sent_ms) >= PONG_TIMEOUT_MS => {
+                KeepAliveAction::TimedOut
+            }
+            Some(_) => KeepAliveAction::None,
+            None if now_ms.wrapping_sub(self.last_received_ms) >= PING_INTERVAL_MS => {
+                KeepAliveAction::SendPing
+            }
+            None => KeepAliveAction::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_when_quiet_then_time_out() {
+        let mut keepalive = KeepAlive::new(0);
+        assert_eq!(keepalive.poll(PING_INTERVAL_MS - 1), KeepAliveAction::None);
+        assert_eq!(keepalive.poll(PING_INTERVAL_MS), KeepAliveAction::SendPing);
+
+        keepalive.on_ping_sent(PING_INTERVAL_MS);
+        assert_eq!(keepalive.poll(PING_INTERVAL_MS + 1), KeepAliveAction::None);
+        assert_eq!(
+            keepalive.poll(PING_INTERVAL_MS + PONG_TIMEOUT_MS),
+            KeepAliveAction::TimedOut
+        );
+    }
+
+    #[test]
+    fn pong_keeps_connection_alive() {
+        let mut keepalive = KeepAlive::new(0);
+        keepalive.on_ping_sent(PING_INTERVAL_MS);
+        keepalive.on_received(PING_INTERVAL_MS + 100);
+        assert_eq!(
+            keepalive.poll(PING_INTERVAL_MS + PONG_TIMEOUT_MS),
+            KeepAliveAction::None
+        );
+    }
+
+    #[test]
+    fn clock_wraps() {
+        let keepalive = KeepAlive::new(u32::MAX - 1000);
+        assert_eq!(keepalive.poll(10), KeepAliveAction::None);
+        assert_eq!(keepalive.poll(PING_INTERVAL_MS), KeepAliveAction::SendPing);
+    }
+}
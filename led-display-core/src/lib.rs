@@ -0,0 +1,33 @@
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+// The led panel application without the board
+// The firmware (with or without TLS) and the simulator share this. Nothing in here knows about the
+// microcontroller: the panel is driven through the embedded-hal SPI and output pin traits, the
+// websocket through the Connection trait in session.rs and the time is passed in. This means all
+// of it can be built and tested on a PC with `cargo test`.
+
+// Lines start with [INF], [WRN] or [ERR], see log.rs for where they go
+macro_rules! rprintln {
+    ($($arg:tt)*) => {
+        $crate::log::write(format_args!($($arg)*))
+    };
+}
+
+pub mod app;
+pub mod brightness;
+pub mod calendar;
+pub mod display;
+pub mod entropy;
+pub mod identity;
+pub mod idle;
+pub mod keepalive;
+pub mod log;
+pub mod protocol;
+pub mod reader;
+pub mod reconnect;
+pub mod session;
+pub mod sntp;
+pub mod timekeeping;
+pub mod tls;
+pub mod trust;
+pub mod x509;
@@ -0,0 +1,27 @@
+use core::fmt;
+
+// Where log lines go
+// The firmware sends them over RTT by calling set_logger() at startup, before anything is logged.
+// Without a logger they go to stdout when built for a PC and nowhere on the device.
+
+static mut LOGGER: Option<fn(fmt::Arguments)> = None;
+
+pub fn set_logger(logger: fn(fmt::Arguments)) {
+    unsafe { LOGGER = Some(logger) };
+}
+
+pub fn write(args: fmt::Arguments) {
+    let logger = unsafe { LOGGER };
+
+    #[cfg(any(test, feature = "std"))]
+    let logger = logger.or(Some(stdout));
+
+    if let Some(logger) = logger {
+        logger(args);
+    }
+}
+
+#[cfg(any(test, feature = "std"))]
+fn stdout(args: fmt::Arguments) {
+    std::println!("{}", args);
+}
@@ -0,0 +1,399 @@
+use crate::{
+    brightness::MAX_LEVEL,
+    display::{Bitmap, Effect, MAX_BITMAP_LEN, MAX_ZONE_NAME_LEN},
+    trust,
+};
+use core::str::FromStr;
+
+// Text frames sent to the panel are either messages to display or commands starting with '#'
+// (e.g. "#speed 40" or "#fx blink Hello"). The server prefixes chat messages with the name of the
+// sender ("@name - ...") so a chat message can never be mistaken for a command.
+// Either can be sent to a zone of the panel with "#in <zone> ..." (e.g. "#in clock #speed 20"),
+// everything else goes to the default zone.
+
+// The panel sends text frames to the server starting with "/report " to tell it how something it
+// was asked to do went (e.g. a self test).
+
+// Binary frames carry a bitmap, optionally animated:
+//   byte 0  kind of frame, BITMAP
+//   byte 1  flags, TIMED if every frame starts with its duration in ms (u16 little endian) and
+//           ZONED if the bitmap is for a zone rather than the default zone
+//   byte 2  width in columns
+//   byte 3  number of frames
+//   if ZONED, the length of the zone's name (u8) and the name
+//   then the frames, one byte per column with bit 0 at the top (see display::Bitmap)
+// or a trust store for TLS:
+//   byte 0  kind of frame, TRUST_STORE
+//   then the signed trust store (see trust.rs)
+
+const COMMAND_PREFIX: char = '#';
+
+pub const REPORT_PREFIX: &str = "/report ";
+
+const BITMAP: u8 = 1;
+pub const TRUST_STORE: u8 = 2;
+const TIMED: u8 = 0x01;
+const ZONED: u8 = 0x02;
+const BITMAP_HEADER_LEN: usize = 4;
+
+// size of the buffer needed to receive the largest binary frame
+pub const MAX_BINARY_LEN: usize = max(
+    BITMAP_HEADER_LEN + 1 + MAX_ZONE_NAME_LEN + MAX_BITMAP_LEN,
+    1 + trust::MAX_LEN,
+);
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+// a frame for a zone of the panel, None for the default zone
+#[derive(Debug, PartialEq)]
+pub struct Addressed<'a> {
+    pub zone: Option<&'a str>,
+    pub frame: Frame<'a>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Frame<'a> {
+    Message(&'a str),
+    Command(Command<'a>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    // scroll speed in columns per second
+    Speed(u32),
+    // show the text with the given effect
+    Show { effect: Effect, text: &'a str },
+    // create a zone from modules first to last (numbered from 1 at the left)
+    Zone { name: &'a str, first: u8, last: u8 },
+    // a fixed brightness from 0 to 15, None ("auto") for the sensor or schedule
+    Brightness(Option<u8>),
+    // what to show once there is nothing else to show
+    Idle(Idle<'a>),
+    // run the test patterns (see display::selftest)
+    SelfTest,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Binary<'a> {
+    // zone is None for the default zone
+    Bitmap {
+        zone: Option<&'a str>,
+        bitmap: Bitmap<'a>,
+    },
+    // still to be checked, see trust::TrustStore::parse
+    TrustStore(&'a [u8]),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Idle<'a> {
+    Off,
+    Clock,
+    Date,
+    Text(&'a str),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ProtocolError<'a> {
+    UnknownCommand(&'a str),
+    MissingArgument,
+    InvalidArgument(&'a str),
+    UnknownFrameKind(u8),
+    InvalidBitmap,
+}
+
+pub fn parse(text: &str) -> Result<Addressed<'_>, ProtocolError<'_>> {
+    if let Some(command) = text.strip_prefix(COMMAND_PREFIX) {
+        let (name, args) = split_word(command);
+        if name == "in" {
+            let (zone, text) = split_word(args);
+            if zone.is_empty() {
+                return Err(ProtocolError::MissingArgument);
+            }
+
+            return Ok(Addressed {
+                zone: Some(zone),
+                frame: parse_frame(text)?,
+            });
+        }
+    }
+
+    Ok(Addressed {
+        zone: None,
+        frame: parse_frame(text)?,
+    })
+}
+
+fn parse_frame(text: &str) -> Result<Frame<'_>, ProtocolError<'_>> {
+    let command = match text.strip_prefix(COMMAND_PREFIX) {
+        Some(command) => command,
+        None => return Ok(Frame::Message(text)),
+    };
+
+    let (name, args) = split_word(command);
+    let command = match name {
+        "speed" => Command::Speed(parse_arg(split_word(args).0)?),
+        "fx" => {
+            let (effect, text) = split_word(args);
+            Command::Show {
+                effect: parse_arg(effect)?,
+                text,
+            }
+        }
+        "zone" => {
+            let (name, args) = split_word(args);
+            if name.is_empty() {
+                return Err(ProtocolError::MissingArgument);
+            }
+
+            let (first, last) = parse_range(split_word(args).0)?;
+            Command::Zone { name, first, last }
+        }
+        "brightness" => match split_word(args).0 {
+            "auto" => Command::Brightness(None),
+            level => Command::Brightness(Some(parse_level(level)?)),
+        },
+        "idle" => match split_word(args) {
+            ("off", _) => Command::Idle(Idle::Off),
+            ("clock", _) => Command::Idle(Idle::Clock),
+            ("date", _) => Command::Idle(Idle::Date),
+            ("text", text) => Command::Idle(Idle::Text(text)),
+            ("", _) => return Err(ProtocolError::MissingArgument),
+            (mode, _) => return Err(ProtocolError::InvalidArgument(mode)),
+        },
+        "selftest" => Command::SelfTest,
+        name => return Err(ProtocolError::UnknownCommand(name)),
+    };
+
+    Ok(Frame::Command(command))
+}
+
+// splits off the first word, the rest is returned as is so that text arguments keep their spacing
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+fn parse_arg<T: FromStr>(arg: &str) -> Result<T, ProtocolError<'_>> {
+    if arg.is_empty() {
+        return Err(ProtocolError::MissingArgument);
+    }
+
+    arg.parse().map_err(|_| ProtocolError::InvalidArgument(arg))
+}
+
+fn parse_level(arg: &str) -> Result<u8, ProtocolError<'_>> {
+    match parse_arg(arg)? {
+        level if level <= MAX_LEVEL => Ok(level),
+        _ => Err(ProtocolError::InvalidArgument(arg)),
+    }
+}
+
+// either "first-last" or a single number
+fn parse_range<T: FromStr + Copy>(arg: &str) -> Result<(T, T), ProtocolError<'_>> {
+    match arg.find('-') {
+        Some(dash) => Ok((parse_arg(&arg[..dash])?, parse_arg(&arg[dash + 1..])?)),
+        None => {
+            let n = parse_arg(arg)?;
+            Ok((n, n))
+        }
+    }
+}
+
+pub fn parse_binary(data: &[u8]) -> Result<Binary<'_>, ProtocolError<'static>> {
+    match data.first() {
+        Some(&BITMAP) if data.len() >= BITMAP_HEADER_LEN => {
+            let timed = data[1] & TIMED != 0;
+            let (zone, frames) = match data[1] & ZONED {
+                0 => (None, &data[BITMAP_HEADER_LEN..]),
+                _ => {
+                    let (zone, frames) = parse_zone_name(&data[BITMAP_HEADER_LEN..])
+                        .ok_or(ProtocolError::InvalidBitmap)?;
+                    (Some(zone), frames)
+                }
+            };
+
+            Bitmap::new(data[2], data[3], timed, frames)
+                .map(|bitmap| Binary::Bitmap { zone, bitmap })
+                .ok_or(ProtocolError::InvalidBitmap)
+        }
+        Some(&BITMAP) | None => Err(ProtocolError::InvalidBitmap),
+        Some(&TRUST_STORE) => Ok(Binary::TrustStore(&data[1..])),
+        Some(kind) => Err(ProtocolError::UnknownFrameKind(*kind)),
+    }
+}
+
+// the name of a zone (its length then the name) and what follows it
+fn parse_zone_name(data: &[u8]) -> Option<(&str, &[u8])> {
+    let (&len, rest) = data.split_first()?;
+    let len = len as usize;
+    if len == 0 || len > MAX_ZONE_NAME_LEN || len > rest.len() {
+        return None;
+    }
+
+    let name = core::str::from_utf8(&rest[..len]).ok()?;
+    Some((name, &rest[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(text: &str) -> Command<'_> {
+        match parse(text) {
+            Ok(Addressed {
+                zone: None,
+                frame: Frame::Command(command),
+            }) => command,
+            other => panic!("not a command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_is_a_message() {
+        assert_eq!(
+            parse("@bob - #speed 10"),
+            Ok(Addressed {
+                zone: None,
+                frame: Frame::Message("@bob - #speed 10"),
+            })
+        );
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(command("#speed 40"), Command::Speed(40));
+        assert_eq!(
+            command("#fx blink Hello  world"),
+            Command::Show {
+                effect: Effect::Blink,
+                text: "Hello  world",
+            }
+        );
+        assert_eq!(
+            command("#zone clock 1-4"),
+            Command::Zone {
+                name: "clock",
+                first: 1,
+                last: 4,
+            }
+        );
+        assert_eq!(
+            command("#zone clock 3"),
+            Command::Zone {
+                name: "clock",
+                first: 3,
+                last: 3,
+            }
+        );
+        assert_eq!(command("#brightness 15"), Command::Brightness(Some(15)));
+        assert_eq!(command("#brightness auto"), Command::Brightness(None));
+        assert_eq!(
+            command("#idle text Back soon"),
+            Command::Idle(Idle::Text("Back soon"))
+        );
+        assert_eq!(command("#idle clock"), Command::Idle(Idle::Clock));
+        assert_eq!(command("#selftest"), Command::SelfTest);
+    }
+
+    #[test]
+    fn zone_prefix() {
+        assert_eq!(
+            parse("#in clock #speed 20"),
+            Ok(Addressed {
+                zone: Some("clock"),
+                frame: Frame::Command(Command::Speed(20)),
+            })
+        );
+        assert_eq!(
+            parse("#in clock Hello"),
+            Ok(Addressed {
+                zone: Some("clock"),
+                frame: Frame::Message("Hello"),
+            })
+        );
+        assert_eq!(parse("#in"), Err(ProtocolError::MissingArgument));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        assert_eq!(parse("#dance"), Err(ProtocolError::UnknownCommand("dance")));
+        assert_eq!(parse("#speed"), Err(ProtocolError::MissingArgument));
+        assert_eq!(
+            parse("#speed fast"),
+            Err(ProtocolError::InvalidArgument("fast"))
+        );
+        assert_eq!(
+            parse("#brightness 16"),
+            Err(ProtocolError::InvalidArgument("16"))
+        );
+        assert_eq!(parse("#zone clock"), Err(ProtocolError::MissingArgument));
+        assert_eq!(
+            parse("#idle later"),
+            Err(ProtocolError::InvalidArgument("later"))
+        );
+    }
+
+    #[test]
+    fn binary_frames() {
+        assert!(parse_binary(&[BITMAP, 0, 2, 1, 0xff, 0x81]).is_ok());
+        assert_eq!(
+            parse_binary(&[BITMAP, 0]).err(),
+            Some(ProtocolError::InvalidBitmap)
+        );
+        assert_eq!(
+            parse_binary(&[7, 0, 2, 1, 0xff, 0x81]).err(),
+            Some(ProtocolError::UnknownFrameKind(7))
+        );
+        assert_eq!(
+            parse_binary(&[TRUST_STORE, 1, 2]),
+            Ok(Binary::TrustStore(&[1, 2]))
+        );
+        // two frames of two columns but only one sent
+        assert_eq!(
+            parse_binary(&[BITMAP, 0, 2, 2, 0xff, 0x81]).err(),
+            Some(ProtocolError::InvalidBitmap)
+        );
+    }
+
+    #[test]
+    fn zoned_bitmaps() {
+        let frame = [
+            BITMAP, ZONED, 2, 1, 5, b'c', b'l', b'o', b'c', b'k', 0xff, 0x81,
+        ];
+        match parse_binary(&frame) {
+            Ok(Binary::Bitmap { zone, bitmap }) => {
+                assert_eq!(zone, Some("clock"));
+                assert_eq!(bitmap.data(), &[0xff, 0x81]);
+            }
+            other => panic!("not a bitmap: {:?}", other),
+        }
+
+        match parse_binary(&[BITMAP, 0, 2, 1, 0xff, 0x81]) {
+            Ok(Binary::Bitmap { zone, .. }) => assert_eq!(zone, None),
+            other => panic!("not a bitmap: {:?}", other),
+        }
+
+        // no name, a name that runs past the end and one longer than a zone name can be
+        for frame in [
+            &[BITMAP, ZONED, 2, 1, 0, 0xff, 0x81][..],
+            &[BITMAP, ZONED, 2, 1, 3, b'a'][..],
+            &[
+                BITMAP, ZONED, 1, 1, 9, b'a', b'b', b'c', b'd', b'e', b'f', b'g', b'h', b'i', 0,
+            ][..],
+        ] {
+            assert_eq!(
+                parse_binary(frame).err(),
+                Some(ProtocolError::InvalidBitmap)
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Reads the binary layouts in identity.rs and trust.rs, numbers are little endian
+// Every read checks there is enough left and fails with Truncated otherwise, which the parsers turn
+// into their own InvalidFormat.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Truncated;
+
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader(data)
+    }
+
+    // what has not been read yet
+    pub fn rest(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Truncated> {
+        if len > self.0.len() {
+            return Err(Truncated);
+        }
+
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], Truncated> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Truncated> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Truncated> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Truncated> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_in_order() {
+        let mut reader = Reader::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(reader.u8(), Ok(1));
+        assert_eq!(reader.u16(), Ok(0x0302));
+        assert_eq!(reader.u32(), Ok(0x0706_0504));
+        assert_eq!(reader.rest(), &[8, 9]);
+        assert_eq!(reader.u16(), Ok(0x0908));
+        assert!(reader.rest().is_empty());
+    }
+
+    #[test]
+    fn too_short() {
+        let mut reader = Reader::new(&[1, 2, 3]);
+        assert_eq!(reader.u32(), Err(Truncated));
+        assert_eq!(reader.bytes(4), Err(Truncated));
+        // a failed read takes nothing
+        assert_eq!(reader.array::<3>(), Ok([1, 2, 3]));
+    }
+}
@@ -0,0 +1,206 @@
+// Reconnect policy shared by the main loop
+// Each kind of failure has its own exponential backoff schedule so that, for example, a server
+// that refuses TCP connections is retried quickly while a failing websocket handshake (which
+// usually means the server is misconfigured) is retried far less often. The delay is jittered
+// so that a room full of panels does not reconnect in lockstep after a server restart.
+
+// how long a session must stay up before a disconnect is treated as a fresh problem, so that a
+// server that accepts and then immediately closes the connection is not hammered
+pub const STABLE_SESSION_MS: u32 = 60_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    // the time could not be fetched from the NTP server (needed to validate certificates)
+    Ntp,
+    // the TCP connection could not be established
+    Tcp,
+    // the TLS handshake failed (unknown CA, expired certificate, wrong time, etc.)
+    Tls,
+    // the websocket opening handshake failed
+    Handshake,
+    // an established session was dropped
+    Session,
+}
+
+struct Schedule {
+    base_ms: u32,
+    max_ms: u32,
+}
+
+impl Failure {
+    fn schedule(self) -> Schedule {
+        match self {
+            // be polite to the public NTP pool
+            Failure::Ntp => Schedule {
+                base_ms: 10_000,
+                max_ms: 600_000,
+            },
+            Failure::Tcp => Schedule {
+                base_ms: 1_000,
+                max_ms: 60_000,
+            },
+            // a full TLS handshake is expensive for both sides and rarely fixes itself quickly
+            Failure::Tls => Schedule {
+                base_ms: 10_000,
+                max_ms: 600_000,
+            },
+            Failure::Handshake => Schedule {
+                base_ms: 5_000,
+                max_ms: 300_000,
+            },
+            Failure::Session => Schedule {
+                base_ms: 500,
+                max_ms: 30_000,
+            },
+        }
+    }
+
+    // short text shown on the led panel while we are offline
+    pub fn glyph(self) -> &'static str {
+        match self {
+            Failure::Ntp => "NTP?",
+            Failure::Tcp => "NET?",
+            Failure::Tls => "TLS?",
+            Failure::Handshake => "WS?",
+            Failure::Session => "...",
+        }
+    }
+}
+
+pub struct Backoff {
+    attempt: u32,
+    last: Option<Failure>,
+    rng: u32,
+    // when the last connection was fully established, by the millisecond clock
+    connected_at_ms: Option<u32>,
+}
+
+impl Backoff {
+    // the seed should be unique per device so that panels spread out their retries
+    pub fn new(seed: u32) -> Self {
+        Self {
+            attempt: 0,
+            last: None,
+            rng: seed | 1, // xorshift gets stuck on zero
+            connected_at_ms: None,
+        }
+    }
+
+    // call this once a connection has been fully established
+    pub fn connected(&mut self, now_ms: u32) {
+        self.connected_at_ms = Some(now_ms);
+    }
+
+    // call this after every connection attempt, the schedule only starts afresh if the
+    // connection was up for at least STABLE_SESSION_MS
+    pub fn disconnected(&mut self, now_ms: u32) {
+        if let Some(connected_at_ms) = self.connected_at_ms.take() {
+            if now_ms.wrapping_sub(connected_at_ms) >= STABLE_SESSION_MS {
+                self.reset();
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.last = None;
+    }
+
+    // returns the number of milliseconds to wait before the next connection attempt
+    pub fn next_delay_ms(&mut self, failure: Failure) -> u32 {
+        // a different kind of failure means we made progress (or regressed) so start its schedule afresh
+        if self.last != Some(failure) {
+            self.attempt = 0;
+            self.last = Some(failure);
+        }
+
+        let schedule = failure.schedule();
+        let exp = self.attempt.min(16);
+        let delay_ms = schedule
+            .base_ms
+            .saturating_mul(1 << exp)
+            .min(schedule.max_ms);
+        self.attempt = self.attempt.saturating_add(1);
+
+        // equal jitter: wait at least half the delay and a random amount of the other half
+        let half = delay_ms / 2;
+        half + self.next_random() % (delay_ms - half + 1)
+    }
+
+    // xorshift32, good enough for spreading out retries
+    fn next_random(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_up_to_the_limit() {
+        let mut backoff = Backoff::new(1234);
+        let schedule = Failure::Tcp.schedule();
+        for attempt in 0..20 {
+            let full_ms = (schedule.base_ms << attempt.min(16)).min(schedule.max_ms);
+            let delay_ms = backoff.next_delay_ms(Failure::Tcp);
+            assert!(
+                delay_ms >= full_ms / 2 && delay_ms <= full_ms,
+                "{}",
+                delay_ms
+            );
+        }
+    }
+
+    #[test]
+    fn new_kind_of_failure_starts_again() {
+        let mut backoff = Backoff::new(1);
+        for _ in 0..10 {
+            backoff.next_delay_ms(Failure::Tcp);
+        }
+
+        assert!(backoff.next_delay_ms(Failure::Handshake) <= 5_000);
+        assert!(backoff.next_delay_ms(Failure::Tcp) <= 1_000);
+    }
+
+    #[test]
+    fn reset() {
+        let mut backoff = Backoff::new(0);
+        for _ in 0..10 {
+            backoff.next_delay_ms(Failure::Session);
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay_ms(Failure::Session) <= 500);
+    }
+
+    #[test]
+    fn short_sessions_keep_backing_off() {
+        let mut backoff = Backoff::new(7);
+        let mut now_ms = 0;
+        for _ in 0..10 {
+            backoff.connected(now_ms);
+            now_ms += 100;
+            backoff.disconnected(now_ms);
+            now_ms += backoff.next_delay_ms(Failure::Session);
+        }
+        assert!(backoff.next_delay_ms(Failure::Session) >= 15_000);
+
+        // a session that stays up long enough starts the schedule again
+        backoff.connected(u32::MAX - 1_000);
+        backoff.disconnected(STABLE_SESSION_MS);
+        assert!(backoff.next_delay_ms(Failure::Session) <= 500);
+    }
+
+    #[test]
+    fn panels_spread_out() {
+        let first = Backoff::new(1).next_delay_ms(Failure::Handshake);
+        let second = Backoff::new(2).next_delay_ms(Failure::Handshake);
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::{
+    app::{App, Board},
+    display::LedPanelError,
+    keepalive::{KeepAlive, KeepAliveAction},
+};
+use core::fmt;
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+
+// An open websocket to the server
+// The firmware implements this on top of embedded-websocket and its TCP (or TLS) stream and a
+// test or simulator can implement it with anything that hands out frames. Reads must return
+// Received::Nothing straight away when there is no data so that the panel keeps moving.
+
+pub enum Received<'b> {
+    Text(&'b str),
+    Binary(&'b [u8]),
+    Pong,
+    Closed,
+    Nothing,
+}
+
+pub trait Connection {
+    type Error: fmt::Debug;
+
+    fn read<'b>(&mut self, buf: &'b mut [u8]) -> Result<Received<'b>, Self::Error>;
+
+    fn send_text(&mut self, text: &str) -> Result<(), Self::Error>;
+
+    fn send_ping(&mut self) -> Result<(), Self::Error>;
+
+    // closes the websocket and the connection underneath, the server may already be gone
+    fn close(&mut self) -> Result<(), Self::Error>;
+
+    // called whenever there was nothing to read, e.g. to fetch the time while it is quiet
+    fn on_quiet(&mut self) {}
+}
+
+#[derive(Debug)]
+pub enum SessionError<ConnectionError, SpiError, PinError> {
+    Display(LedPanelError<SpiError, PinError>),
+    Connection(ConnectionError),
+    KeepAliveTimeout,
+}
+
+impl<ConnectionError, SpiError, PinError> From<LedPanelError<SpiError, PinError>>
+    for SessionError<ConnectionError, SpiError, PinError>
+{
+    fn from(err: LedPanelError<SpiError, PinError>) -> Self {
+        SessionError::Display(err)
+    }
+}
+
+// Shows whatever the server sends until the connection is closed (Ok) or fails (Err)
+// The websocket opening handshake must already be done.
+pub fn run<C, SPI, CS, SpiError, PinError>(
+    app: &mut App<SPI, CS>,
+    connection: &mut C,
+    board: &mut impl Board,
+    frame_buf: &mut [u8],
+) -> Result<(), SessionError<C::Error, SpiError, PinError>>
+where
+    C: Connection,
+    SPI: Write<u8, Error = SpiError>,
+    CS: OutputPin<Error = PinError>,
+    SpiError: fmt::Debug,
+    PinError: fmt::Debug,
+{
+    let mut keepalive = KeepAlive::new(board.now_ms());
+
+    // queue up messages as they arrive and show them one after the other
+    loop {
+        match connection
+            .read(frame_buf)
+            .map_err(SessionError::Connection)?
+        {
+            Received::Text(text) => {
+                keepalive.on_received(board.now_ms());
+                rprintln!("[INF] Websocket received: {}", text);
+                app.on_text(text, board.now_ms());
+            }
+            Received::Binary(data) => {
+                keepalive.on_received(board.now_ms());
+                rprintln!("[INF] Websocket received: {} bytes", data.len());
+                app.on_binary(data, board);
+            }
+            Received::Pong => keepalive.on_received(board.now_ms()),
+            Received::Closed => return Ok(()),
+            Received::Nothing => {
+                // nothing to read so check that the connection is still alive
+                match keepalive.poll(board.now_ms()) {
+                    KeepAliveAction::None => {}
+                    KeepAliveAction::SendPing => {
+                        rprintln!("[INF] Websocket sending ping");
+                        connection.send_ping().map_err(SessionError::Connection)?;
+                        keepalive.on_ping_sent(board.now_ms());
+                    }
+                    KeepAliveAction::TimedOut => {
+                        rprintln!("[WRN] Websocket ping timed out, closing connection");
+                        connection.close().map_err(SessionError::Connection)?;
+                        return Err(SessionError::KeepAliveTimeout);
+                    }
+                }
+
+                connection.on_quiet();
+            }
+        }
+
+        app.poll(board)?;
+
+        if let Some(report) = app.take_report() {
+            connection
+                .send_text(&report)
+                .map_err(SessionError::Connection)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{LedPanel, NUM_DEVICES};
+    use core::{cell::RefCell, convert::Infallible};
+    use std::{collections::VecDeque, rc::Rc, string::String, vec::Vec};
+
+    // how far the clock moves on every read
+    const STEP_MS: u32 = 10;
+
+    // remembers every (register, data) pair sent to the panel
+    #[derive(Default)]
+    struct RecordingSpi {
+        writes: Vec<[u8; 2]>,
+    }
+
+    impl Write<u8> for RecordingSpi {
+        type Error = Infallible;
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.writes.push([words[0], words[1]]);
+            Ok(())
+        }
+    }
+
+    struct Pin;
+
+    impl OutputPin for Pin {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    struct FakeBoard {
+        now_ms: Rc<RefCell<u32>>,
+    }
+
+    impl Board for FakeBoard {
+        fn now_ms(&self) -> u32 {
+            *self.now_ms.borrow()
+        }
+    }
+
+    enum Frame {
+        Text(&'static str),
+        Binary(&'static [u8]),
+        Pong,
+        Closed,
+    }
+
+    // plays back the frames one per read then has nothing more to say until closed_at_ms
+    struct FakeConnection {
+        now_ms: Rc<RefCell<u32>>,
+        frames: VecDeque<Frame>,
+        closed_at_ms: u32,
+        sent: Vec<String>,
+        pings: usize,
+        closed: bool,
+    }
+
+    impl FakeConnection {
+        fn new(frames: Vec<Frame>, closed_at_ms: u32) -> Self {
+            Self {
+                now_ms: Rc::new(RefCell::new(0)),
+                frames: frames.into(),
+                closed_at_ms,
+                sent: Vec::new(),
+                pings: 0,
+                closed: false,
+            }
+        }
+    }
+
+    impl Connection for FakeConnection {
+        type Error = &'static str;
+
+        fn read<'b>(&mut self, buf: &'b mut [u8]) -> Result<Received<'b>, Self::Error> {
+            let now_ms = {
+                let mut now_ms = self.now_ms.borrow_mut();
+                *now_ms += STEP_MS;
+                *now_ms
+            };
+
+            match self.frames.pop_front() {
+                Some(Frame::Text(text)) => Ok(Received::Text(text)),
+                Some(Frame::Binary(data)) => {
+                    buf[..data.len()].copy_from_slice(data);
+                    Ok(Received::Binary(&buf[..data.len()]))
+                }
+                Some(Frame::Pong) => Ok(Received::Pong),
+                Some(Frame::Closed) => Ok(Received::Closed),
+                None if now_ms >= self.closed_at_ms => Ok(Received::Closed),
+                None => Ok(Received::Nothing),
+            }
+        }
+
+        fn send_text(&mut self, text: &str) -> Result<(), Self::Error> {
+            self.sent.push(text.into());
+            Ok(())
+        }
+
+        fn send_ping(&mut self) -> Result<(), Self::Error> {
+            self.pings += 1;
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<(), Self::Error> {
+            self.closed = true;
+            Ok(())
+        }
+    }
+
+    type SessionResult = Result<(), SessionError<&'static str, Infallible, Infallible>>;
+
+    // runs a session against the fake connection and returns what was sent to the panel
+    fn run_session(connection: &mut FakeConnection) -> (SessionResult, Vec<[u8; 2]>) {
+        let spi = RefCell::new(RecordingSpi::default());
+        let mut pin = Pin;
+        let mut app = App::new(LedPanel::new(&mut pin, &spi));
+        let mut board = FakeBoard {
+            now_ms: connection.now_ms.clone(),
+        };
+        let mut frame_buf = [0; crate::protocol::MAX_BINARY_LEN];
+
+        let result = run(&mut app, connection, &mut board, &mut frame_buf);
+        drop(app);
+        (result, spi.into_inner().writes)
+    }
+
+    fn rows_lit(writes: &[[u8; 2]]) -> usize {
+        writes
+            .iter()
+            .filter(|[register, data]| (1..=8).contains(register) && *data != 0)
+            .count()
+    }
+
+    #[test]
+    fn message_is_shown() {
+        let mut connection = FakeConnection::new(vec![Frame::Text("Hello")], 1_000);
+        let (result, writes) = run_session(&mut connection);
+
+        assert!(result.is_ok());
+        assert!(rows_lit(&writes) > 0);
+        assert_eq!(connection.pings, 0);
+    }
+
+    #[test]
+    fn invalid_frames_are_ignored() {
+        let frames = vec![
+            Frame::Text("#dance"),
+            Frame::Binary(&[9, 9, 9, 9]),
+            Frame::Closed,
+        ];
+        let mut connection = FakeConnection::new(frames, u32::MAX);
+        let (result, writes) = run_session(&mut connection);
+
+        assert!(result.is_ok());
+        assert_eq!(rows_lit(&writes), 0);
+    }
+
+    #[test]
+    fn brightness_command() {
+        let mut connection = FakeConnection::new(vec![Frame::Text("#brightness 3")], 100);
+        let (result, writes) = run_session(&mut connection);
+
+        assert!(result.is_ok());
+        // the intensity register of every module
+        assert!(writes
+            .windows(NUM_DEVICES)
+            .any(|writes| writes.iter().all(|write| *write == [0x0A, 3])));
+    }
+
+    #[test]
+    fn ping_when_quiet_and_give_up_without_pong() {
+        let mut connection = FakeConnection::new(vec![], u32::MAX);
+        let (result, _) = run_session(&mut connection);
+
+        assert!(matches!(result, Err(SessionError::KeepAliveTimeout)));
+        assert_eq!(connection.pings, 1);
+        assert!(connection.closed);
+    }
+
+    #[test]
+    fn pong_keeps_session_alive() {
+        let mut frames: Vec<Frame> = (0..4_000).map(|_| Frame::Pong).collect();
+        frames.push(Frame::Closed);
+        let mut connection = FakeConnection::new(frames, u32::MAX);
+        let (result, _) = run_session(&mut connection);
+
+        assert!(result.is_ok());
+        assert!(!connection.closed);
+    }
+
+    #[test]
+    fn self_test_is_reported() {
+        let frames = vec![Frame::Text("#selftest"), Frame::Pong];
+        let mut connection = FakeConnection::new(frames, 20_000);
+        let (result, _) = run_session(&mut connection);
+
+        assert!(result.is_ok());
+        assert_eq!(
+            connection.sent,
+            ["/report Self test done: 20 modules, 0 SPI errors"]
+        );
+    }
+
+    #[test]
+    fn trust_store_result_is_reported() {
+        let frames = vec![Frame::Binary(&[crate::protocol::TRUST_STORE, 1, 2, 3])];
+        let mut connection = FakeConnection::new(frames, 100);
+        let (result, _) = run_session(&mut connection);
+
+        // the fake board cannot store one
+        assert!(result.is_ok());
+        assert_eq!(
+            connection.sent,
+            ["/report Trust store not updated: Unsupported"]
+        );
+    }
+}
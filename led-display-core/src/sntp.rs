@@ -0,0 +1,462 @@
+// A simple network time protocol (SNTP, RFC 4330) client
+// The firmware has no battery backed clock so it asks an NTP server for the time before making a
+// TLS connection and every few hours after that to keep the panel clock right. Each request
+// waits a limited time for its reply and the servers in the list are tried in turn until one
+// gives a reply that checks out: it must answer our request (origin timestamp), come from a
+// synchronised server (leap indicator and stratum) and not be a kiss-of-death telling us to go
+// away. The sending and receiving is done by the board through the Udp trait.
+//
+// Times are in milliseconds. The board's clock is the millisecond counter plus a base, its idea
+// of the unix time in ms when the counter was 0 (0 if it has never been set). A Sample says how
+// far that clock is off, add offset_ms to the base to correct it.
+
+// the standard NTP port
+pub const PORT: u16 = 123;
+pub const PACKET_LEN: usize = 48;
+
+// seconds from the NTP epoch (1900) to the unix epoch (1970)
+const UNIX_EPOCH: i64 = 2_208_988_800;
+
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const VERSION: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+const MAX_STRATUM: u8 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SntpError {
+    // no valid reply within the timeout
+    Timeout,
+    InvalidLength(usize),
+    // only versions 3 and 4 are understood
+    InvalidVersion(u8),
+    // not a reply from a server
+    InvalidMode(u8),
+    // the server's own clock is not set (leap indicator 3 or stratum 16 and above)
+    Unsynchronized,
+    // stratum 0, the server wants us to slow down or go away, e.g. "RATE" or "DENY"
+    KissOfDeath([u8; 4]),
+    // the reply is not to our last request, e.g. a late reply to an earlier one
+    OriginMismatch,
+    // the server left its receive or transmit timestamp empty
+    InvalidTimestamp,
+}
+
+#[derive(Debug)]
+pub enum QueryError<E> {
+    Io(E),
+    // why the last server failed
+    Sntp(SntpError),
+}
+
+// how far the board's clock is off according to one reply
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub offset_ms: i64,
+    // round trip time less the time the server took to answer
+    pub delay_ms: i64,
+    pub stratum: u8,
+}
+
+// What the client needs from the board
+pub trait Udp {
+    type Error;
+
+    fn send(&mut self, server: [u8; 4], packet: &[u8]) -> Result<(), Self::Error>;
+
+    // a datagram from port 123 if one has arrived (its sender and length), the board may wait a
+    // little when there is none so that the client does not spin
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<([u8; 4], usize)>, Self::Error>;
+
+    fn now_ms(&mut self) -> u32;
+}
+
+// An NTP timestamp for a unix time in ms. The seconds wrap in 2036, from_timestamp() takes
+// timestamps with the top bit clear to be after that.
+pub fn to_timestamp(unix_ms: i64) -> u64 {
+    let seconds = (unix_ms.div_euclid(1000) + UNIX_EPOCH) as u64 & 0xFFFF_FFFF;
+    // rounded up so that from_timestamp() gives the same ms back
+    let fraction = ((unix_ms.rem_euclid(1000) as u64) << 32) + 999;
+    (seconds << 32) | (fraction / 1000)
+}
+
+pub fn from_timestamp(timestamp: u64) -> i64 {
+    let mut seconds = (timestamp >> 32) as i64;
+    if seconds & 0x8000_0000 == 0 {
+        seconds += 1 << 32;
+    }
+
+    let ms = ((timestamp & 0xFFFF_FFFF) * 1000) >> 32;
+    (seconds - UNIX_EPOCH) * 1000 + ms as i64
+}
+
+// A client request, the transmit timestamp comes back as the origin timestamp of the reply
+pub fn request(transmit: u64) -> [u8; PACKET_LEN] {
+    let mut packet = [0; PACKET_LEN];
+    packet[0] = (VERSION << 3) | MODE_CLIENT;
+    packet[40..48].copy_from_slice(&transmit.to_be_bytes());
+    packet
+}
+
+// Checks a reply to the request sent at origin (our transmit timestamp) and received at received
+// (both by the board's clock) and works out the offset and delay
+pub fn parse(reply: &[u8], origin: u64, received: u64) -> Result<Sample, SntpError> {
+    if reply.len() != PACKET_LEN {
+        return Err(SntpError::InvalidLength(reply.len()));
+    }
+
+    let leap = reply[0] >> 6;
+    let version = (reply[0] >> 3) & 0x07;
+    let mode = reply[0] & 0x07;
+    let stratum = reply[1];
+    let timestamp = |at: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&reply[at..at + 8]);
+        u64::from_be_bytes(bytes)
+    };
+
+    if version != 3 && version != 4 {
+        return Err(SntpError::InvalidVersion(version));
+    } else if mode != MODE_SERVER {
+        return Err(SntpError::InvalidMode(mode));
+    } else if timestamp(24) != origin {
+        return Err(SntpError::OriginMismatch);
+    } else if stratum == 0 {
+        // the reference id holds the kiss code
+        let mut code = [0; 4];
+        code.copy_from_slice(&reply[12..16]);
+        return Err(SntpError::KissOfDeath(code));
+    } else if leap == LEAP_UNSYNCHRONIZED || stratum > MAX_STRATUM {
+        return Err(SntpError::Unsynchronized);
+    }
+
+    let (server_received, server_sent) = (timestamp(32), timestamp(40));
+    if server_received == 0 || server_sent == 0 {
+        return Err(SntpError::InvalidTimestamp);
+    }
+
+    let t1 = from_timestamp(origin);
+    let t2 = from_timestamp(server_received);
+    let t3 = from_timestamp(server_sent);
+    let t4 = from_timestamp(received);
+    Ok(Sample {
+        offset_ms: ((t2 - t1) + (t3 - t4)) / 2,
+        delay_ms: (t4 - t1) - (t3 - t2),
+        stratum,
+    })
+}
+
+// Asks the servers in turn, starting with the last one that answered
+pub struct Client<'s> {
+    servers: &'s [[u8; 4]],
+    timeout_ms: u32,
+    attempts: usize,
+    next: usize,
+    // varies the low bits of our transmit timestamps so that replies cannot be guessed
+    nonce: u32,
+}
+
+impl<'s> Client<'s> {
+    // every request waits up to timeout_ms for its reply, a query gives up after attempts
+    // requests (spread over the servers)
+    pub fn new(servers: &'s [[u8; 4]], timeout_ms: u32, attempts: usize, seed: u32) -> Self {
+        Self {
+            servers,
+            timeout_ms,
+            attempts,
+            next: 0,
+            nonce: seed,
+        }
+    }
+
+    // Fetches a sample from the first server to give a valid reply, base_ms is the board's
+    // clock (unix time in ms when udp.now_ms() was 0)
+    pub fn query<U: Udp>(
+        &mut self,
+        udp: &mut U,
+        base_ms: i64,
+    ) -> Result<Sample, QueryError<U::Error>> {
+        let mut error = SntpError::Timeout;
+        for _ in 0..self.attempts {
+            let server = match self.servers.get(self.next) {
+                Some(server) => *server,
+                None => break,
+            };
+
+            match self.ask(udp, server, base_ms)? {
+                Ok(sample) => {
+                    rprintln!(
+                        "[INF] Time from NTP server {}: offset {} ms, delay {} ms, stratum {}",
+                        Address(server),
+                        sample.offset_ms,
+                        sample.delay_ms,
+                        sample.stratum
+                    );
+                    return Ok(sample);
+                }
+                Err(sntp_error) => {
+                    rprintln!("[WRN] NTP server {}: {:?}", Address(server), sntp_error);
+                    error = sntp_error;
+                    self.next = (self.next + 1) % self.servers.len();
+                }
+            }
+        }
+
+        Err(QueryError::Sntp(error))
+    }
+
+    // one request, an io error ends the whole query while anything wrong with the server
+    // moves on to the next one
+    fn ask<U: Udp>(
+        &mut self,
+        udp: &mut U,
+        server: [u8; 4],
+        base_ms: i64,
+    ) -> Result<Result<Sample, SntpError>, QueryError<U::Error>> {
+        // bits well below a millisecond
+        self.nonce = self
+            .nonce
+            .wrapping_mul(1_664_525)
+            .wrapping_add(1_013_904_223);
+        let sent_ms = udp.now_ms();
+        let origin = to_timestamp(base_ms + sent_ms as i64) ^ (self.nonce >> 16) as u64;
+        udp.send(server, &request(origin)).map_err(QueryError::Io)?;
+
+        let mut reply = [0; PACKET_LEN + 1];
+        loop {
+            let received = udp.receive(&mut reply).map_err(QueryError::Io)?;
+            let elapsed_ms = udp.now_ms().wrapping_sub(sent_ms);
+            match received {
+                Some((from, len)) if from == server => {
+                    let received = to_timestamp(base_ms + sent_ms as i64 + elapsed_ms as i64);
+                    match parse(&reply[..len.min(reply.len())], origin, received) {
+                        // a late reply to an earlier request, keep waiting
+                        Err(SntpError::OriginMismatch) => {}
+                        result => return Ok(result),
+                    }
+                }
+                // someone else, ignore it
+                Some(_) => {}
+                None => {}
+            }
+
+            if elapsed_ms >= self.timeout_ms {
+                return Ok(Err(SntpError::Timeout));
+            }
+        }
+    }
+}
+
+struct Address([u8; 4]);
+
+impl core::fmt::Display for Address {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::VecDeque, vec::Vec};
+
+    // 2026-10-19 12:00:00 UTC
+    const NOW_MS: i64 = 1_792_411_200_000;
+
+    // A reply from a stratum 2 server as it comes off the wire: version 4, mode 4, poll 3,
+    // precision -24, reference id 192.0.2.1, and the origin timestamp is filled in per test
+    const REPLY: [u8; PACKET_LEN] = [
+        0x24, 0x02, 0x03, 0xE8, 0x00, 0x00, 0x00, 0x19, 0x00, 0x00, 0x00, 0x2D, 0xC0, 0x00, 0x02,
+        0x01, 0xEE, 0x80, 0x76, 0xB0, 0x3A, 0x5E, 0x35, 0x3F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0xEE, 0x80, 0x84, 0xC0, 0x19, 0x99, 0x99, 0x9A, 0xEE, 0x80, 0x84, 0xC0, 0x1A,
+        0x9F, 0xBE, 0x77,
+    ];
+
+    // A rate limiting kiss-of-death: leap 3, version 4, mode 4, stratum 0 and "RATE"
+    const RATE: [u8; PACKET_LEN] = [
+        0xE4, 0x00, 0x03, 0xE8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x52, 0x41, 0x54,
+        0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00,
+    ];
+
+    fn with_origin(packet: &[u8; PACKET_LEN], origin: u64) -> [u8; PACKET_LEN] {
+        let mut packet = *packet;
+        packet[24..32].copy_from_slice(&origin.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn timestamps() {
+        // the server's timestamps: received 12:00:00.100, sent 12:00:00.104
+        assert_eq!(from_timestamp(0xEE80_84C0_1999_999A), NOW_MS + 100);
+        assert_eq!(from_timestamp(0xEE80_84C0_1A9F_BE77), NOW_MS + 104);
+        assert_eq!(from_timestamp(to_timestamp(NOW_MS + 123)), NOW_MS + 123);
+
+        // before the board knows the time, and after the NTP seconds wrap in 2036
+        assert_eq!(from_timestamp(to_timestamp(5_000)), 5_000);
+        let after_2036 = 2_100_000_000_000;
+        assert_eq!(from_timestamp(to_timestamp(after_2036)), after_2036);
+    }
+
+    #[test]
+    fn offset_and_delay() {
+        // the board thinks it is 10 s earlier than it is, the request takes 50 ms each way
+        let origin = to_timestamp(NOW_MS + 50 - 10_000);
+        let received = to_timestamp(NOW_MS + 154 - 10_000);
+        let sample = parse(&with_origin(&REPLY, origin), origin, received).unwrap();
+
+        assert_eq!(sample.offset_ms, 10_000);
+        assert_eq!(sample.delay_ms, 100);
+        assert_eq!(sample.stratum, 2);
+    }
+
+    #[test]
+    fn invalid_replies() {
+        let origin = to_timestamp(NOW_MS);
+        let reply = with_origin(&REPLY, origin);
+        let check = |reply: &[u8]| parse(reply, origin, origin).unwrap_err();
+
+        assert_eq!(check(&reply[..47]), SntpError::InvalidLength(47));
+        assert_eq!(
+            parse(&REPLY, origin, origin).unwrap_err(),
+            SntpError::OriginMismatch
+        );
+        assert_eq!(
+            check(&with_origin(&RATE, origin)),
+            SntpError::KissOfDeath(*b"RATE")
+        );
+
+        let mut changed = reply;
+        changed[0] = 0x23; // a client request
+        assert_eq!(check(&changed), SntpError::InvalidMode(3));
+        changed[0] = 0x14; // version 2
+        assert_eq!(check(&changed), SntpError::InvalidVersion(2));
+        changed[0] = 0xE4; // leap 3
+        assert_eq!(check(&changed), SntpError::Unsynchronized);
+        changed = reply;
+        changed[1] = 16;
+        assert_eq!(check(&changed), SntpError::Unsynchronized);
+        changed = reply;
+        changed[40..48].copy_from_slice(&[0; 8]);
+        assert_eq!(check(&changed), SntpError::InvalidTimestamp);
+
+        // version 3 servers are fine
+        changed = reply;
+        changed[0] = 0x1C;
+        assert!(parse(&changed, origin, origin).is_ok());
+    }
+
+    enum Answer {
+        Nothing,
+        Reply(&'static [u8; PACKET_LEN]),
+        // a reply that is not to our request
+        Stale,
+    }
+
+    // answers each request in turn after 40 ms, a server that says nothing times out
+    struct FakeUdp {
+        now_ms: u32,
+        answers: VecDeque<Answer>,
+        pending: Option<[u8; PACKET_LEN]>,
+        reply_at_ms: u32,
+        server: [u8; 4],
+        asked: Vec<[u8; 4]>,
+    }
+
+    impl FakeUdp {
+        fn new(answers: Vec<Answer>) -> Self {
+            Self {
+                now_ms: 1_000,
+                answers: answers.into(),
+                pending: None,
+                reply_at_ms: 0,
+                server: [0; 4],
+                asked: Vec::new(),
+            }
+        }
+    }
+
+    impl Udp for FakeUdp {
+        type Error = ();
+
+        fn send(&mut self, server: [u8; 4], packet: &[u8]) -> Result<(), ()> {
+            let mut origin = [0; 8];
+            origin.copy_from_slice(&packet[40..48]);
+            let origin = u64::from_be_bytes(origin);
+
+            self.server = server;
+            self.asked.push(server);
+            self.reply_at_ms = self.now_ms + 40;
+            self.pending = match self.answers.pop_front() {
+                Some(Answer::Reply(reply)) => Some(with_origin(reply, origin)),
+                Some(Answer::Stale) => Some(with_origin(&REPLY, origin ^ 1)),
+                Some(Answer::Nothing) | None => None,
+            };
+            Ok(())
+        }
+
+        fn receive(&mut self, buf: &mut [u8]) -> Result<Option<([u8; 4], usize)>, ()> {
+            self.now_ms += 10;
+            match self.pending {
+                Some(reply) if self.now_ms >= self.reply_at_ms => {
+                    self.pending = None;
+                    buf[..PACKET_LEN].copy_from_slice(&reply);
+                    Ok(Some((self.server, PACKET_LEN)))
+                }
+                _ => Ok(None),
+            }
+        }
+
+        fn now_ms(&mut self) -> u32 {
+            self.now_ms
+        }
+    }
+
+    const SERVERS: [[u8; 4]; 2] = [[192, 0, 2, 1], [192, 0, 2, 2]];
+
+    #[test]
+    fn first_server_answers() {
+        let mut udp = FakeUdp::new(vec![Answer::Reply(&REPLY)]);
+        let mut client = Client::new(&SERVERS, 500, 4, 1);
+        let sample = client.query(&mut udp, 0).unwrap();
+
+        assert_eq!(udp.asked, [SERVERS[0]]);
+        assert_eq!(sample.stratum, 2);
+        // the board's clock starts at 0 so the offset is about the time itself
+        assert!((sample.offset_ms - NOW_MS).abs() < 1_000);
+    }
+
+    #[test]
+    fn next_server_after_timeout_or_kiss_of_death() {
+        let answers = vec![Answer::Nothing, Answer::Reply(&RATE), Answer::Reply(&REPLY)];
+        let mut udp = FakeUdp::new(answers);
+        let mut client = Client::new(&SERVERS, 500, 4, 1);
+        assert!(client.query(&mut udp, 0).is_ok());
+        assert_eq!(udp.asked, [SERVERS[0], SERVERS[1], SERVERS[0]]);
+
+        // the server that answered is asked first next time
+        let mut udp = FakeUdp::new(vec![Answer::Reply(&REPLY)]);
+        assert!(client.query(&mut udp, 0).is_ok());
+        assert_eq!(udp.asked, [SERVERS[0]]);
+    }
+
+    #[test]
+    fn stale_replies_are_ignored() {
+        let mut udp = FakeUdp::new(vec![Answer::Stale]);
+        let mut client = Client::new(&SERVERS, 500, 1, 1);
+        let error = client.query(&mut udp, 0).unwrap_err();
+
+        assert!(matches!(error, QueryError::Sntp(SntpError::Timeout)));
+        assert!(udp.now_ms >= 1_500);
+    }
+
+    #[test]
+    fn gives_up_after_attempts() {
+        let mut udp = FakeUdp::new(vec![]);
+        let mut client = Client::new(&SERVERS, 200, 3, 1);
+        assert!(client.query(&mut udp, 0).is_err());
+        assert_eq!(udp.asked, [SERVERS[0], SERVERS[1], SERVERS[0]]);
+    }
+}
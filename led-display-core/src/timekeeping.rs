@@ -0,0 +1,147 @@
+use crate::sntp::Sample;
+
+// Keeps the unix time between NTP syncs
+// The board only has a millisecond counter that starts at 0 on reset and wraps after about 49
+// days. The Timekeeper extends it to 64 bits so that it never wraps and keeps the unix time the
+// counter was at 0 (base_ms), corrected by each NTP sample, so the time is always the counter
+// plus the base. The counter must be passed in at least once every 49 days, resyncs happen far
+// more often than that.
+
+// times are by the board's millisecond counter
+pub struct Timekeeper {
+    resync_interval_ms: u64,
+    retry_ms: u64,
+    // the counter extended to 64 bits and its last 32 bit value
+    ticks: u64,
+    last_tick: u32,
+    // unix time in ms when ticks was 0, None until the first sync
+    base_ms: Option<i64>,
+    synced_at: u64,
+    attempted_at: Option<u64>,
+}
+
+impl Timekeeper {
+    // the time is fetched again every resync_interval_ms, and retry_ms after a failed attempt
+    pub const fn new(resync_interval_ms: u32, retry_ms: u32) -> Self {
+        Self {
+            resync_interval_ms: resync_interval_ms as u64,
+            retry_ms: retry_ms as u64,
+            ticks: 0,
+            last_tick: 0,
+            base_ms: None,
+            synced_at: 0,
+            attempted_at: None,
+        }
+    }
+
+    fn advance(&mut self, tick: u32) -> u64 {
+        self.ticks += tick.wrapping_sub(self.last_tick) as u64;
+        self.last_tick = tick;
+        self.ticks
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.base_ms.is_some()
+    }
+
+    // the unix time in ms, None until the first sync
+    pub fn now_ms(&mut self, tick: u32) -> Option<i64> {
+        let ticks = self.advance(tick);
+        self.base_ms.map(|base_ms| base_ms + ticks as i64)
+    }
+
+    // The base for sntp::Client::query, which counts with the 32 bit counter: the unix time in
+    // ms when it was last 0 (0 until the first sync)
+    pub fn query_base_ms(&mut self, tick: u32) -> i64 {
+        let ticks = self.advance(tick);
+        self.base_ms.unwrap_or(0) + (ticks - tick as u64) as i64
+    }
+
+    // a sample from a query with query_base_ms(), the clock steps to the corrected time
+    // The offset is the same for the 32 and 64 bit counters, even if the first wrapped since.
+    pub fn on_sample(&mut self, sample: &Sample, tick: u32) {
+        self.base_ms = Some(self.base_ms.unwrap_or(0) + sample.offset_ms);
+        self.synced_at = self.advance(tick);
+    }
+
+    pub fn on_attempt(&mut self, tick: u32) {
+        self.attempted_at = Some(self.advance(tick));
+    }
+
+    // true when the time has never been fetched or is getting old, and we have not asked for it
+    // recently
+    pub fn resync_due(&mut self, tick: u32) -> bool {
+        let ticks = self.advance(tick);
+        let stale = !self.is_set() || ticks - self.synced_at >= self.resync_interval_ms;
+        let retry = match self.attempted_at {
+            Some(attempted_at) => ticks - attempted_at >= self.retry_ms,
+            None => true,
+        };
+
+        stale && retry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW_MS: i64 = 1_792_411_200_000;
+    const HOUR_MS: u32 = 60 * 60 * 1000;
+
+    fn sample(offset_ms: i64) -> Sample {
+        Sample {
+            offset_ms,
+            delay_ms: 20,
+            stratum: 2,
+        }
+    }
+
+    #[test]
+    fn advances_between_syncs() {
+        let mut time = Timekeeper::new(6 * HOUR_MS, 5 * 60 * 1000);
+        assert_eq!(time.now_ms(1_000), None);
+
+        // the query saw a base of 0 so the offset is the unix time at tick 0
+        assert_eq!(time.query_base_ms(2_000), 0);
+        time.on_sample(&sample(NOW_MS), 2_000);
+        assert_eq!(time.now_ms(2_000), Some(NOW_MS + 2_000));
+        assert_eq!(time.now_ms(62_000), Some(NOW_MS + 62_000));
+
+        // a resync corrects the drift
+        let base_ms = time.query_base_ms(100_000);
+        assert_eq!(base_ms, NOW_MS);
+        time.on_sample(&sample(-500), 100_000);
+        assert_eq!(time.now_ms(100_000), Some(NOW_MS + 99_500));
+    }
+
+    #[test]
+    fn counter_wraps() {
+        let mut time = Timekeeper::new(6 * HOUR_MS, 5 * 60 * 1000);
+        let before_wrap = u32::MAX - 1_000;
+        time.now_ms(before_wrap);
+        time.on_sample(&sample(NOW_MS), before_wrap);
+        let at_sync = time.now_ms(before_wrap).unwrap();
+
+        // 2 s later the counter has wrapped but the time keeps going
+        assert_eq!(time.now_ms(999), Some(at_sync + 2_000));
+        // and the query sees the base of the wrapped counter
+        assert_eq!(time.query_base_ms(999), at_sync + 2_000 - 999);
+    }
+
+    #[test]
+    fn resync_schedule() {
+        let mut time = Timekeeper::new(6 * HOUR_MS, 5 * 60 * 1000);
+        assert!(time.resync_due(0));
+
+        // a failed attempt waits 5 minutes before the next one
+        time.on_attempt(1_000);
+        assert!(!time.resync_due(2_000));
+        assert!(time.resync_due(301_000));
+
+        time.on_attempt(301_000);
+        time.on_sample(&sample(NOW_MS), 301_000);
+        assert!(!time.resync_due(301_000 + HOUR_MS));
+        assert!(time.resync_due(301_000 + 6 * HOUR_MS));
+    }
+}
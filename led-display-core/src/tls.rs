@@ -0,0 +1,315 @@
+use core::fmt;
+
+// Why BearSSL gave up on a TLS connection
+// The engine reports a BR_ERR_* number (see led-display-hardware/src/bearssl.rs). The numbers are
+// turned into a TlsError here so that the firmware can log something readable and show a short
+// category on the panel, which is often all someone standing in front of it has to go on.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsError {
+    Engine(EngineError),
+    Certificate(CertificateError),
+    // the server sent a fatal alert, the number is from the TLS spec (e.g. 40 handshake failure)
+    ReceivedAlert(u8),
+    // we sent a fatal alert to the server
+    SentAlert(u8),
+    // a number this firmware does not know about
+    Unknown(i32),
+}
+
+// BR_ERR_* from the SSL engine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EngineError {
+    BadParam,
+    BadState,
+    UnsupportedVersion,
+    BadVersion,
+    BadLength,
+    TooLarge,
+    BadMac,
+    NoRandom,
+    UnknownType,
+    Unexpected,
+    BadCcs,
+    BadAlert,
+    BadHandshake,
+    OversizedId,
+    BadCipherSuite,
+    BadCompression,
+    BadFragLen,
+    BadSecureRenegotiation,
+    ExtraExtension,
+    BadSni,
+    BadHelloDone,
+    LimitExceeded,
+    BadFinished,
+    ResumeMismatch,
+    InvalidAlgorithm,
+    BadSignature,
+    WrongKeyUsage,
+    NoClientAuth,
+    Io,
+}
+
+// BR_ERR_X509_* from the certificate checks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CertificateError {
+    InvalidValue,
+    Truncated,
+    EmptyChain,
+    InnerTruncated,
+    BadTagClass,
+    BadTagValue,
+    IndefiniteLength,
+    ExtraElement,
+    Unexpected,
+    NotConstructed,
+    NotPrimitive,
+    PartialByte,
+    BadBoolean,
+    Overflow,
+    BadDn,
+    BadTime,
+    Unsupported,
+    LimitExceeded,
+    WrongKeyType,
+    BadSignature,
+    TimeUnknown,
+    Expired,
+    DnMismatch,
+    BadServerName,
+    CriticalExtension,
+    NotCa,
+    ForbiddenKeyUsage,
+    WeakPublicKey,
+    // no trust anchor or pin matches, see trust.rs
+    NotTrusted,
+}
+
+// what is most likely wrong, for the panel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    // the clock is wrong or the certificate has expired
+    Time,
+    // the certificate does not chain to a trust anchor or match a pin
+    Trust,
+    // the certificate is not for the host name we connected to
+    Name,
+    // the certificate could not be parsed or uses something BearSSL does not support
+    Certificate,
+    // the server sent an alert, its logs will say why
+    Alert,
+    // the two sides do not agree on the protocol (version, cipher suites, etc.)
+    Protocol,
+    // the connection underneath failed
+    Io,
+    // something in the firmware, e.g. the engine was used the wrong way
+    Internal,
+}
+
+// BR_ERR_RECV_FATAL_ALERT and BR_ERR_SEND_FATAL_ALERT, the alert number is added to them
+const RECV_FATAL_ALERT: i32 = 256;
+const SEND_FATAL_ALERT: i32 = 512;
+
+impl TlsError {
+    // None for BR_ERR_OK, which means the connection was closed normally
+    pub fn from_code(code: i32) -> Option<Self> {
+        use CertificateError as C;
+        use EngineError as E;
+
+        let error = match code {
+            0 => return None,
+            1 => TlsError::Engine(E::BadParam),
+            2 => TlsError::Engine(E::BadState),
+            3 => TlsError::Engine(E::UnsupportedVersion),
+            4 => TlsError::Engine(E::BadVersion),
+            5 => TlsError::Engine(E::BadLength),
+            6 => TlsError::Engine(E::TooLarge),
+            7 => TlsError::Engine(E::BadMac),
+            8 => TlsError::Engine(E::NoRandom),
+            9 => TlsError::Engine(E::UnknownType),
+            10 => TlsError::Engine(E::Unexpected),
+            12 => TlsError::Engine(E::BadCcs),
+            13 => TlsError::Engine(E::BadAlert),
+            14 => TlsError::Engine(E::BadHandshake),
+            15 => TlsError::Engine(E::OversizedId),
+            16 => TlsError::Engine(E::BadCipherSuite),
+            17 => TlsError::Engine(E::BadCompression),
+            18 => TlsError::Engine(E::BadFragLen),
+            19 => TlsError::Engine(E::BadSecureRenegotiation),
+            20 => TlsError::Engine(E::ExtraExtension),
+            21 => TlsError::Engine(E::BadSni),
+            22 => TlsError::Engine(E::BadHelloDone),
+            23 => TlsError::Engine(E::LimitExceeded),
+            24 => TlsError::Engine(E::BadFinished),
+            25 => TlsError::Engine(E::ResumeMismatch),
+            26 => TlsError::Engine(E::InvalidAlgorithm),
+            27 => TlsError::Engine(E::BadSignature),
+            28 => TlsError::Engine(E::WrongKeyUsage),
+            29 => TlsError::Engine(E::NoClientAuth),
+            31 => TlsError::Engine(E::Io),
+            33 => TlsError::Certificate(C::InvalidValue),
+            34 => TlsError::Certificate(C::Truncated),
+            35 => TlsError::Certificate(C::EmptyChain),
+            36 => TlsError::Certificate(C::InnerTruncated),
+            37 => TlsError::Certificate(C::BadTagClass),
+            38 => TlsError::Certificate(C::BadTagValue),
+            39 => TlsError::Certificate(C::IndefiniteLength),
+            40 => TlsError::Certificate(C::ExtraElement),
+            41 => TlsError::Certificate(C::Unexpected),
+            42 => TlsError::Certificate(C::NotConstructed),
+            43 => TlsError::Certificate(C::NotPrimitive),
+            44 => TlsError::Certificate(C::PartialByte),
+            45 => TlsError::Certificate(C::BadBoolean),
+            46 => TlsError::Certificate(C::Overflow),
+            47 => TlsError::Certificate(C::BadDn),
+            48 => TlsError::Certificate(C::BadTime),
+            49 => TlsError::Certificate(C::Unsupported),
+            50 => TlsError::Certificate(C::LimitExceeded),
+            51 => TlsError::Certificate(C::WrongKeyType),
+            52 => TlsError::Certificate(C::BadSignature),
+            53 => TlsError::Certificate(C::TimeUnknown),
+            54 => TlsError::Certificate(C::Expired),
+            55 => TlsError::Certificate(C::DnMismatch),
+            56 => TlsError::Certificate(C::BadServerName),
+            57 => TlsError::Certificate(C::CriticalExtension),
+            58 => TlsError::Certificate(C::NotCa),
+            59 => TlsError::Certificate(C::ForbiddenKeyUsage),
+            60 => TlsError::Certificate(C::WeakPublicKey),
+            62 => TlsError::Certificate(C::NotTrusted),
+            RECV_FATAL_ALERT..=0x1FF => TlsError::ReceivedAlert((code - RECV_FATAL_ALERT) as u8),
+            SEND_FATAL_ALERT..=0x2FF => TlsError::SentAlert((code - SEND_FATAL_ALERT) as u8),
+            _ => TlsError::Unknown(code),
+        };
+
+        Some(error)
+    }
+
+    pub fn category(&self) -> Category {
+        use CertificateError as C;
+        use EngineError as E;
+
+        match self {
+            TlsError::Certificate(C::TimeUnknown) | TlsError::Certificate(C::Expired) => {
+                Category::Time
+            }
+            TlsError::Certificate(C::EmptyChain)
+            | TlsError::Certificate(C::DnMismatch)
+            | TlsError::Certificate(C::NotCa)
+            | TlsError::Certificate(C::BadSignature)
+            | TlsError::Certificate(C::WeakPublicKey)
+            | TlsError::Certificate(C::NotTrusted) => Category::Trust,
+            TlsError::Certificate(C::BadServerName) => Category::Name,
+            TlsError::Certificate(_) => Category::Certificate,
+            TlsError::ReceivedAlert(_) => Category::Alert,
+            TlsError::Engine(E::Io) => Category::Io,
+            TlsError::Engine(E::BadParam)
+            | TlsError::Engine(E::BadState)
+            | TlsError::Engine(E::NoRandom)
+            | TlsError::Unknown(_) => Category::Internal,
+            TlsError::Engine(_) | TlsError::SentAlert(_) => Category::Protocol,
+        }
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TlsError::Engine(error) => write!(f, "{:?}", error),
+            TlsError::Certificate(error) => write!(f, "certificate {:?}", error),
+            TlsError::ReceivedAlert(alert) => write!(f, "server sent alert {}", alert),
+            TlsError::SentAlert(alert) => write!(f, "sent alert {}", alert),
+            TlsError::Unknown(code) => write!(f, "unknown error {}", code),
+        }?;
+
+        write!(f, " ({})", self.category().description())
+    }
+}
+
+impl Category {
+    fn description(self) -> &'static str {
+        match self {
+            Category::Time => "check the clock and the certificate dates",
+            Category::Trust => "the certificate is not trusted",
+            Category::Name => "the certificate is for another host",
+            Category::Certificate => "the certificate is invalid or unsupported",
+            Category::Alert => "the server refused the connection",
+            Category::Protocol => "the server and panel do not agree on TLS",
+            Category::Io => "the connection failed",
+            Category::Internal => "firmware error",
+        }
+    }
+
+    // short text shown on the led panel while we are offline, see reconnect::Failure::glyph()
+    pub fn glyph(self) -> &'static str {
+        match self {
+            Category::Time => "TLS TIME?",
+            Category::Trust => "TLS CA?",
+            Category::Name => "TLS NAME?",
+            Category::Certificate => "TLS CERT?",
+            Category::Alert => "TLS ALERT?",
+            Category::Protocol => "TLS PROTO?",
+            Category::Io => "TLS IO?",
+            Category::Internal => "TLS ERR?",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn codes() {
+        assert_eq!(TlsError::from_code(0), None);
+        assert_eq!(
+            TlsError::from_code(54),
+            Some(TlsError::Certificate(CertificateError::Expired))
+        );
+        assert_eq!(
+            TlsError::from_code(62),
+            Some(TlsError::Certificate(CertificateError::NotTrusted))
+        );
+        assert_eq!(
+            TlsError::from_code(16),
+            Some(TlsError::Engine(EngineError::BadCipherSuite))
+        );
+        assert_eq!(TlsError::from_code(11), Some(TlsError::Unknown(11)));
+    }
+
+    #[test]
+    fn alerts() {
+        // handshake failure from the server, unrecognized name from us
+        assert_eq!(
+            TlsError::from_code(256 + 40),
+            Some(TlsError::ReceivedAlert(40))
+        );
+        assert_eq!(
+            TlsError::from_code(512 + 112),
+            Some(TlsError::SentAlert(112))
+        );
+        assert_eq!(TlsError::from_code(1024), Some(TlsError::Unknown(1024)));
+    }
+
+    #[test]
+    fn categories() {
+        let category = |code| TlsError::from_code(code).unwrap().category();
+        assert_eq!(category(53), Category::Time);
+        assert_eq!(category(62), Category::Trust);
+        assert_eq!(category(56), Category::Name);
+        assert_eq!(category(38), Category::Certificate);
+        assert_eq!(category(256 + 40), Category::Alert);
+        assert_eq!(category(14), Category::Protocol);
+        assert_eq!(category(31), Category::Io);
+        assert_eq!(category(8), Category::Internal);
+    }
+
+    #[test]
+    fn readable() {
+        assert_eq!(
+            TlsError::from_code(54).unwrap().to_string(),
+            "certificate Expired (check the clock and the certificate dates)"
+        );
+    }
+}
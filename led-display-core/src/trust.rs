@@ -0,0 +1,617 @@
+use crate::reader::{Reader, Truncated};
+use crate::x509::{self, PublicKey, X509Error};
+use sha2::{Digest, Sha256};
+
+// Trust anchors and pins for TLS
+// The firmware trusts the root certificates in a table generated at build time (see
+// led-display-certgen) unless a trust store has been written to flash. A trust store can be sent
+// to the panels over the websocket to replace the roots without flashing them, so it is signed
+// with ECDSA over P-256 and anything the public key built into the firmware does not verify is
+// refused. The private key never goes near a panel so reading one out does not let anyone sign a
+// store. Each store has a version and a panel only takes one newer than the store it has, so an
+// old store (e.g. one still trusting a revoked root) cannot be sent again.
+//
+// Pins narrow things down further: when there are any, the server must present a certificate
+// (Pin::Certificate, SHA-256 of its DER) or a public key (Pin::Spki, SHA-256 of its
+// SubjectPublicKeyInfo) matching one of them as well as chaining to a trusted root.
+//
+// Layout of a trust store, numbers are little endian:
+//   "TRST" then the format, 2, the version of the store (u32) and the length of the entries (u16)
+//   entries, each a kind (u8), the length of the rest of the entry (u16) and then
+//     ANCHOR: flags (u8, CA), subject length (u16) and subject (DER), then the key type and key:
+//       RSA: modulus length (u16), modulus, exponent length (u16), exponent
+//       EC: curve (u8, as in x509::SECP256R1 etc.), point length (u16), point
+//     PIN_CERTIFICATE or PIN_SPKI: the 32 byte hash
+//   the signature of the SHA-256 of everything before it, ECDSA in ASN.1 DER (as made by
+//   `openssl pkeyutl -sign`)
+
+// a trust store and its length (u16) fit in one page of flash
+pub const MAX_LEN: usize = 1022;
+
+// the most anchors and pins the firmware keeps for a connection
+pub const MAX_ANCHORS: usize = 4;
+pub const MAX_PINS: usize = 4;
+
+// the public key stores are checked against, an uncompressed P-256 point
+pub const PUBLIC_KEY_LEN: usize = 65;
+// an ASN.1 ECDSA signature over P-256
+pub const MAX_SIGNATURE_LEN: usize = 72;
+
+const MAGIC: &[u8] = b"TRST";
+const FORMAT: u8 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + 2;
+
+const ANCHOR: u8 = 1;
+const PIN_CERTIFICATE: u8 = 2;
+const PIN_SPKI: u8 = 3;
+
+const FLAG_CA: u8 = 0x01;
+const KEY_RSA: u8 = 1;
+const KEY_EC: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrustError {
+    // this board cannot store a trust store (e.g. a build without TLS)
+    Unsupported,
+    TooLarge,
+    InvalidFormat,
+    // not signed with the key built into the firmware
+    BadSignature,
+    // not newer than the trust store the panel already has
+    Outdated,
+    // a trust store must have at least one anchor
+    NoAnchors,
+    TooManyAnchors,
+    TooManyPins,
+    // the store was fine but could not be saved
+    Storage,
+    X509(X509Error),
+}
+
+impl From<Truncated> for TrustError {
+    fn from(_: Truncated) -> Self {
+        TrustError::InvalidFormat
+    }
+}
+
+impl From<X509Error> for TrustError {
+    fn from(err: X509Error) -> Self {
+        TrustError::X509(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor<'a> {
+    // DER of the subject Name of the root certificate
+    pub subject: &'a [u8],
+    pub key: PublicKey<'a>,
+    // false to trust a self signed server certificate directly
+    pub ca: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pin {
+    Certificate([u8; 32]),
+    Spki([u8; 32]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub version: u32,
+    pub anchors: usize,
+    pub pins: usize,
+}
+
+// a trust store that has been checked, see parse()
+#[derive(Debug, Clone, Copy)]
+pub struct TrustStore<'a> {
+    version: u32,
+    entries: &'a [u8],
+}
+
+enum Entry<'a> {
+    Anchor(Anchor<'a>),
+    Pin(Pin),
+}
+
+impl<'a> TrustStore<'a> {
+    // Checks the layout and the signature, after this reading the entries cannot fail. verify is
+    // given the SHA-256 of the signed part of the store and the signature and returns true if the
+    // signature is good.
+    pub fn parse(
+        data: &'a [u8],
+        verify: impl FnOnce(&[u8; 32], &[u8]) -> bool,
+    ) -> Result<Self, TrustError> {
+        if data.len() > MAX_LEN {
+            return Err(TrustError::TooLarge);
+        }
+
+        let mut reader = Reader::new(data);
+        if reader.bytes(MAGIC.len())? != MAGIC || reader.u8()? != FORMAT {
+            return Err(TrustError::InvalidFormat);
+        }
+
+        let version = reader.u32()?;
+        let len = reader.u16()? as usize;
+        let entries = reader.bytes(len)?;
+        let signature = reader.rest();
+        if signature.is_empty() || signature.len() > MAX_SIGNATURE_LEN {
+            return Err(TrustError::InvalidFormat);
+        }
+
+        let signed = &data[..HEADER_LEN + len];
+        if !verify(&hash(signed), signature) {
+            return Err(TrustError::BadSignature);
+        }
+
+        let store = Self { version, entries };
+
+        let mut summary = Summary {
+            version,
+            anchors: 0,
+            pins: 0,
+        };
+        let mut entries = store.entries;
+        while !entries.is_empty() {
+            match next_entry(&mut entries)? {
+                Some(Entry::Anchor(_)) => summary.anchors += 1,
+                Some(Entry::Pin(_)) => summary.pins += 1,
+                None => {}
+            }
+        }
+
+        if summary.anchors == 0 {
+            Err(TrustError::NoAnchors)
+        } else if summary.anchors > MAX_ANCHORS {
+            Err(TrustError::TooManyAnchors)
+        } else if summary.pins > MAX_PINS {
+            Err(TrustError::TooManyPins)
+        } else {
+            Ok(store)
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn anchors(&self) -> impl Iterator<Item = Anchor<'a>> {
+        self.entries().filter_map(|entry| match entry {
+            Entry::Anchor(anchor) => Some(anchor),
+            Entry::Pin(_) => None,
+        })
+    }
+
+    pub fn pins(&self) -> Pins {
+        let mut pins = Pins::default();
+        for entry in self.entries() {
+            if let Entry::Pin(pin) = entry {
+                pins.push(pin);
+            }
+        }
+
+        pins
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            version: self.version,
+            anchors: self.anchors().count(),
+            pins: self.pins().len(),
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = Entry<'a>> {
+        let mut entries = self.entries;
+        core::iter::from_fn(move || loop {
+            if entries.is_empty() {
+                return None;
+            }
+
+            // checked by parse()
+            if let Ok(Some(entry)) = next_entry(&mut entries) {
+                return Some(entry);
+            }
+        })
+    }
+}
+
+fn next_entry<'a>(entries: &mut &'a [u8]) -> Result<Option<Entry<'a>>, TrustError> {
+    let mut reader = Reader::new(entries);
+    let kind = reader.u8()?;
+    let len = reader.u16()? as usize;
+    let mut body = Reader::new(reader.bytes(len)?);
+    *entries = reader.rest();
+
+    let entry = match kind {
+        ANCHOR => {
+            let ca = body.u8()? & FLAG_CA != 0;
+            let len = body.u16()? as usize;
+            let subject = body.bytes(len)?;
+            let key = match body.u8()? {
+                KEY_RSA => {
+                    let len = body.u16()? as usize;
+                    let n = body.bytes(len)?;
+                    let len = body.u16()? as usize;
+                    let e = body.bytes(len)?;
+                    PublicKey::Rsa { n, e }
+                }
+                KEY_EC => {
+                    let curve = body.u8()?;
+                    let len = body.u16()? as usize;
+                    let q = body.bytes(len)?;
+                    PublicKey::Ec { curve, q }
+                }
+                _ => return Err(TrustError::InvalidFormat),
+            };
+
+            Some(Entry::Anchor(Anchor { subject, key, ca }))
+        }
+        PIN_CERTIFICATE => Some(Entry::Pin(Pin::Certificate(body.array()?))),
+        PIN_SPKI => Some(Entry::Pin(Pin::Spki(body.array()?))),
+        // from a newer version, skipped
+        _ => None,
+    };
+
+    Ok(entry)
+}
+
+// Writes a trust store without its signature to out and returns its length. The signature of
+// hash(&out[..len]) goes after it, see append_signature().
+pub fn encode(
+    anchors: &[Anchor],
+    pins: &[Pin],
+    version: u32,
+    out: &mut [u8],
+) -> Result<usize, TrustError> {
+    if anchors.is_empty() {
+        return Err(TrustError::NoAnchors);
+    } else if anchors.len() > MAX_ANCHORS {
+        return Err(TrustError::TooManyAnchors);
+    } else if pins.len() > MAX_PINS {
+        return Err(TrustError::TooManyPins);
+    }
+
+    let mut writer = Writer { out, len: 0 };
+    writer.bytes(MAGIC)?;
+    writer.u8(FORMAT)?;
+    writer.bytes(&version.to_le_bytes())?;
+    // the length of the entries is filled in at the end
+    writer.u16(0)?;
+
+    for anchor in anchors {
+        writer.u8(ANCHOR)?;
+        let len_at = writer.len;
+        writer.u16(0)?;
+
+        writer.u8(if anchor.ca { FLAG_CA } else { 0 })?;
+        writer.field(anchor.subject)?;
+        match anchor.key {
+            PublicKey::Rsa { n, e } => {
+                writer.u8(KEY_RSA)?;
+                writer.field(n)?;
+                writer.field(e)?;
+            }
+            PublicKey::Ec { curve, q } => {
+                writer.u8(KEY_EC)?;
+                writer.u8(curve)?;
+                writer.field(q)?;
+            }
+        }
+
+        let len = (writer.len - len_at - 2) as u16;
+        writer.out[len_at..len_at + 2].copy_from_slice(&len.to_le_bytes());
+    }
+
+    for pin in pins {
+        let (kind, hash) = match pin {
+            Pin::Certificate(hash) => (PIN_CERTIFICATE, hash),
+            Pin::Spki(hash) => (PIN_SPKI, hash),
+        };
+        writer.u8(kind)?;
+        writer.field(hash)?;
+    }
+
+    // leave room for the signature
+    if writer.len + MAX_SIGNATURE_LEN > MAX_LEN {
+        return Err(TrustError::TooLarge);
+    }
+
+    let len = (writer.len - HEADER_LEN) as u16;
+    writer.out[HEADER_LEN - 2..HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+    Ok(writer.len)
+}
+
+// the hash of a trust store from encode() to sign
+pub fn hash(store: &[u8]) -> [u8; 32] {
+    Sha256::digest(store).into()
+}
+
+// adds the signature to a trust store of len bytes from encode() and returns the new length
+pub fn append_signature(out: &mut [u8], len: usize, signature: &[u8]) -> Result<usize, TrustError> {
+    if signature.is_empty() || signature.len() > MAX_SIGNATURE_LEN {
+        return Err(TrustError::InvalidFormat);
+    }
+
+    let mut writer = Writer { out, len };
+    writer.bytes(signature)?;
+    Ok(writer.len)
+}
+
+struct Writer<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), TrustError> {
+        let end = self.len + bytes.len();
+        self.out
+            .get_mut(self.len..end)
+            .ok_or(TrustError::TooLarge)?
+            .copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) -> Result<(), TrustError> {
+        self.bytes(&[value])
+    }
+
+    fn u16(&mut self, value: u16) -> Result<(), TrustError> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    // length then the bytes
+    fn field(&mut self, bytes: &[u8]) -> Result<(), TrustError> {
+        if bytes.len() > u16::MAX as usize {
+            return Err(TrustError::TooLarge);
+        }
+
+        self.u16(bytes.len() as u16)?;
+        self.bytes(bytes)
+    }
+}
+
+// The pins for a connection, copied out of the trust store so that it does not need to stay
+// borrowed while the connection is up
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pins {
+    pins: [Option<Pin>; MAX_PINS],
+}
+
+impl Pins {
+    pub fn new(pins: &[Pin]) -> Self {
+        let mut new = Self::default();
+        for pin in pins {
+            new.push(*pin);
+        }
+
+        new
+    }
+
+    // pins beyond MAX_PINS are dropped, parse() and encode() do not allow that many
+    fn push(&mut self, pin: Pin) {
+        if let Some(slot) = self.pins.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(pin);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pins.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Checks the server certificate against the pins while BearSSL validates the chain
+// The server sends its own certificate first so only that one is hashed.
+pub struct PinCheck {
+    pins: Pins,
+    certificates: u32,
+    hasher: Sha256,
+}
+
+impl PinCheck {
+    pub fn new(pins: Pins) -> Self {
+        Self {
+            pins,
+            certificates: 0,
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn start_chain(&mut self) {
+        self.certificates = 0;
+        self.hasher = Sha256::new();
+    }
+
+    pub fn start_certificate(&mut self) {
+        self.certificates += 1;
+    }
+
+    pub fn append(&mut self, data: &[u8]) {
+        if self.certificates == 1 {
+            self.hasher.update(data);
+        }
+    }
+
+    // true if there are no pins or the server certificate or its key (as BearSSL reports it once
+    // the chain checks out) matches one of them
+    pub fn matches(&self, server_key: &PublicKey) -> bool {
+        if self.pins.is_empty() {
+            return true;
+        }
+
+        let certificate: [u8; 32] = self.hasher.clone().finalize().into();
+        let spki = x509::spki_hash(server_key).ok();
+        self.pins.pins.iter().flatten().any(|pin| match pin {
+            Pin::Certificate(hash) => *hash == certificate,
+            Pin::Spki(hash) => Some(*hash) == spki,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x509::{decode_pem, Certificate};
+
+    // Stand ins for ECDSA: the signature is the hash with the key xored in. Real signatures are
+    // checked by BearSSL in the firmware and tested with openssl in led-display-certgen.
+    const KEY: u8 = 7;
+
+    fn sign(out: &mut [u8], len: usize, key: u8) -> usize {
+        let signature = hash(&out[..len]).map(|byte| byte ^ key);
+        append_signature(out, len, &signature).unwrap()
+    }
+
+    fn verify(key: u8) -> impl FnOnce(&[u8; 32], &[u8]) -> bool {
+        move |hash, signature| hash.map(|byte| byte ^ key) == signature
+    }
+    const RSA_ROOT: &str = include_str!("../../certs/isrg-root-x1.pem");
+    const EC_ROOT: &str = include_str!("../../certs/isrg-root-x2.pem");
+
+    fn anchor(certificate: &Certificate<'_>) -> Anchor<'static> {
+        // leak so that the anchors can outlive the DER buffers in a test
+        let subject = Vec::leak(certificate.subject.to_vec());
+        let key = match certificate.key {
+            PublicKey::Rsa { n, e } => PublicKey::Rsa {
+                n: Vec::leak(n.to_vec()),
+                e: Vec::leak(e.to_vec()),
+            },
+            PublicKey::Ec { curve, q } => PublicKey::Ec {
+                curve,
+                q: Vec::leak(q.to_vec()),
+            },
+        };
+
+        Anchor {
+            subject,
+            key,
+            ca: certificate.ca,
+        }
+    }
+
+    fn roots() -> Vec<Anchor<'static>> {
+        [RSA_ROOT, EC_ROOT]
+            .iter()
+            .map(|pem| {
+                let mut buf = [0; 2048];
+                let (der, _) = decode_pem(pem, &mut buf).unwrap();
+                anchor(&Certificate::parse(der).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let anchors = roots();
+        let pins = [Pin::Spki([1; 32]), Pin::Certificate([2; 32])];
+        let mut buf = [0; MAX_LEN];
+        let len = encode(&anchors, &pins, 3, &mut buf).unwrap();
+        let len = sign(&mut buf, len, KEY);
+
+        let store = TrustStore::parse(&buf[..len], verify(KEY)).unwrap();
+        assert_eq!(store.version(), 3);
+        assert_eq!(store.anchors().collect::<Vec<_>>(), anchors);
+        assert_eq!(store.pins().len(), 2);
+        assert_eq!(
+            store.summary(),
+            Summary {
+                version: 3,
+                anchors: 2,
+                pins: 2
+            }
+        );
+    }
+
+    #[test]
+    fn signature_is_checked() {
+        let mut buf = [0; MAX_LEN];
+        let len = encode(&roots(), &[], 1, &mut buf).unwrap();
+        let len = sign(&mut buf, len, KEY);
+
+        assert_eq!(
+            TrustStore::parse(&buf[..len], verify(8)).unwrap_err(),
+            TrustError::BadSignature
+        );
+
+        // a single flipped bit anywhere, including the version
+        for index in [5, 20] {
+            let mut copy = buf;
+            copy[index] ^= 1;
+            assert_eq!(
+                TrustStore::parse(&copy[..len], verify(KEY)).unwrap_err(),
+                TrustError::BadSignature
+            );
+        }
+
+        // without a signature
+        let unsigned = encode(&roots(), &[], 1, &mut buf).unwrap();
+        assert_eq!(
+            TrustStore::parse(&buf[..unsigned], verify(KEY)).unwrap_err(),
+            TrustError::InvalidFormat
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let anchors = roots();
+        let mut buf = [0; MAX_LEN];
+        assert_eq!(
+            encode(&[], &[], 1, &mut buf).unwrap_err(),
+            TrustError::NoAnchors
+        );
+        assert_eq!(
+            encode(&anchors, &[Pin::Spki([0; 32]); 5], 1, &mut buf).unwrap_err(),
+            TrustError::TooManyPins
+        );
+        assert_eq!(
+            encode(&anchors, &[], 1, &mut buf[..100]).unwrap_err(),
+            TrustError::TooLarge
+        );
+        assert_eq!(
+            TrustStore::parse(b"TRST", verify(KEY)).unwrap_err(),
+            TrustError::InvalidFormat
+        );
+
+        let len = encode(&anchors, &[], 1, &mut buf).unwrap();
+        assert_eq!(
+            append_signature(&mut buf, len, &[0; MAX_SIGNATURE_LEN + 1]).unwrap_err(),
+            TrustError::InvalidFormat
+        );
+    }
+
+    #[test]
+    fn pins() {
+        let mut buf = [0; 2048];
+        let (der, _) = decode_pem(RSA_ROOT, &mut buf).unwrap();
+        let certificate = Certificate::parse(der).unwrap();
+        let spki: [u8; 32] = Sha256::digest(certificate.spki).into();
+
+        let check = |pins: &[Pin]| {
+            let mut check = PinCheck::new(Pins::new(pins));
+            check.start_chain();
+            check.start_certificate();
+            for chunk in der.chunks(100) {
+                check.append(chunk);
+            }
+            // an intermediate, not hashed
+            check.start_certificate();
+            check.append(&[1, 2, 3]);
+            check.matches(&certificate.key)
+        };
+
+        assert!(check(&[]));
+        assert!(check(&[Pin::Spki(spki)]));
+        assert!(check(&[
+            Pin::Spki([0; 32]),
+            Pin::Certificate(Certificate::hash(der))
+        ]));
+        assert!(!check(&[Pin::Spki([0; 32])]));
+        assert!(!check(&[Pin::Certificate(spki)]));
+    }
+}
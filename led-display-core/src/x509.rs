@@ -0,0 +1,515 @@
+use sha2::{Digest, Sha256};
+
+// Just enough X.509 to pick trust anchors out of root certificates and to pin server keys
+// Certificates are DER (see decode_pem for PEM) and nothing is copied: a Certificate borrows the
+// subject name and public key from the DER it was parsed from. Signatures and dates are left to
+// BearSSL, which does the actual certificate validation. The EC private key of a panel's client
+// certificate is read with ec_private_key.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum X509Error {
+    // not valid base64 or no BEGIN/END lines
+    InvalidPem,
+    // the DER is truncated or malformed
+    InvalidDer,
+    // a key other than RSA or EC on one of the curves BearSSL knows
+    UnsupportedKey,
+    // the output buffer is too small
+    TooLarge,
+}
+
+// TLS named curve ids, the same numbers BearSSL uses (BR_EC_secp256r1 etc.)
+pub const SECP256R1: u8 = 23;
+pub const SECP384R1: u8 = 24;
+pub const SECP521R1: u8 = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PublicKey<'a> {
+    // big endian modulus and exponent without leading zeros
+    Rsa { n: &'a [u8], e: &'a [u8] },
+    // uncompressed curve point
+    Ec { curve: u8, q: &'a [u8] },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Certificate<'a> {
+    // DER of the subject Name, what BearSSL matches issuers against
+    pub subject: &'a [u8],
+    // DER of the SubjectPublicKeyInfo, its SHA-256 is the usual public key pin
+    pub spki: &'a [u8],
+    pub key: PublicKey<'a>,
+    // the basic constraints extension says this is a certificate authority
+    pub ca: bool,
+}
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const NULL: u8 = 0x05;
+const OID: u8 = 0x06;
+const BOOLEAN: u8 = 0x01;
+const VERSION: u8 = 0xA0;
+const EXTENSIONS: u8 = 0xA3;
+
+// OID contents (without tag and length)
+const RSA_ENCRYPTION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+const EC_PUBLIC_KEY: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+const PRIME256V1: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const SECP384R1_OID: &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x22];
+const SECP521R1_OID: &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x23];
+const BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1D, 0x13];
+
+// reads DER one tag-length-value at a time
+struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    // the contents of the next value and the whole of it including tag and length
+    fn read_any(&mut self) -> Result<(u8, &'a [u8], &'a [u8]), X509Error> {
+        let data = self.data;
+        let tag = *data.first().ok_or(X509Error::InvalidDer)?;
+        let first = *data.get(1).ok_or(X509Error::InvalidDer)?;
+        let (len, header_len) = if first < 0x80 {
+            (first as usize, 2)
+        } else {
+            let count = (first & 0x7F) as usize;
+            if count == 0 || count > 3 {
+                return Err(X509Error::InvalidDer);
+            }
+
+            let bytes = data.get(2..2 + count).ok_or(X509Error::InvalidDer)?;
+            let len = bytes.iter().fold(0, |len, byte| len << 8 | *byte as usize);
+            (len, 2 + count)
+        };
+
+        let end = header_len + len;
+        let whole = data.get(..end).ok_or(X509Error::InvalidDer)?;
+        self.data = &data[end..];
+        Ok((tag, &whole[header_len..], whole))
+    }
+
+    fn read(&mut self, tag: u8) -> Result<&'a [u8], X509Error> {
+        match self.read_any()? {
+            (found, contents, _) if found == tag => Ok(contents),
+            _ => Err(X509Error::InvalidDer),
+        }
+    }
+
+    fn read_whole(&mut self, tag: u8) -> Result<&'a [u8], X509Error> {
+        match self.read_any()? {
+            (found, _, whole) if found == tag => Ok(whole),
+            _ => Err(X509Error::InvalidDer),
+        }
+    }
+
+    fn skip_if(&mut self, tag: u8) -> Result<(), X509Error> {
+        if self.peek_tag() == Some(tag) {
+            self.read_any()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Certificate<'a> {
+    pub fn parse(der: &'a [u8]) -> Result<Self, X509Error> {
+        let mut certificate = Der::new(Der::new(der).read(SEQUENCE)?);
+        let mut tbs = Der::new(certificate.read(SEQUENCE)?);
+
+        tbs.skip_if(VERSION)?;
+        tbs.read(INTEGER)?; // serial number
+        tbs.read(SEQUENCE)?; // signature algorithm
+        tbs.read(SEQUENCE)?; // issuer
+        tbs.read(SEQUENCE)?; // validity
+        let subject = tbs.read_whole(SEQUENCE)?;
+        let spki = tbs.read_whole(SEQUENCE)?;
+        let key = parse_spki(spki)?;
+
+        let mut ca = false;
+        while !tbs.is_empty() {
+            let (tag, contents, _) = tbs.read_any()?;
+            if tag == EXTENSIONS {
+                ca = is_ca(contents)?;
+            }
+        }
+
+        Ok(Self {
+            subject,
+            spki,
+            key,
+            ca,
+        })
+    }
+
+    // SHA-256 of the whole certificate, for pinning one particular certificate
+    pub fn hash(der: &[u8]) -> [u8; 32] {
+        Sha256::digest(der).into()
+    }
+}
+
+fn curve(oid: &[u8]) -> Result<u8, X509Error> {
+    match oid {
+        PRIME256V1 => Ok(SECP256R1),
+        SECP384R1_OID => Ok(SECP384R1),
+        SECP521R1_OID => Ok(SECP521R1),
+        _ => Err(X509Error::UnsupportedKey),
+    }
+}
+
+// the key in the DER of a SubjectPublicKeyInfo, e.g. from `openssl pkey -pubout`
+pub fn public_key(spki: &[u8]) -> Result<PublicKey<'_>, X509Error> {
+    parse_spki(spki)
+}
+
+fn parse_spki(spki: &[u8]) -> Result<PublicKey<'_>, X509Error> {
+    let mut spki = Der::new(Der::new(spki).read(SEQUENCE)?);
+    let mut algorithm = Der::new(spki.read(SEQUENCE)?);
+    let bits = spki.read(BIT_STRING)?;
+    // the number of unused bits in the last byte, always 0 for keys
+    let key = match bits.split_first() {
+        Some((0, key)) => key,
+        _ => return Err(X509Error::InvalidDer),
+    };
+
+    let oid = algorithm.read(OID)?;
+    if oid == RSA_ENCRYPTION {
+        let mut rsa = Der::new(Der::new(key).read(SEQUENCE)?);
+        let n = strip_zeros(rsa.read(INTEGER)?);
+        let e = strip_zeros(rsa.read(INTEGER)?);
+        Ok(PublicKey::Rsa { n, e })
+    } else if oid == EC_PUBLIC_KEY {
+        let curve = curve(algorithm.read(OID)?)?;
+        Ok(PublicKey::Ec { curve, q: key })
+    } else {
+        Err(X509Error::UnsupportedKey)
+    }
+}
+
+// the cA flag of the basic constraints extension
+fn is_ca(extensions: &[u8]) -> Result<bool, X509Error> {
+    let mut extensions = Der::new(Der::new(extensions).read(SEQUENCE)?);
+    while !extensions.is_empty() {
+        let mut extension = Der::new(extensions.read(SEQUENCE)?);
+        let oid = extension.read(OID)?;
+        extension.skip_if(BOOLEAN)?; // critical
+        let value = extension.read(OCTET_STRING)?;
+        if oid == BASIC_CONSTRAINTS {
+            let mut constraints = Der::new(Der::new(value).read(SEQUENCE)?);
+            if constraints.peek_tag() == Some(BOOLEAN) {
+                return Ok(constraints.read(BOOLEAN)? != [0]);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn strip_zeros(mut value: &[u8]) -> &[u8] {
+    while let Some((0, rest)) = value.split_first() {
+        value = rest;
+    }
+
+    value
+}
+
+// Hashes the DER of the SubjectPublicKeyInfo for a key, which is what BearSSL hands back once it
+// has checked a server certificate. It matches the SHA-256 of Certificate::spki and so the pins
+// from `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | sha256sum`.
+pub fn spki_hash(key: &PublicKey) -> Result<[u8; 32], X509Error> {
+    let mut hasher = Sha256::new();
+    match *key {
+        PublicKey::Rsa { n, e } => {
+            let n = strip_zeros(n);
+            let e = strip_zeros(e);
+            let integers = integer_len(n) + integer_len(e);
+            let bits = 1 + tlv_len(integers);
+            let algorithm = tlv_len(RSA_ENCRYPTION.len()) + tlv_len(0);
+
+            header(&mut hasher, SEQUENCE, tlv_len(algorithm) + tlv_len(bits));
+            header(&mut hasher, SEQUENCE, algorithm);
+            header(&mut hasher, OID, RSA_ENCRYPTION.len());
+            hasher.update(RSA_ENCRYPTION);
+            header(&mut hasher, NULL, 0);
+            header(&mut hasher, BIT_STRING, bits);
+            hasher.update([0]);
+            header(&mut hasher, SEQUENCE, integers);
+            integer(&mut hasher, n);
+            integer(&mut hasher, e);
+        }
+        PublicKey::Ec { curve, q } => {
+            let curve = match curve {
+                SECP256R1 => PRIME256V1,
+                SECP384R1 => SECP384R1_OID,
+                SECP521R1 => SECP521R1_OID,
+                _ => return Err(X509Error::UnsupportedKey),
+            };
+            let bits = 1 + q.len();
+            let algorithm = tlv_len(EC_PUBLIC_KEY.len()) + tlv_len(curve.len());
+
+            header(&mut hasher, SEQUENCE, tlv_len(algorithm) + tlv_len(bits));
+            header(&mut hasher, SEQUENCE, algorithm);
+            header(&mut hasher, OID, EC_PUBLIC_KEY.len());
+            hasher.update(EC_PUBLIC_KEY);
+            header(&mut hasher, OID, curve.len());
+            hasher.update(curve);
+            header(&mut hasher, BIT_STRING, bits);
+            hasher.update([0]);
+            hasher.update(q);
+        }
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+// The curve and big endian private key of an EC private key, either SEC 1 (BEGIN EC PRIVATE KEY)
+// or PKCS #8 (BEGIN PRIVATE KEY) DER
+pub fn ec_private_key(der: &[u8]) -> Result<(u8, &[u8]), X509Error> {
+    let mut key = Der::new(Der::new(der).read(SEQUENCE)?);
+    match key.read(INTEGER)? {
+        // PKCS #8, the curve is in the algorithm and the SEC 1 key inside may leave it out
+        [0] => {
+            let mut algorithm = Der::new(key.read(SEQUENCE)?);
+            if algorithm.read(OID)? != EC_PUBLIC_KEY {
+                return Err(X509Error::UnsupportedKey);
+            }
+
+            let curve = curve(algorithm.read(OID)?)?;
+            match sec1_private_key(key.read(OCTET_STRING)?)? {
+                (Some(inner), _) if inner != curve => Err(X509Error::InvalidDer),
+                (_, private) => Ok((curve, private)),
+            }
+        }
+        [1] => match sec1_private_key(der)? {
+            (Some(curve), private) => Ok((curve, private)),
+            (None, _) => Err(X509Error::UnsupportedKey),
+        },
+        _ => Err(X509Error::InvalidDer),
+    }
+}
+
+// ECPrivateKey from SEC 1, the curve (parameters) is optional
+fn sec1_private_key(der: &[u8]) -> Result<(Option<u8>, &[u8]), X509Error> {
+    const PARAMETERS: u8 = 0xA0;
+
+    let mut key = Der::new(Der::new(der).read(SEQUENCE)?);
+    if key.read(INTEGER)? != [1] {
+        return Err(X509Error::InvalidDer);
+    }
+
+    let private = key.read(OCTET_STRING)?;
+    let curve = match key.peek_tag() {
+        Some(PARAMETERS) => Some(curve(Der::new(key.read(PARAMETERS)?).read(OID)?)?),
+        _ => None,
+    };
+
+    Ok((curve, private))
+}
+
+// length of a value with its tag and length
+fn tlv_len(len: usize) -> usize {
+    1 + match len {
+        0..=0x7F => 1,
+        0x80..=0xFF => 2,
+        0x100..=0xFFFF => 3,
+        _ => 4,
+    } + len
+}
+
+// a positive integer needs a leading zero when its top bit is set
+fn integer_len(value: &[u8]) -> usize {
+    tlv_len(value.len() + needs_zero(value) as usize)
+}
+
+fn needs_zero(value: &[u8]) -> bool {
+    !matches!(value.first(), Some(first) if first & 0x80 == 0)
+}
+
+fn integer(hasher: &mut Sha256, value: &[u8]) {
+    header(hasher, INTEGER, value.len() + needs_zero(value) as usize);
+    if needs_zero(value) {
+        hasher.update([0]);
+    }
+
+    hasher.update(value);
+}
+
+fn header(hasher: &mut Sha256, tag: u8, len: usize) {
+    hasher.update([tag]);
+    match len {
+        0..=0x7F => hasher.update([len as u8]),
+        0x80..=0xFF => hasher.update([0x81, len as u8]),
+        0x100..=0xFFFF => hasher.update([0x82, (len >> 8) as u8, len as u8]),
+        _ => hasher.update([0x83, (len >> 16) as u8, (len >> 8) as u8, len as u8]),
+    }
+}
+
+// Decodes the first certificate in PEM text into out, returning the DER and the text after it
+pub fn decode_pem<'a, 't>(
+    text: &'t str,
+    out: &'a mut [u8],
+) -> Result<(&'a [u8], &'t str), X509Error> {
+    decode_pem_block(text, "CERTIFICATE", out)
+}
+
+// decode_pem for anything else, the label is what follows BEGIN (e.g. "EC PRIVATE KEY")
+pub fn decode_pem_block<'a, 't>(
+    text: &'t str,
+    label: &str,
+    out: &'a mut [u8],
+) -> Result<(&'a [u8], &'t str), X509Error> {
+    const DASHES: &str = "-----";
+
+    let start = text
+        .match_indices("-----BEGIN ")
+        .find_map(|(index, begin)| {
+            let rest = text[index + begin.len()..].strip_prefix(label)?;
+            let body = rest.strip_prefix(DASHES)?;
+            Some(text.len() - body.len())
+        })
+        .ok_or(X509Error::InvalidPem)?;
+    let end = start
+        + text[start..]
+            .find("-----END ")
+            .ok_or(X509Error::InvalidPem)?;
+    let after = end + text[end..].find('\n').unwrap_or(text.len() - end);
+
+    // base64, 4 characters for every 3 bytes
+    let mut len = 0;
+    let mut bits = 0_u32;
+    let mut count = 0;
+    for c in text[start..end].bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return Err(X509Error::InvalidPem),
+        };
+
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            *out.get_mut(len).ok_or(X509Error::TooLarge)? = (bits >> count) as u8;
+            len += 1;
+        }
+    }
+
+    Ok((&out[..len], &text[after..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSA_ROOT: &str = include_str!("../../certs/isrg-root-x1.pem");
+    const EC_ROOT: &str = include_str!("../../certs/isrg-root-x2.pem");
+    const PANEL_KEY: &str = include_str!("../../certs/test/panel-key.pem");
+    const PANEL_KEY_PKCS8: &str = include_str!("../../certs/test/panel-key-pkcs8.pem");
+
+    fn with_der<T>(pem: &str, f: impl FnOnce(&[u8]) -> T) -> T {
+        let mut buf = [0; 2048];
+        let (der, rest) = decode_pem(pem, &mut buf).unwrap();
+        assert!(rest.trim().is_empty());
+        f(der)
+    }
+
+    #[test]
+    fn rsa_root() {
+        with_der(RSA_ROOT, |der| {
+            let certificate = Certificate::parse(der).unwrap();
+            assert!(certificate.ca);
+            match certificate.key {
+                PublicKey::Rsa { n, e } => {
+                    assert_eq!(n.len(), 512);
+                    assert_eq!(e, [0x01, 0x00, 0x01]);
+                }
+                key => panic!("{:?}", key),
+            }
+
+            // the common name is in the subject
+            let name = b"ISRG Root X1";
+            assert!(certificate.subject.windows(name.len()).any(|w| w == name));
+        });
+    }
+
+    #[test]
+    fn ec_root() {
+        with_der(EC_ROOT, |der| {
+            let certificate = Certificate::parse(der).unwrap();
+            assert!(certificate.ca);
+            match certificate.key {
+                PublicKey::Ec { curve, q } => {
+                    assert_eq!(curve, SECP384R1);
+                    assert_eq!(q.len(), 97);
+                    assert_eq!(q[0], 0x04);
+                }
+                key => panic!("{:?}", key),
+            }
+        });
+    }
+
+    #[test]
+    fn spki_hash_matches_certificate() {
+        for pem in [RSA_ROOT, EC_ROOT] {
+            with_der(pem, |der| {
+                let certificate = Certificate::parse(der).unwrap();
+                let expected: [u8; 32] = Sha256::digest(certificate.spki).into();
+                assert_eq!(spki_hash(&certificate.key).unwrap(), expected);
+            });
+        }
+    }
+
+    #[test]
+    fn ec_private_keys() {
+        let mut sec1 = [0; 256];
+        let (sec1, _) = decode_pem_block(PANEL_KEY, "EC PRIVATE KEY", &mut sec1).unwrap();
+        let mut pkcs8 = [0; 256];
+        let (pkcs8, _) = decode_pem_block(PANEL_KEY_PKCS8, "PRIVATE KEY", &mut pkcs8).unwrap();
+
+        let (curve, key) = ec_private_key(sec1).unwrap();
+        assert_eq!(curve, SECP256R1);
+        assert_eq!(key.len(), 32);
+        assert_eq!(ec_private_key(pkcs8).unwrap(), (curve, key));
+
+        // a certificate is not a key
+        with_der(RSA_ROOT, |der| assert!(ec_private_key(der).is_err()));
+    }
+
+    #[test]
+    fn invalid_input() {
+        let mut buf = [0; 16];
+        assert_eq!(
+            decode_pem("hello", &mut buf).unwrap_err(),
+            X509Error::InvalidPem
+        );
+        assert_eq!(
+            decode_pem(RSA_ROOT, &mut buf).unwrap_err(),
+            X509Error::TooLarge
+        );
+
+        with_der(RSA_ROOT, |der| {
+            assert_eq!(
+                Certificate::parse(&der[..100]).unwrap_err(),
+                X509Error::InvalidDer
+            );
+        });
+    }
+}
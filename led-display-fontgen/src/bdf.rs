@@ -0,0 +1,168 @@
+//! Reader for the subset of the Glyph Bitmap Distribution Format that pixel fonts use
+
+use crate::{read_to_string, Error, Font, Glyph, HEIGHT};
+use std::path::Path;
+
+struct Parser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn numbers(&self, args: &[&str], count: usize) -> Result<Vec<i32>, Error> {
+        if args.len() < count {
+            return Err(self.error(format!("expected {} numbers", count)));
+        }
+
+        args[..count]
+            .iter()
+            .map(|arg| {
+                arg.parse()
+                    .map_err(|_| self.error(format!("invalid number '{}'", arg)))
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Char {
+    code: Option<u32>,
+    advance: Option<i32>,
+    // width, height, x offset, y offset
+    bbx: Option<[i32; 4]>,
+    rows: Vec<u32>,
+}
+
+pub fn load(path: &Path) -> Result<Font, Error> {
+    let text = read_to_string(path)?;
+    let mut parser = Parser { path, line: 0 };
+    let mut font = Font::default();
+    let mut ascent = HEIGHT as i32;
+    let mut current: Option<Char> = None;
+    let mut in_bitmap = false;
+
+    for (index, line) in text.lines().enumerate() {
+        parser.line = index + 1;
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = words.collect();
+
+        if in_bitmap {
+            let c = current.as_mut().expect("bitmap outside of a character");
+            if keyword == "ENDCHAR" {
+                in_bitmap = false;
+                let c = current.take().unwrap();
+                if let Some(glyph) = to_glyph(&parser, c, ascent)? {
+                    font.glyphs.push(glyph);
+                }
+            } else {
+                if keyword.len() > 8 {
+                    return Err(parser.error("bitmap rows wider than 32 pixels are not supported"));
+                }
+                let row = u32::from_str_radix(keyword, 16)
+                    .map_err(|_| parser.error(format!("invalid bitmap row '{}'", keyword)))?;
+                // rows are padded to whole bytes with the leftmost pixel in the top bit
+                c.rows.push(row << (32 - 4 * keyword.len() as u32));
+            }
+            continue;
+        }
+
+        match keyword {
+            "FONT_ASCENT" => ascent = parser.numbers(&args, 1)?[0],
+            "STARTCHAR" => current = Some(Char::default()),
+            "ENCODING" => {
+                let c = current
+                    .as_mut()
+                    .ok_or_else(|| parser.error("ENCODING outside of a character"))?;
+                // -1 means the glyph has no standard encoding so there is no way to type it
+                let code = parser.numbers(&args, 1)?[0];
+                c.code = if code >= 0 { Some(code as u32) } else { None };
+            }
+            "DWIDTH" => {
+                let c = current
+                    .as_mut()
+                    .ok_or_else(|| parser.error("DWIDTH outside of a character"))?;
+                c.advance = Some(parser.numbers(&args, 1)?[0]);
+            }
+            "BBX" => {
+                let c = current
+                    .as_mut()
+                    .ok_or_else(|| parser.error("BBX outside of a character"))?;
+                let n = parser.numbers(&args, 4)?;
+                c.bbx = Some([n[0], n[1], n[2], n[3]]);
+            }
+            "BITMAP" => {
+                if current.is_none() {
+                    return Err(parser.error("BITMAP outside of a character"));
+                }
+                in_bitmap = true;
+            }
+            _ => {}
+        }
+    }
+
+    if current.is_some() {
+        return Err(parser.error("missing ENDCHAR"));
+    }
+
+    Ok(font)
+}
+
+fn to_glyph(parser: &Parser, c: Char, ascent: i32) -> Result<Option<Glyph>, Error> {
+    let code = match c.code {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+
+    let [width, height, x_offset, y_offset] = c
+        .bbx
+        .ok_or_else(|| parser.error("character without a BBX"))?;
+    if c.rows.len() != height as usize {
+        return Err(parser.error(format!(
+            "expected {} bitmap rows, found {}",
+            height,
+            c.rows.len()
+        )));
+    }
+
+    // the bounding box is relative to the baseline, the panel counts rows from the top
+    let top = ascent - y_offset - height;
+    if top < 0 || top + height > HEIGHT as i32 {
+        return Err(parser.error(format!(
+            "glyph U+{:04X} does not fit in {} rows",
+            code, HEIGHT
+        )));
+    }
+
+    let x_offset = x_offset.max(0) as usize;
+    let mut columns = vec![0u8; x_offset + width as usize];
+    for (y, row) in c.rows.iter().enumerate() {
+        for x in 0..width as usize {
+            if row & (0x8000_0000 >> x) != 0 {
+                columns[x_offset + x] |= 1 << (top as usize + y);
+            }
+        }
+    }
+
+    let advance = c.advance.unwrap_or(columns.len() as i32 + 1);
+    if !(0..=u8::MAX as i32).contains(&advance) {
+        return Err(parser.error(format!("invalid advance {}", advance)));
+    }
+
+    Ok(Some(Glyph {
+        code,
+        columns,
+        advance: advance as u8,
+    }))
+}
@@ -0,0 +1,95 @@
+//! Reader for fonts drawn as PNG images
+//!
+//! The image is a single row of equally sized cells, one per character listed in the `.txt` file
+//! of the same name. Dark, opaque pixels are lit. Blank columns either side of a glyph are
+//! trimmed so that the font comes out proportional, an empty cell becomes a space half a cell wide.
+
+use crate::{read_to_string, Error, Font, Glyph, HEIGHT};
+use std::{fs::File, path::Path};
+
+pub fn load(path: &Path) -> Result<Font, Error> {
+    let chars_path = path.with_extension("txt");
+    let chars: Vec<char> = read_to_string(&chars_path)?
+        .chars()
+        .filter(|c| *c != '\n' && *c != '\r')
+        .collect();
+
+    let png_error = |err| Error::Png(path.to_path_buf(), err);
+    let file = File::open(path).map_err(|err| Error::Io(path.to_path_buf(), err))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(png_error)?;
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    if height > HEIGHT {
+        return Err(Error::Font(format!(
+            "{}: image is {} pixels high, the panel only has {} rows",
+            path.display(),
+            height,
+            HEIGHT
+        )));
+    }
+
+    if chars.is_empty() || !width.is_multiple_of(chars.len()) {
+        return Err(Error::Font(format!(
+            "{}: image width {} is not a multiple of the {} characters in {}",
+            path.display(),
+            width,
+            chars.len(),
+            chars_path.display()
+        )));
+    }
+
+    let samples = info.color_type.samples();
+    let lit = |x: usize, y: usize| {
+        let pixel = &buf[y * info.line_size + x * samples..][..samples];
+        let (luma, alpha) = match pixel {
+            [l] => (*l as u32, 255),
+            [l, a] => (*l as u32, *a),
+            [r, g, b] => ((*r as u32 + *g as u32 + *b as u32) / 3, 255),
+            [r, g, b, a] => ((*r as u32 + *g as u32 + *b as u32) / 3, *a),
+            _ => (255, 0),
+        };
+        alpha >= 128 && luma < 128
+    };
+
+    let cell_width = width / chars.len();
+    let mut font = Font::default();
+    for (index, c) in chars.iter().enumerate() {
+        let mut columns: Vec<u8> = (0..cell_width)
+            .map(|x| {
+                (0..height).fold(0, |bits, y| {
+                    if lit(index * cell_width + x, y) {
+                        bits | 1 << y
+                    } else {
+                        bits
+                    }
+                })
+            })
+            .collect();
+
+        let first = columns.iter().position(|bits| *bits != 0);
+        let last = columns.iter().rposition(|bits| *bits != 0);
+        let advance = match (first, last) {
+            (Some(first), Some(last)) => {
+                columns = columns[first..=last].to_vec();
+                columns.len() + 1
+            }
+            _ => {
+                columns.clear();
+                (cell_width / 2).max(1)
+            }
+        };
+
+        font.glyphs.push(Glyph {
+            code: *c as u32,
+            columns,
+            advance: advance.min(u8::MAX as usize) as u8,
+        });
+    }
+
+    Ok(font)
+}
@@ -0,0 +1,44 @@
+//! Reader for kerning files
+//!
+//! Each line holds a pair of characters followed by the number of columns to move the second one
+//! by, e.g. `LT -1`. Blank lines and lines starting with `#` are ignored.
+
+use crate::{read_to_string, Error, Kern};
+use std::path::Path;
+
+pub fn load(path: &Path) -> Result<Vec<Kern>, Error> {
+    let text = read_to_string(path)?;
+    let mut kerning = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: &str| Error::Parse {
+            path: path.to_path_buf(),
+            line: index + 1,
+            message: message.to_string(),
+        };
+
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut chars = line.chars();
+        let (left, right) = match (chars.next(), chars.next()) {
+            (Some(left), Some(right)) => (left, right),
+            _ => return Err(error("expected a pair of characters")),
+        };
+
+        let adjust = chars
+            .as_str()
+            .trim()
+            .parse()
+            .map_err(|_| error("expected the number of columns to adjust by"))?;
+
+        kerning.push(Kern {
+            left: left as u32,
+            right: right as u32,
+            adjust,
+        });
+    }
+
+    Ok(kerning)
+}
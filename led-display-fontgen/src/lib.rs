@@ -0,0 +1,282 @@
+//! Converts fonts into the compact glyph table used by the led panel firmware.
+//!
+//! Glyphs are stored as columns of 8 bits (bit 0 at the top) because that is how the panel
+//! draws text. The generated Rust source defines `GLYPHS` (sorted by code point so that the
+//! firmware can binary search it), `COLUMNS` (the bitmaps of all glyphs back to back) and
+//! `KERNING` (sorted by pair). The `Glyph` and `Kern` types are defined by the firmware.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+mod bdf;
+mod image;
+mod kerning;
+mod table;
+
+/// Height of the panel in pixels, glyphs must fit within it
+pub const HEIGHT: usize = 8;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Png(PathBuf, png::DecodingError),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Font(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            Error::Png(path, err) => write!(f, "{}: {}", path.display(), err),
+            Error::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            Error::Font(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub code: u32,
+    /// one byte per column, bit 0 is the top row
+    pub columns: Vec<u8>,
+    /// distance to the start of the next glyph, usually the width plus one blank column
+    pub advance: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Kern {
+    pub left: u32,
+    pub right: u32,
+    /// columns to move the right glyph by, negative moves it closer
+    pub adjust: i8,
+}
+
+#[derive(Debug, Default)]
+pub struct Font {
+    pub glyphs: Vec<Glyph>,
+    pub kerning: Vec<Kern>,
+}
+
+/// Loads a `.bdf` or `.png` font along with the kerning pairs in a `.kern` file of the same name
+/// if there is one.
+///
+/// A PNG font is a single row of equally sized cells, one per character, and needs a `.txt` file
+/// of the same name listing the characters in the order they appear. Dark pixels are lit.
+pub fn load(path: &Path) -> Result<Font, Error> {
+    let mut font = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("bdf") => bdf::load(path)?,
+        Some("png") => image::load(path)?,
+        _ => {
+            return Err(Error::Font(format!(
+                "{}: expected a .bdf or .png font",
+                path.display()
+            )))
+        }
+    };
+
+    let kern_path = path.with_extension("kern");
+    if kern_path.exists() {
+        font.kerning = kerning::load(&kern_path)?;
+    }
+
+    Ok(font)
+}
+
+/// Loads a font and writes its glyph table as Rust source to `out`
+pub fn generate(path: &Path, out: &Path) -> Result<(), Error> {
+    let font = load(path)?;
+    let source = table::render(&font, path)?;
+    fs::write(out, source).map_err(|err| Error::Io(out.to_path_buf(), err))
+}
+
+fn read_to_string(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|err| Error::Io(path.to_path_buf(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a font with '?', 'A' and 'V' plus a glyph without an encoding, which is skipped
+    const BDF: &str = "STARTFONT 2.1
+FONT_ASCENT 8
+STARTCHAR question
+ENCODING 63
+DWIDTH 2 0
+BBX 1 1 0 7
+BITMAP
+80
+ENDCHAR
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 2 0 0
+BITMAP
+E0
+A0
+ENDCHAR
+STARTCHAR V
+ENCODING 86
+BBX 2 1 1 3
+BITMAP
+C0
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+
+    // pairs with characters that are not in the font are left out of the table
+    const KERN: &str = "# pairs\nAV -1\nA? 2\nAZ 5\n";
+
+    // writes the files to a directory of their own and returns the path of the one named `font`
+    fn write_files(test: &str, files: &[(&str, &[u8])], font: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fontgen-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+
+        dir.join(font)
+    }
+
+    fn render(test: &str, files: &[(&str, &[u8])], font: &str) -> Result<String, Error> {
+        let path = write_files(test, files, font);
+        let result = load(&path).and_then(|font| table::render(&font, &path));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        result
+    }
+
+    fn bdf_with(glyph: &str) -> String {
+        BDF.replace("ENDFONT", &format!("{}ENDFONT", glyph))
+    }
+
+    fn png(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(pixels).unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    #[test]
+    fn bdf_table() {
+        let files: [(&str, &[u8]); 2] =
+            [("font.bdf", BDF.as_bytes()), ("font.kern", KERN.as_bytes())];
+        let table = render("bdf", &files, "font.bdf").unwrap();
+
+        // 'A' sits on the bottom two rows, 'V' is moved right by its x offset and has the
+        // default advance of its width plus one
+        assert!(table.contains("static GLYPHS: [Glyph; 3] = [\n    Glyph { code: 0x003F, offset: 0, width: 1, advance: 2 },\n    Glyph { code: 0x0041, offset: 1, width: 3, advance: 4 },\n    Glyph { code: 0x0056, offset: 4, width: 3, advance: 4 },\n];"));
+        assert!(table.contains(
+            "static COLUMNS: [u8; 7] = [\n    0x01, 0xC0, 0x40, 0xC0, 0x00, 0x10, 0x10,\n];"
+        ));
+        assert!(table.contains("static KERNING: [Kern; 2] = [\n    Kern { left: 0x0041, right: 0x003F, adjust: 2 },\n    Kern { left: 0x0041, right: 0x0056, adjust: -1 },\n];"));
+    }
+
+    #[test]
+    fn bdf_rejects_what_does_not_fit() {
+        // a descender below the bottom row
+        let low = bdf_with("STARTCHAR g\nENCODING 103\nBBX 1 2 0 -1\nBITMAP\n80\n80\nENDCHAR\n");
+        match render("low", &[("font.bdf", low.as_bytes())], "font.bdf") {
+            Err(Error::Parse { line, message, .. }) => {
+                assert_eq!(line, 36);
+                assert_eq!(message, "glyph U+0067 does not fit in 8 rows");
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let wide =
+            bdf_with("STARTCHAR w\nENCODING 119\nBBX 33 1 0 0\nBITMAP\n123456789\nENDCHAR\n");
+        assert!(matches!(
+            render("wide", &[("font.bdf", wide.as_bytes())], "font.bdf"),
+            Err(Error::Parse { line: 34, .. })
+        ));
+
+        // the table only has room for the basic multilingual plane
+        let emoji = bdf_with("STARTCHAR grin\nENCODING 128512\nBBX 1 1 0 0\nBITMAP\n80\nENDCHAR\n");
+        assert!(matches!(
+            render("emoji", &[("font.bdf", emoji.as_bytes())], "font.bdf"),
+            Err(Error::Font(_))
+        ));
+
+        let no_replacement = BDF.replace("ENCODING 63", "ENCODING 64");
+        assert!(matches!(
+            render(
+                "no-replacement",
+                &[("font.bdf", no_replacement.as_bytes())],
+                "font.bdf"
+            ),
+            Err(Error::Font(_))
+        ));
+    }
+
+    #[test]
+    fn png_table() {
+        // three cells of 3 by 8: a dot at the top of the second column, nothing, and a bar down the
+        // last column
+        let mut pixels = vec![255; 9 * 8];
+        pixels[1] = 0;
+        for y in 0..8 {
+            pixels[y * 9 + 8] = 0;
+        }
+
+        let image = png(9, 8, &pixels);
+        let files: [(&str, &[u8]); 2] = [("font.png", &image), ("font.txt", b"? I\n")];
+        let table = render("png", &files, "font.png").unwrap();
+
+        // blank columns are trimmed and the empty cell is a space half a cell wide
+        assert!(table.contains("static GLYPHS: [Glyph; 3] = [\n    Glyph { code: 0x0020, offset: 0, width: 0, advance: 1 },\n    Glyph { code: 0x003F, offset: 0, width: 1, advance: 2 },\n    Glyph { code: 0x0049, offset: 1, width: 1, advance: 2 },\n];"));
+        assert!(table.contains("static COLUMNS: [u8; 2] = [\n    0x01, 0xFF,\n];"));
+        assert!(table.contains("static KERNING: [Kern; 0] = [\n];"));
+    }
+
+    #[test]
+    fn png_rejects_what_does_not_fit() {
+        let tall = png(3, 9, &[255; 27]);
+        assert!(matches!(
+            render(
+                "tall",
+                &[("font.png", &tall), ("font.txt", b"?")],
+                "font.png"
+            ),
+            Err(Error::Font(_))
+        ));
+
+        // 8 pixels cannot be split into 3 cells
+        let uneven = png(8, 8, &[255; 64]);
+        assert!(matches!(
+            render(
+                "uneven",
+                &[("font.png", &uneven), ("font.txt", b"?ab")],
+                "font.png"
+            ),
+            Err(Error::Font(_))
+        ));
+
+        assert!(matches!(
+            render("extension", &[("font.gif", b"GIF89a")], "font.gif"),
+            Err(Error::Font(_))
+        ));
+    }
+}
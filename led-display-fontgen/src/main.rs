@@ -0,0 +1,19 @@
+use std::{env, path::Path, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <font.bdf|font.png> <out.rs>", args[0]);
+        process::exit(2);
+    }
+
+    let font = Path::new(&args[1]);
+    let out = Path::new(&args[2]);
+    match led_display_fontgen::generate(font, out) {
+        Ok(()) => println!("wrote {}", out.display()),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
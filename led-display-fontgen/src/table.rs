@@ -0,0 +1,92 @@
+//! Writes a font out as Rust source for the firmware to include
+
+use crate::{Error, Font};
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+// glyph codes and bitmap offsets are stored as u16 to keep the table small
+const MAX_CODE: u32 = u16::MAX as u32;
+const MAX_COLUMNS: usize = u16::MAX as usize;
+
+pub fn render(font: &Font, path: &Path) -> Result<String, Error> {
+    // later glyphs replace earlier ones with the same code, the map keeps them sorted
+    let mut glyphs = BTreeMap::new();
+    for glyph in &font.glyphs {
+        if glyph.code > MAX_CODE {
+            return Err(Error::Font(format!(
+                "U+{:X} is outside the basic multilingual plane",
+                glyph.code
+            )));
+        }
+        glyphs.insert(glyph.code, glyph);
+    }
+
+    if !glyphs.contains_key(&('?' as u32)) {
+        return Err(Error::Font(
+            "the font needs a '?' glyph to draw unknown characters with".to_string(),
+        ));
+    }
+
+    let mut kerning = BTreeMap::new();
+    for kern in &font.kerning {
+        if glyphs.contains_key(&kern.left) && glyphs.contains_key(&kern.right) {
+            kerning.insert((kern.left, kern.right), kern.adjust);
+        }
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut out = String::new();
+    let mut columns = Vec::new();
+
+    // writing to a String cannot fail
+    writeln!(
+        out,
+        "// Generated by led-display-fontgen from {}, do not edit",
+        name
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "static GLYPHS: [Glyph; {}] = [", glyphs.len()).unwrap();
+    for glyph in glyphs.values() {
+        writeln!(
+            out,
+            "    Glyph {{ code: 0x{:04X}, offset: {}, width: {}, advance: {} }},",
+            glyph.code,
+            columns.len(),
+            glyph.columns.len(),
+            glyph.advance
+        )
+        .unwrap();
+        columns.extend_from_slice(&glyph.columns);
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    if columns.len() > MAX_COLUMNS {
+        return Err(Error::Font(format!(
+            "the font has {} columns of bitmaps, at most {} fit in the table",
+            columns.len(),
+            MAX_COLUMNS
+        )));
+    }
+
+    writeln!(out, "static COLUMNS: [u8; {}] = [", columns.len()).unwrap();
+    for chunk in columns.chunks(16) {
+        let bytes: Vec<String> = chunk.iter().map(|bits| format!("0x{:02X}", bits)).collect();
+        writeln!(out, "    {},", bytes.join(", ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "static KERNING: [Kern; {}] = [", kerning.len()).unwrap();
+    for ((left, right), adjust) in &kerning {
+        writeln!(
+            out,
+            "    Kern {{ left: 0x{:04X}, right: 0x{:04X}, adjust: {} }},",
+            left, right, adjust
+        )
+        .unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    Ok(out)
+}
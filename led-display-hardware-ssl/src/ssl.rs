@@ -1,380 +0,0 @@
-use cty::size_t;
-use embedded_websocket::framer::Stream;
-
-use crate::{bearssl::*, tcp::TcpStream, time::UNIX_TIME};
-use core::{marker::PhantomPinned, mem::MaybeUninit};
-
-// Notes on safety and use of unsafe rust in this module.
-// This module uses the statically linked BearSsl library to perform all the TLS operations
-// Any FFI call is unsafe. Additionally, for objects like br_ssl_client_context and br_sslio_context
-// we set asside the memory on the stack but we let the BearSsl library set the contents of that memory up.
-// Since we take pointers to these objects and pass those pointers to the BearSsl library it is important
-// to make sure that the the instances that those pointers point to do not move. This is why we call new()
-// and then init() in a separate step once the instances are safely moved inside the SslStream struct.
-// The largest object IO_BUF is statically created because BearSsl uses it internally. Since the TLS 1.2
-// protocol supports client buffer size negotiation we can get away with using a buffer much smaller than
-// the 64KB you would normally need if this was a TLS server.
-
-// Note about the chain of trust
-// There are only two root certificates in here which should be sufficient for any certificate signed by
-// the free certificate authority Lets Encrypt so make sure your website uses that certificate authority.
-// Alternatively add the trust anchor of your CA below.
-
-// Note on cryptography
-// The biggest issue with the code below is that the entropy we inject into BearSsl is hardcoded. We should
-// generate high quality entropy and use that instead but we don't. This weakens the encryption.
-
-#[derive(Debug)]
-pub enum SslError {
-    // See BR_ERR_XXXXXX in bearssl.rs for error meaning
-    // BR_ERR_OK = 0
-    BearSslWriteErr(i32),
-    BearSslReadErr(i32),
-}
-
-// LetsEncrypt trust anchor ISRG Root X1 exp. 04 Jun 2035
-static mut TA0_DN: [u8; 81] = [
-    0x30, 0x4F, 0x31, 0x0B, 0x30, 0x09, 0x06, 0x03, 0x55, 0x04, 0x06, 0x13, 0x02, 0x55, 0x53, 0x31,
-    0x29, 0x30, 0x27, 0x06, 0x03, 0x55, 0x04, 0x0A, 0x13, 0x20, 0x49, 0x6E, 0x74, 0x65, 0x72, 0x6E,
-    0x65, 0x74, 0x20, 0x53, 0x65, 0x63, 0x75, 0x72, 0x69, 0x74, 0x79, 0x20, 0x52, 0x65, 0x73, 0x65,
-    0x61, 0x72, 0x63, 0x68, 0x20, 0x47, 0x72, 0x6F, 0x75, 0x70, 0x31, 0x15, 0x30, 0x13, 0x06, 0x03,
-    0x55, 0x04, 0x03, 0x13, 0x0C, 0x49, 0x53, 0x52, 0x47, 0x20, 0x52, 0x6F, 0x6F, 0x74, 0x20, 0x58,
-    0x31,
-];
-
-// LetsEncrypt trust anchor ISRG Root X1 exp. 04 Jun 2035
-static mut TA0_RSA_N: [u8; 512] = [
-    0xAD, 0xE8, 0x24, 0x73, 0xF4, 0x14, 0x37, 0xF3, 0x9B, 0x9E, 0x2B, 0x57, 0x28, 0x1C, 0x87, 0xBE,
-    0xDC, 0xB7, 0xDF, 0x38, 0x90, 0x8C, 0x6E, 0x3C, 0xE6, 0x57, 0xA0, 0x78, 0xF7, 0x75, 0xC2, 0xA2,
-    0xFE, 0xF5, 0x6A, 0x6E, 0xF6, 0x00, 0x4F, 0x28, 0xDB, 0xDE, 0x68, 0x86, 0x6C, 0x44, 0x93, 0xB6,
-    0xB1, 0x63, 0xFD, 0x14, 0x12, 0x6B, 0xBF, 0x1F, 0xD2, 0xEA, 0x31, 0x9B, 0x21, 0x7E, 0xD1, 0x33,
-    0x3C, 0xBA, 0x48, 0xF5, 0xDD, 0x79, 0xDF, 0xB3, 0xB8, 0xFF, 0x12, 0xF1, 0x21, 0x9A, 0x4B, 0xC1,
-    0x8A, 0x86, 0x71, 0x69, 0x4A, 0x66, 0x66, 0x6C, 0x8F, 0x7E, 0x3C, 0x70, 0xBF, 0xAD, 0x29, 0x22,
-    0x06, 0xF3, 0xE4, 0xC0, 0xE6, 0x80, 0xAE, 0xE2, 0x4B, 0x8F, 0xB7, 0x99, 0x7E, 0x94, 0x03, 0x9F,
-    0xD3, 0x47, 0x97, 0x7C, 0x99, 0x48, 0x23, 0x53, 0xE8, 0x38, 0xAE, 0x4F, 0x0A, 0x6F, 0x83, 0x2E,
-    0xD1, 0x49, 0x57, 0x8C, 0x80, 0x74, 0xB6, 0xDA, 0x2F, 0xD0, 0x38, 0x8D, 0x7B, 0x03, 0x70, 0x21,
-    0x1B, 0x75, 0xF2, 0x30, 0x3C, 0xFA, 0x8F, 0xAE, 0xDD, 0xDA, 0x63, 0xAB, 0xEB, 0x16, 0x4F, 0xC2,
-    0x8E, 0x11, 0x4B, 0x7E, 0xCF, 0x0B, 0xE8, 0xFF, 0xB5, 0x77, 0x2E, 0xF4, 0xB2, 0x7B, 0x4A, 0xE0,
-    0x4C, 0x12, 0x25, 0x0C, 0x70, 0x8D, 0x03, 0x29, 0xA0, 0xE1, 0x53, 0x24, 0xEC, 0x13, 0xD9, 0xEE,
-    0x19, 0xBF, 0x10, 0xB3, 0x4A, 0x8C, 0x3F, 0x89, 0xA3, 0x61, 0x51, 0xDE, 0xAC, 0x87, 0x07, 0x94,
-    0xF4, 0x63, 0x71, 0xEC, 0x2E, 0xE2, 0x6F, 0x5B, 0x98, 0x81, 0xE1, 0x89, 0x5C, 0x34, 0x79, 0x6C,
-    0x76, 0xEF, 0x3B, 0x90, 0x62, 0x79, 0xE6, 0xDB, 0xA4, 0x9A, 0x2F, 0x26, 0xC5, 0xD0, 0x10, 0xE1,
-    0x0E, 0xDE, 0xD9, 0x10, 0x8E, 0x16, 0xFB, 0xB7, 0xF7, 0xA8, 0xF7, 0xC7, 0xE5, 0x02, 0x07, 0x98,
-    0x8F, 0x36, 0x08, 0x95, 0xE7, 0xE2, 0x37, 0x96, 0x0D, 0x36, 0x75, 0x9E, 0xFB, 0x0E, 0x72, 0xB1,
-    0x1D, 0x9B, 0xBC, 0x03, 0xF9, 0x49, 0x05, 0xD8, 0x81, 0xDD, 0x05, 0xB4, 0x2A, 0xD6, 0x41, 0xE9,
-    0xAC, 0x01, 0x76, 0x95, 0x0A, 0x0F, 0xD8, 0xDF, 0xD5, 0xBD, 0x12, 0x1F, 0x35, 0x2F, 0x28, 0x17,
-    0x6C, 0xD2, 0x98, 0xC1, 0xA8, 0x09, 0x64, 0x77, 0x6E, 0x47, 0x37, 0xBA, 0xCE, 0xAC, 0x59, 0x5E,
-    0x68, 0x9D, 0x7F, 0x72, 0xD6, 0x89, 0xC5, 0x06, 0x41, 0x29, 0x3E, 0x59, 0x3E, 0xDD, 0x26, 0xF5,
-    0x24, 0xC9, 0x11, 0xA7, 0x5A, 0xA3, 0x4C, 0x40, 0x1F, 0x46, 0xA1, 0x99, 0xB5, 0xA7, 0x3A, 0x51,
-    0x6E, 0x86, 0x3B, 0x9E, 0x7D, 0x72, 0xA7, 0x12, 0x05, 0x78, 0x59, 0xED, 0x3E, 0x51, 0x78, 0x15,
-    0x0B, 0x03, 0x8F, 0x8D, 0xD0, 0x2F, 0x05, 0xB2, 0x3E, 0x7B, 0x4A, 0x1C, 0x4B, 0x73, 0x05, 0x12,
-    0xFC, 0xC6, 0xEA, 0xE0, 0x50, 0x13, 0x7C, 0x43, 0x93, 0x74, 0xB3, 0xCA, 0x74, 0xE7, 0x8E, 0x1F,
-    0x01, 0x08, 0xD0, 0x30, 0xD4, 0x5B, 0x71, 0x36, 0xB4, 0x07, 0xBA, 0xC1, 0x30, 0x30, 0x5C, 0x48,
-    0xB7, 0x82, 0x3B, 0x98, 0xA6, 0x7D, 0x60, 0x8A, 0xA2, 0xA3, 0x29, 0x82, 0xCC, 0xBA, 0xBD, 0x83,
-    0x04, 0x1B, 0xA2, 0x83, 0x03, 0x41, 0xA1, 0xD6, 0x05, 0xF1, 0x1B, 0xC2, 0xB6, 0xF0, 0xA8, 0x7C,
-    0x86, 0x3B, 0x46, 0xA8, 0x48, 0x2A, 0x88, 0xDC, 0x76, 0x9A, 0x76, 0xBF, 0x1F, 0x6A, 0xA5, 0x3D,
-    0x19, 0x8F, 0xEB, 0x38, 0xF3, 0x64, 0xDE, 0xC8, 0x2B, 0x0D, 0x0A, 0x28, 0xFF, 0xF7, 0xDB, 0xE2,
-    0x15, 0x42, 0xD4, 0x22, 0xD0, 0x27, 0x5D, 0xE1, 0x79, 0xFE, 0x18, 0xE7, 0x70, 0x88, 0xAD, 0x4E,
-    0xE6, 0xD9, 0x8B, 0x3A, 0xC6, 0xDD, 0x27, 0x51, 0x6E, 0xFF, 0xBC, 0x64, 0xF5, 0x33, 0x43, 0x4F,
-];
-
-pub static mut TA0_RSA_E: [u8; 3] = [0x01, 0x00, 0x01];
-
-// LetsEncrypt trust anchor DST Root CA X3 exp. 30 Sep 2021
-static mut TA1_DN: [u8; 65] = [
-    0x30, 0x3F, 0x31, 0x24, 0x30, 0x22, 0x06, 0x03, 0x55, 0x04, 0x0A, 0x13, 0x1B, 0x44, 0x69, 0x67,
-    0x69, 0x74, 0x61, 0x6C, 0x20, 0x53, 0x69, 0x67, 0x6E, 0x61, 0x74, 0x75, 0x72, 0x65, 0x20, 0x54,
-    0x72, 0x75, 0x73, 0x74, 0x20, 0x43, 0x6F, 0x2E, 0x31, 0x17, 0x30, 0x15, 0x06, 0x03, 0x55, 0x04,
-    0x03, 0x13, 0x0E, 0x44, 0x53, 0x54, 0x20, 0x52, 0x6F, 0x6F, 0x74, 0x20, 0x43, 0x41, 0x20, 0x58,
-    0x33,
-];
-
-// LetsEncrypt trust anchor DST Root CA X3 exp. 30 Sep 2021
-static mut TA1_RSA_N: [u8; 256] = [
-    0xDF, 0xAF, 0xE9, 0x97, 0x50, 0x08, 0x83, 0x57, 0xB4, 0xCC, 0x62, 0x65, 0xF6, 0x90, 0x82, 0xEC,
-    0xC7, 0xD3, 0x2C, 0x6B, 0x30, 0xCA, 0x5B, 0xEC, 0xD9, 0xC3, 0x7D, 0xC7, 0x40, 0xC1, 0x18, 0x14,
-    0x8B, 0xE0, 0xE8, 0x33, 0x76, 0x49, 0x2A, 0xE3, 0x3F, 0x21, 0x49, 0x93, 0xAC, 0x4E, 0x0E, 0xAF,
-    0x3E, 0x48, 0xCB, 0x65, 0xEE, 0xFC, 0xD3, 0x21, 0x0F, 0x65, 0xD2, 0x2A, 0xD9, 0x32, 0x8F, 0x8C,
-    0xE5, 0xF7, 0x77, 0xB0, 0x12, 0x7B, 0xB5, 0x95, 0xC0, 0x89, 0xA3, 0xA9, 0xBA, 0xED, 0x73, 0x2E,
-    0x7A, 0x0C, 0x06, 0x32, 0x83, 0xA2, 0x7E, 0x8A, 0x14, 0x30, 0xCD, 0x11, 0xA0, 0xE1, 0x2A, 0x38,
-    0xB9, 0x79, 0x0A, 0x31, 0xFD, 0x50, 0xBD, 0x80, 0x65, 0xDF, 0xB7, 0x51, 0x63, 0x83, 0xC8, 0xE2,
-    0x88, 0x61, 0xEA, 0x4B, 0x61, 0x81, 0xEC, 0x52, 0x6B, 0xB9, 0xA2, 0xE2, 0x4B, 0x1A, 0x28, 0x9F,
-    0x48, 0xA3, 0x9E, 0x0C, 0xDA, 0x09, 0x8E, 0x3E, 0x17, 0x2E, 0x1E, 0xDD, 0x20, 0xDF, 0x5B, 0xC6,
-    0x2A, 0x8A, 0xAB, 0x2E, 0xBD, 0x70, 0xAD, 0xC5, 0x0B, 0x1A, 0x25, 0x90, 0x74, 0x72, 0xC5, 0x7B,
-    0x6A, 0xAB, 0x34, 0xD6, 0x30, 0x89, 0xFF, 0xE5, 0x68, 0x13, 0x7B, 0x54, 0x0B, 0xC8, 0xD6, 0xAE,
-    0xEC, 0x5A, 0x9C, 0x92, 0x1E, 0x3D, 0x64, 0xB3, 0x8C, 0xC6, 0xDF, 0xBF, 0xC9, 0x41, 0x70, 0xEC,
-    0x16, 0x72, 0xD5, 0x26, 0xEC, 0x38, 0x55, 0x39, 0x43, 0xD0, 0xFC, 0xFD, 0x18, 0x5C, 0x40, 0xF1,
-    0x97, 0xEB, 0xD5, 0x9A, 0x9B, 0x8D, 0x1D, 0xBA, 0xDA, 0x25, 0xB9, 0xC6, 0xD8, 0xDF, 0xC1, 0x15,
-    0x02, 0x3A, 0xAB, 0xDA, 0x6E, 0xF1, 0x3E, 0x2E, 0xF5, 0x5C, 0x08, 0x9C, 0x3C, 0xD6, 0x83, 0x69,
-    0xE4, 0x10, 0x9B, 0x19, 0x2A, 0xB6, 0x29, 0x57, 0xE3, 0xE5, 0x3D, 0x9B, 0x9F, 0xF0, 0x02, 0x5D,
-];
-
-pub static mut TA1_RSA_E: [u8; 3] = [0x01, 0x00, 0x01];
-
-//pub static mut IO_BUF: [u8; 4096] = [0; 4096];
-pub static mut IO_BUF: [u8; 2048] = [0; 2048];
-pub const NETWORK_HOST: &[u8; 15usize] = b"ninjametal.com\0"; // must be null terminated!!
-
-// NOTE: we want to get real entropy somehow - The entropy below is hardcoded
-pub static ENTROPY: [u8; 64] = [
-    0x04, 0xCD, 0x7D, 0x68, 0x64, 0xC6, 0x5E, 0xED, 0x18, 0x7E, 0xA3, 0x51, 0xDC, 0x1E, 0x32, 0x7E,
-    0x50, 0xF1, 0xFC, 0x19, 0xE3, 0x99, 0x53, 0x77, 0xC8, 0x06, 0xB0, 0xE3, 0x3B, 0x26, 0xCD, 0x14,
-    0xED, 0x2E, 0xB4, 0xDB, 0x24, 0xD5, 0xF0, 0xBC, 0xEF, 0xF0, 0xE7, 0x36, 0xF2, 0x4D, 0x3B, 0xF2,
-    0x6C, 0xBA, 0x2C, 0x3A, 0x45, 0xB5, 0x9C, 0xC4, 0x8F, 0xC2, 0xAC, 0x3F, 0x47, 0x63, 0x4C, 0x1E,
-];
-
-pub fn build_trust_anchor_ta0() -> br_x509_trust_anchor {
-    let dn = br_x500_name {
-        data: unsafe { TA0_DN.as_mut_ptr() },
-        len: unsafe { TA0_DN.len() as size_t },
-    };
-
-    let rsa_key = br_rsa_public_key {
-        n: unsafe { TA0_RSA_N.as_mut_ptr() },
-        nlen: unsafe { TA0_RSA_N.len() as size_t },
-        e: unsafe { TA0_RSA_E.as_mut_ptr() },
-        elen: unsafe { TA0_RSA_E.len() as size_t },
-    };
-
-    let pkey = br_x509_pkey {
-        key_type: BR_KEYTYPE_RSA as cty::c_uchar,
-        key: br_x509_pkey__bindgen_ty_1 { rsa: rsa_key },
-    };
-
-    br_x509_trust_anchor {
-        dn,
-        flags: BR_X509_TA_CA, // use for certificates with a root certificate authority
-        // flags: 0, // use for self signed certificates
-        pkey,
-    }
-}
-
-pub fn build_trust_anchor_ta1() -> br_x509_trust_anchor {
-    let dn = br_x500_name {
-        data: unsafe { TA1_DN.as_mut_ptr() },
-        len: unsafe { TA1_DN.len() as size_t },
-    };
-
-    let rsa_key = br_rsa_public_key {
-        n: unsafe { TA1_RSA_N.as_mut_ptr() },
-        nlen: unsafe { TA1_RSA_N.len() as size_t },
-        e: unsafe { TA1_RSA_E.as_mut_ptr() },
-        elen: unsafe { TA1_RSA_E.len() as size_t },
-    };
-
-    let pkey = br_x509_pkey {
-        key_type: BR_KEYTYPE_RSA as cty::c_uchar,
-        key: br_x509_pkey__bindgen_ty_1 { rsa: rsa_key },
-    };
-
-    br_x509_trust_anchor {
-        dn,
-        flags: BR_X509_TA_CA, // use for certificates with a root certificate authority
-        // flags: 0, // use for self signed certificates
-        pkey,
-    }
-}
-
-// no mangle so that the linker can find this function which will be called from BearSSL
-#[no_mangle]
-extern "C" fn time(_time: &crate::bearssl::__time_t) -> crate::bearssl::__time_t {
-    let time = unsafe { UNIX_TIME };
-    rprintln!("[INF] time: {}", time);
-    time
-}
-
-// since we are not linking the clib we need to implement this function ourselves
-#[no_mangle]
-extern "C" fn strlen(s: *const cty::c_char) -> isize {
-    let mut count = 0;
-    while (unsafe { *s.add(count) } != b'\0') {
-        count += 1;
-    }
-
-    rprintln!("[INF] strlen: {}", count);
-    count as isize
-}
-
-pub struct SslStream<'a> {
-    stream: TcpStream<'a>,
-    trust_anchors: [br_x509_trust_anchor; 2],
-    client_context: br_ssl_client_context,
-    x509: br_x509_minimal_context,
-    io_context: br_sslio_context,
-
-    // TODO: check if this actually works.
-    // We dont want SslStream to move because we hold pointers to all the br_xx instances
-    _marker: PhantomPinned,
-}
-
-impl<'a> SslStream<'a> {
-    pub fn new(stream: TcpStream<'a>) -> Self {
-        let trust_anchors: [br_x509_trust_anchor; 2] =
-            [build_trust_anchor_ta0(), build_trust_anchor_ta1()];
-        let client_context =
-            unsafe { MaybeUninit::<br_ssl_client_context>::uninit().assume_init() };
-        let x509 = unsafe { MaybeUninit::<br_x509_minimal_context>::uninit().assume_init() };
-        let io_context = unsafe { MaybeUninit::<br_sslio_context>::uninit().assume_init() };
-
-        Self {
-            stream,
-            trust_anchors,
-            client_context,
-            x509,
-            io_context,
-            _marker: PhantomPinned,
-        }
-    }
-
-    pub fn init(&mut self) {
-        let client_context = &mut self.client_context as *mut _;
-        let x509 = &mut self.x509 as *mut _;
-        let eng = &mut self.client_context.eng as *mut _;
-        let io_context = &mut self.io_context as *mut _;
-
-        rprintln!("[INF] building trust anchors");
-
-        // client init
-        unsafe {
-            br_ssl_client_init_full(
-                client_context,
-                x509,
-                self.trust_anchors.as_ptr(),
-                self.trust_anchors.len(),
-            )
-        };
-        rprintln!(
-            "[INF] br_ssl_client_init_full: Err: {}",
-            self.client_context.eng.err
-        );
-
-        // inject entropy
-        unsafe {
-            br_ssl_engine_inject_entropy(
-                eng,
-                (&ENTROPY).as_ptr() as *const cty::c_void,
-                ENTROPY.len(),
-            )
-        };
-        rprintln!(
-            "[INF] br_ssl_engine_inject_entropy: Err: {}",
-            self.client_context.eng.err
-        );
-
-        // set internal IO buffer
-        unsafe {
-            br_ssl_engine_set_buffer(
-                eng,
-                &mut IO_BUF as *mut _ as *mut cty::c_void,
-                IO_BUF.len(),
-                0, // half duplex
-            )
-        };
-        rprintln!(
-            "[INF] br_ssl_engine_set_buffer: Err: {}",
-            self.client_context.eng.err
-        );
-
-        // reset client in preparation for connection
-        unsafe { br_ssl_client_reset(client_context, NETWORK_HOST.as_ptr() as *const u8, 0) };
-        rprintln!(
-            "[INF] br_ssl_client_reset: Err: {}",
-            self.client_context.eng.err
-        );
-
-        let context = &mut self.stream as *mut _ as *mut cty::c_void;
-
-        unsafe {
-            br_sslio_init(
-                io_context,
-                eng,
-                Some(sock_read),
-                context,
-                Some(sock_write),
-                context,
-            )
-        };
-
-        rprintln!("[INF] br_sslio_init: Err: {}", self.client_context.eng.err);
-    }
-}
-
-pub extern "C" fn sock_read(
-    read_context: *mut cty::c_void,
-    data: *mut cty::c_uchar,
-    len: size_t,
-) -> cty::c_int {
-    rprintln!("[INF] sock_read");
-    let stream: &mut TcpStream = unsafe { &mut *(read_context as *mut TcpStream) };
-    let buf: &mut [u8] = unsafe { core::slice::from_raw_parts_mut(data, len as usize) };
-    rprintln!("[INF] sock_read into buffer of {} bytes", len);
-
-    match stream.read(buf) {
-        Ok(read_len) => read_len as cty::c_int,
-        Err(e) => {
-            rprintln!("sock_read Error: {:?}", e);
-            return -1;
-        }
-    }
-}
-
-pub extern "C" fn sock_write(
-    write_context: *mut cty::c_void,
-    data: *const cty::c_uchar,
-    len: size_t,
-) -> cty::c_int {
-    rprintln!("[INF] sock_write");
-    let stream: &mut TcpStream = unsafe { &mut *(write_context as *mut TcpStream) };
-    let buf: &[u8] = unsafe { core::slice::from_raw_parts(data, len as usize) };
-    rprintln!("[INF] sock_write: Sending {} bytes", buf.len());
-
-    match stream.write_all(buf) {
-        Ok(()) => return buf.len() as cty::c_int,
-        Err(e) => {
-            rprintln!("sock_write Error: {:?}", e);
-            return -1;
-        }
-    }
-}
-
-impl<'a> Stream<SslError> for SslStream<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, SslError> {
-        rprintln!("[INF] read");
-
-        let rlen = unsafe {
-            br_sslio_read(
-                &mut self.io_context as *mut _,
-                buf as *mut _ as *mut cty::c_void,
-                buf.len(),
-            )
-        };
-
-        if rlen < 0 {
-            rprintln!("[ERR] br_sslio_read failed to read. rlen: {}", rlen);
-
-            let err = self.client_context.eng.err;
-            return Err(SslError::BearSslReadErr(err));
-        }
-
-        Ok(rlen as usize)
-    }
-
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), SslError> {
-        rprintln!("[INF] write_all: {} bytes", buf.len());
-        let io_context = &mut self.io_context as *mut _;
-
-        let success =
-            unsafe { br_sslio_write_all(io_context, buf.as_ptr() as *const _, buf.len()) };
-
-        if success < 0 {
-            rprintln!("[ERR] br_sslio_write_all failed: {}", success);
-
-            let err = self.client_context.eng.err;
-            return Err(SslError::BearSslWriteErr(err));
-        }
-
-        rprintln!("[INF] br_sslio_flush");
-        unsafe { br_sslio_flush(io_context) };
-        Ok(())
-    }
-}
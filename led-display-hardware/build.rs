@@ -0,0 +1,83 @@
+use std::{env, fs, path::PathBuf};
+
+// the root certificates built into the TLS firmware, see src/trust.rs
+#[cfg(feature = "tls")]
+const CERTS: &str = "../certs";
+
+fn main() {
+    memory_layout();
+
+    // the special build step for linking the bearssl library, only needed for TLS
+    if env::var_os("CARGO_FEATURE_TLS").is_some() {
+        //  println!("cargo:rustc-link-search=./lib");
+        println!("cargo:rustc-link-search=.");
+        println!("cargo:rustc-link-lib=bearssl");
+
+        #[cfg(feature = "tls")]
+        trust_anchors();
+    }
+
+    println!("cargo:rerun-if-changed=libbearssl.a");
+}
+
+// The plain firmware fits the 64K of the STM32F103C8, TLS needs a 128K part and keeps its client
+// certificate, trust store and entropy seed in the last three pages. cortex-m-rt links in whichever
+// memory.x it finds first so the one for this build is copied to OUT_DIR. There must not be a
+// memory.x in this directory because the linker would find that one first.
+fn memory_layout() {
+    let layout = if env::var_os("CARGO_FEATURE_TLS").is_some() {
+        "memory-tls.x"
+    } else {
+        "memory-plain.x"
+    };
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy(layout, out.join("memory.x")).unwrap_or_else(|err| panic!("{}: {}", layout, err));
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory-plain.x");
+    println!("cargo:rerun-if-changed=memory-tls.x");
+}
+
+// Converts the certificates in CERTS into the table included by src/trust.rs. Trust stores sent by
+// the server are only accepted when LED_DISPLAY_TRUST_KEY (the path of the P-256 public key they
+// are signed with, in PEM) is set, and the pins
+// in LED_DISPLAY_PINS (cert:<sha256> or spki:<sha256>, separated by commas) are optional.
+#[cfg(feature = "tls")]
+fn trust_anchors() {
+    let mut pems: Vec<PathBuf> = fs::read_dir(CERTS)
+        .expect("the certs directory is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("pem".as_ref()))
+        .collect();
+    pems.sort();
+
+    let key_path = env::var_os("LED_DISPLAY_TRUST_KEY").map(PathBuf::from);
+    let key = key_path.as_ref().map(|path| {
+        led_display_certgen::load_public_key(path)
+            .unwrap_or_else(|err| panic!("LED_DISPLAY_TRUST_KEY: {}", err))
+    });
+
+    let pins: Vec<_> = env::var("LED_DISPLAY_PINS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|pin| !pin.trim().is_empty())
+        .map(|pin| {
+            led_display_certgen::parse_pin(pin.trim())
+                .unwrap_or_else(|err| panic!("LED_DISPLAY_PINS: {}", err))
+        })
+        .collect();
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("trust_anchors.rs");
+    let result = led_display_certgen::load_all(&pems)
+        .and_then(|roots| led_display_certgen::generate(&roots, &pins, key.as_ref(), &out));
+    if let Err(err) = result {
+        panic!("failed to generate the trust anchors: {}", err);
+    }
+
+    println!("cargo:rerun-if-changed={}", CERTS);
+    println!("cargo:rerun-if-env-changed=LED_DISPLAY_TRUST_KEY");
+    if let Some(path) = key_path {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+    println!("cargo:rerun-if-env-changed=LED_DISPLAY_PINS");
+}
@@ -1,4 +1,4 @@
-/* Linker script for the STM32F103C8T6 */
+/* Linker script for the STM32F103C8T6, used unless the tls feature is on (see build.rs) */
 MEMORY
 {
   FLASH : ORIGIN = 0x08000000, LENGTH = 64K
@@ -0,0 +1,10 @@
+/* Linker script for the TLS firmware, see build.rs */
+/* TLS needs 128K of flash: the STM32F103CB (as on the maple mini) or a C8T6 that turns out to */
+/* have it. The last three 1K pages (125K-127K) are left out, one for the client certificate */
+/* (see src/identity.rs), one for a trust store sent by the server (see src/trust.rs) and the */
+/* last one for the entropy seed (see src/entropy.rs). */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 125K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
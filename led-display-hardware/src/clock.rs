@@ -0,0 +1,45 @@
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use cortex_m::interrupt::Mutex;
+use stm32f1xx_hal::{
+    pac::{interrupt, Interrupt, TIM2},
+    timer::{CountDownTimer, Event},
+};
+
+// Millisecond clock driven by the TIM2 update interrupt
+// This lets the rest of the application measure timeouts without blocking on the Delay (SysTick)
+// which is still used for short busy waits. The counter wraps after about 49 days so always
+// compare times using elapsed_ms() rather than directly.
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+static TIMER: Mutex<RefCell<Option<CountDownTimer<TIM2>>>> = Mutex::new(RefCell::new(None));
+
+// the timer passed in must already be counting down at 1 kHz
+pub fn init(mut timer: CountDownTimer<TIM2>) {
+    timer.listen(Event::Update);
+    cortex_m::interrupt::free(|cs| TIMER.borrow(cs).replace(Some(timer)));
+    unsafe { cortex_m::peripheral::NVIC::unmask(Interrupt::TIM2) };
+}
+
+// milliseconds since the clock was started
+pub fn now_ms() -> u32 {
+    MILLIS.load(Ordering::Relaxed)
+}
+
+// milliseconds since a time previously returned by now_ms()
+pub fn elapsed_ms(since_ms: u32) -> u32 {
+    now_ms().wrapping_sub(since_ms)
+}
+
+#[interrupt]
+fn TIM2() {
+    cortex_m::interrupt::free(|cs| {
+        if let Some(timer) = TIMER.borrow(cs).borrow_mut().as_mut() {
+            timer.clear_update_interrupt_flag();
+        }
+    });
+
+    MILLIS.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,150 @@
+use core::cell::RefCell;
+use cortex_m::{
+    interrupt::Mutex,
+    peripheral::{DCB, DWT},
+};
+use embedded_hal::adc::OneShot;
+use led_display_core::entropy::{EntropyPool, Source, SEED_LEN};
+#[cfg(feature = "tls")]
+use stm32f1xx_hal::flash::{self, FlashWriter};
+use stm32f1xx_hal::{
+    adc::{Adc, VRef, VTemp},
+    pac::ADC1,
+};
+
+// Entropy for BearSSL and the SNTP nonce, see led-display-core/src/entropy.rs for how it is mixed and tested
+// On boot the internal temperature sensor and VREFINT are sampled with the shortest ADC sample
+// time (which makes the lowest bits noisy) along with the cycle counter. The network adds the
+// cycle counter each time the server accepts a connection. A seed kept in the last page of flash
+// (left out of the program by memory-tls.x) is mixed in and replaced straight away so that no two
+// boots start from the same pool, even one that resets before connecting. The seed is not
+// credited though, so TLS is refused until the samples alone have passed the health tests and
+// made up READY_BITS. Without TLS there is no page for the seed and the samples are all there is.
+
+// None until init() has been called
+static POOL: Mutex<RefCell<Option<EntropyPool>>> = Mutex::new(RefCell::new(None));
+
+// samples from each ADC source, each is credited with 1 bit
+const ADC_SAMPLES: usize = 256;
+
+// offset of the seed from the start of flash, the page is erased and rewritten on every boot
+// which is well within the 10k erase cycles of the flash for a panel that rarely restarts
+#[cfg(feature = "tls")]
+const SEED_OFFSET: u32 = 127 * 1024;
+#[cfg(feature = "tls")]
+const SEED_MAGIC: &[u8; 4] = b"SEED";
+
+// the cycle counter has to be running before init() is called, it keeps running after this
+pub fn enable_cycle_counter(mut dcb: DCB, mut dwt: DWT) {
+    dcb.enable_trace();
+    dwt.enable_cycle_counter();
+}
+
+pub fn init(adc: &mut Adc<ADC1>, #[cfg(feature = "tls")] flash: &mut FlashWriter) {
+    let mut pool = EntropyPool::new();
+
+    // the temperature sensor and VREFINT are only powered while TSVREFE is set
+    unsafe { (*ADC1::ptr()).cr2.modify(|_, w| w.tsvrefe().set_bit()) };
+
+    for _ in 0..ADC_SAMPLES {
+        if let Ok(sample) = adc.read(&mut VTemp) {
+            add(&mut pool, Source::Temperature, sample);
+        }
+
+        if let Ok(sample) = adc.read(&mut VRef) {
+            add(&mut pool, Source::Vref, sample);
+        }
+
+        add(&mut pool, Source::CycleCounter, DWT::get_cycle_count());
+    }
+
+    unsafe { (*ADC1::ptr()).cr2.modify(|_, w| w.tsvrefe().clear_bit()) };
+
+    #[cfg(feature = "tls")]
+    {
+        match load_seed(flash) {
+            Some(seed) => pool.mix_seed(&seed),
+            None => rprintln!("[WRN] No entropy seed in flash, this should only happen once"),
+        }
+
+        if let Err(error) = store_seed(flash, &pool.next_seed()) {
+            rprintln!("[WRN] Could not save the entropy seed: {:?}", error);
+        }
+    }
+
+    if pool.is_ready() {
+        rprintln!("[INF] Entropy pool ready ({} bits)", pool.credited_bits());
+    } else {
+        rprintln!(
+            "[WRN] Only {} bits of entropy, the network makes up the rest",
+            pool.credited_bits()
+        );
+    }
+
+    cortex_m::interrupt::free(|cs| POOL.borrow(cs).replace(Some(pool)));
+}
+
+// the server answered, the time that took depends on the network
+pub fn add_network_timing() {
+    let sample = DWT::get_cycle_count();
+    cortex_m::interrupt::free(|cs| {
+        if let Some(pool) = POOL.borrow(cs).borrow_mut().as_mut() {
+            add(pool, Source::Network, sample);
+        }
+    });
+}
+
+// Fresh entropy for a TLS connection, None until the pool is ready (or if init() has not been
+// called). Every connection to the server adds to the pool so a pool that starts out short gets
+// there while the panel backs off.
+pub fn output() -> Option<[u8; SEED_LEN]> {
+    cortex_m::interrupt::free(|cs| {
+        POOL.borrow(cs)
+            .borrow_mut()
+            .as_mut()
+            .filter(|pool| pool.is_ready())
+            .map(|pool| pool.output())
+    })
+}
+
+// The nonce in an SNTP request, so that a reply has to have seen the request to be accepted. It
+// only has to be hard to guess, not secret for long, so it is drawn before the pool is ready
+// rather than waiting for a connection that needs the time first. None if init() has not been
+// called.
+pub fn nonce() -> Option<u32> {
+    cortex_m::interrupt::free(|cs| {
+        POOL.borrow(cs).borrow_mut().as_mut().map(|pool| {
+            let output = pool.output();
+            u32::from_le_bytes([output[0], output[1], output[2], output[3]])
+        })
+    })
+}
+
+fn add<S: Into<u32>>(pool: &mut EntropyPool, source: Source, sample: S) {
+    if let Err(error) = pool.add(source, sample.into()) {
+        rprintln!("[WRN] Entropy health test failed: {:?}", error);
+    }
+}
+
+// the seed is stored as SEED_MAGIC followed by SEED_LEN bytes, erased flash reads 0xFF
+#[cfg(feature = "tls")]
+fn load_seed(flash: &mut FlashWriter) -> Option<[u8; SEED_LEN]> {
+    let stored = flash.read(SEED_OFFSET, SEED_MAGIC.len() + SEED_LEN).ok()?;
+    if &stored[..SEED_MAGIC.len()] != SEED_MAGIC {
+        return None;
+    }
+
+    let mut seed = [0; SEED_LEN];
+    seed.copy_from_slice(&stored[SEED_MAGIC.len()..]);
+    Some(seed)
+}
+
+#[cfg(feature = "tls")]
+fn store_seed(flash: &mut FlashWriter, seed: &[u8; SEED_LEN]) -> Result<(), flash::Error> {
+    let mut page = [0; SEED_MAGIC.len() + SEED_LEN];
+    page[..SEED_MAGIC.len()].copy_from_slice(SEED_MAGIC);
+    page[SEED_MAGIC.len()..].copy_from_slice(seed);
+
+    flash.erase(SEED_OFFSET, 1024)?;
+    flash.write(SEED_OFFSET, &page)
+}
@@ -0,0 +1,60 @@
+use led_display_core::identity::{Identity, MAX_LEN};
+use stm32f1xx_hal::stm32;
+
+// The panel's client certificate and private key for mutual TLS, see led-display-core/src/identity.rs
+// They are written to a page of flash of their own below the trust store (see memory-tls.x) when the
+// panel is set up, using `led-display-certgen identity`, and the firmware never writes it so
+// flashing a new version leaves them alone. Without one the panel connects without a client
+// certificate and servers that insist on one will refuse it. Turn on flash read out protection
+// (RDP) once the panel is set up so that the key cannot be read back with a debugger.
+
+// offset of the identity from the start of flash
+const IDENTITY_OFFSET: usize = 125 * 1024;
+const FLASH_ADDRESS: usize = 0x0800_0000;
+
+// the identity is saved after its length (u16), erased flash gives a length of 0xFFFF
+const LEN_LEN: usize = 2;
+
+// the identity in flash if there is a valid one
+pub fn stored() -> Option<Identity<'static>> {
+    // flash is memory mapped and the firmware never changes this page
+    let address = FLASH_ADDRESS + IDENTITY_OFFSET;
+    let page: &'static [u8] =
+        unsafe { core::slice::from_raw_parts(address as *const u8, LEN_LEN + MAX_LEN) };
+    let len = u16::from_le_bytes([page[0], page[1]]) as usize;
+    if len > MAX_LEN {
+        return None;
+    }
+
+    match Identity::parse(&page[LEN_LEN..LEN_LEN + len]) {
+        Ok(identity) => Some(identity),
+        Err(error) => {
+            rprintln!(
+                "[WRN] Ignoring the client certificate in flash: {:?}",
+                error
+            );
+            None
+        }
+    }
+}
+
+// logs what the panel will identify itself with, on boot
+pub fn check() {
+    match stored() {
+        Some(identity) => {
+            rprintln!(
+                "[INF] Client certificate of {} bytes",
+                identity.certificate.len()
+            );
+            if !read_protected() {
+                rprintln!("[WRN] Flash read out protection is off, the client key can be read");
+            }
+        }
+        None => rprintln!("[WRN] No client certificate, servers that ask for one will refuse us"),
+    }
+}
+
+fn read_protected() -> bool {
+    // RDPRT in FLASH_OBR, only reading it so the HAL owning the flash does not mind
+    unsafe { (*stm32::FLASH::ptr()).obr.read().rdprt().bit_is_set() }
+}
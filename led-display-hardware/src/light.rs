@@ -0,0 +1,26 @@
+use embedded_hal::adc::OneShot;
+use stm32f1xx_hal::{
+    adc::Adc,
+    gpio::{gpioa::PA0, Analog},
+    pac::ADC1,
+};
+
+// Optional ambient light sensor (enable with the light-sensor feature)
+// A light dependent resistor from 3.3V to PA0 with a 10k resistor from PA0 to ground so that the
+// reading goes up with the light. Any sensor with an analog output from 0 to 3.3V will do.
+
+pub struct LightSensor {
+    adc: Adc<ADC1>,
+    pin: PA0<Analog>,
+}
+
+impl LightSensor {
+    pub fn new(adc: Adc<ADC1>, pin: PA0<Analog>) -> Self {
+        Self { adc, pin }
+    }
+
+    // a 12 bit reading, the conversion only takes a few microseconds and blocks until it is done
+    pub fn read(&mut self) -> Option<u16> {
+        self.adc.read(&mut self.pin).ok()
+    }
+}
@@ -5,57 +5,127 @@
 #[macro_use]
 extern crate rtt_target;
 
-use core::cell::RefCell;
+#[cfg(feature = "tls")]
+use core::pin::Pin;
+use core::{cell::RefCell, convert::Infallible};
 use cortex_m::asm;
 use cortex_m_rt::entry;
-use display::{LedPanel, LedPanelError};
-use embedded_hal::{spi::Mode, spi::Phase, spi::Polarity};
+use embedded_hal::{digital::v2::InputPin, spi::Mode, spi::Phase, spi::Polarity};
 use embedded_websocket as ws;
-use max7219_dot_matrix::MAX7219;
-use network::{NetworkError, TcpStream};
+#[cfg(feature = "ntp")]
+use led_display_core::calendar::DateTime;
+#[cfg(feature = "tls")]
+use led_display_core::trust::{Summary, TrustError};
+use led_display_core::{
+    app::{App, Board},
+    display::LedPanel,
+    protocol,
+    reconnect::{Backoff, Failure},
+    session::{self, SessionError},
+};
 use rtt_target::{rprintln, rtt_init_print};
+#[cfg(feature = "tls")]
+use ssl::{SslError, SslStream, TlsContext};
 use stm32f1xx_hal::{
     delay::Delay,
     gpio::{
-        gpioa::{PA5, PA6, PA7},
-        Alternate, Floating, Input, PushPull,
+        gpioa::{PA2, PA4, PA5, PA6, PA7},
+        Alternate, Floating, Input, Output, PushPull,
     },
     pac::SPI1,
     prelude::*,
     spi::{Spi, Spi1NoRemap},
     stm32,
+    timer::Timer,
 };
+use tcp::{TcpError, TcpStream};
+use transport::Transport;
 use w5500::{IpAddress, Socket, W5500};
+use websocket::WebSocket;
 use ws::{
     framer::{Framer, FramerError},
     EmptyRng, WebSocketOptions,
 };
 
-mod display;
-mod network;
+#[cfg(feature = "tls")]
+mod bearssl;
+mod clock;
+#[cfg(feature = "ntp")]
+mod entropy;
+#[cfg(feature = "tls")]
+mod identity;
+#[cfg(feature = "light-sensor")]
+mod light;
+#[cfg(feature = "tls")]
+mod ssl;
+mod tcp;
+#[cfg(feature = "ntp")]
+mod time;
+mod transport;
+#[cfg(feature = "tls")]
+mod trust;
+mod websocket;
+
+// how long we wait for the server during the TLS and websocket opening handshakes
+#[cfg(not(feature = "tls"))]
+const HANDSHAKE_TIMEOUT_MS: u32 = 10_000;
+#[cfg(feature = "tls")]
+const HANDSHAKE_TIMEOUT_MS: u32 = 30_000;
+
+// what the websocket runs on, see src/transport.rs
+#[cfg(not(feature = "tls"))]
+type StreamError = TcpError;
+#[cfg(feature = "tls")]
+type StreamError = SslError;
 
 #[derive(Debug)]
 enum LedDemoError {
-    Display(LedPanelError),
-    Network(NetworkError),
-    Framer(FramerError<NetworkError>),
+    Tcp(TcpError),
+    #[cfg(feature = "tls")]
+    Tls(SslError),
+    Handshake(FramerError<StreamError>),
+    Session(SessionError<FramerError<StreamError>, SpiError, Infallible>),
 }
 
-impl From<LedPanelError> for LedDemoError {
-    fn from(err: LedPanelError) -> LedDemoError {
-        LedDemoError::Display(err)
+impl LedDemoError {
+    // classify the error so that the main loop can pick a suitable backoff
+    fn failure(&self) -> Failure {
+        match self {
+            #[cfg(feature = "ntp")]
+            LedDemoError::Tcp(TcpError::Time(_)) => Failure::Ntp,
+            LedDemoError::Tcp(_) => Failure::Tcp,
+            #[cfg(feature = "tls")]
+            LedDemoError::Tls(_) => Failure::Tls,
+            // the connection failed while sending the websocket handshake
+            #[cfg(feature = "tls")]
+            LedDemoError::Handshake(FramerError::Io(_)) => Failure::Tls,
+            LedDemoError::Handshake(_) => Failure::Handshake,
+            LedDemoError::Session(_) => Failure::Session,
+        }
+    }
+
+    // what to show on the panel until we are back, TLS failures say which part went wrong
+    fn status(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "tls")]
+            LedDemoError::Tls(SslError::Engine(error))
+            | LedDemoError::Handshake(FramerError::Io(SslError::Engine(error))) => {
+                error.category().glyph()
+            }
+            _ => self.failure().glyph(),
+        }
     }
 }
 
-impl From<FramerError<NetworkError>> for LedDemoError {
-    fn from(err: FramerError<NetworkError>) -> LedDemoError {
-        LedDemoError::Framer(err)
+impl From<SessionError<FramerError<StreamError>, SpiError, Infallible>> for LedDemoError {
+    fn from(err: SessionError<FramerError<StreamError>, SpiError, Infallible>) -> LedDemoError {
+        LedDemoError::Session(err)
     }
 }
 
-impl From<NetworkError> for LedDemoError {
-    fn from(err: NetworkError) -> LedDemoError {
-        LedDemoError::Network(err)
+impl From<TcpError> for LedDemoError {
+    fn from(err: TcpError) -> LedDemoError {
+        LedDemoError::Tcp(err)
     }
 }
 
@@ -73,6 +143,51 @@ type SpiPhysical = Spi<
 
 type SpiError = stm32f1xx_hal::spi::Error;
 
+// W5500 ethernet card with CS pin PA2
+type W5500Physical = W5500<PA2<Output<PushPull>>>;
+
+// the CS output pin on stm32f1xx_hal is Infallible
+type W5500Error = w5500::Error<SpiError, Infallible>;
+
+// MAX7219 dot matrix chain with CS pin PA4
+type LedApp<'a> = App<'a, SpiPhysical, PA4<Output<PushPull>>>;
+
+// What the application needs from the board apart from the panel
+// Without NTP there is no time of day so neither the brightness schedule nor the clock apply.
+struct Hardware {
+    #[cfg(feature = "light-sensor")]
+    light_sensor: light::LightSensor,
+    // for saving trust stores sent by the server
+    #[cfg(feature = "tls")]
+    flash: stm32f1xx_hal::flash::Parts,
+    // a trust store sent by the server, saved once the connection has closed
+    #[cfg(feature = "tls")]
+    trust_update: Option<trust::Update>,
+}
+
+impl Board for Hardware {
+    fn now_ms(&self) -> u32 {
+        clock::now_ms()
+    }
+
+    #[cfg(feature = "light-sensor")]
+    fn ambient_light(&mut self) -> Option<u16> {
+        self.light_sensor.read()
+    }
+
+    #[cfg(feature = "ntp")]
+    fn local_time(&self) -> Option<DateTime> {
+        time::local_time()
+    }
+
+    #[cfg(feature = "tls")]
+    fn update_trust_store(&mut self, store: &[u8]) -> Result<Summary, TrustError> {
+        let (update, summary) = trust::check(store, self.trust_update.as_ref())?;
+        self.trust_update = Some(update);
+        Ok(summary)
+    }
+}
+
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
     rprintln!("{}", info);
@@ -84,6 +199,7 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
+    led_display_core::log::set_logger(|args| rprintln!("{}", args));
     rprintln!("[INF] Initializing");
 
     // general peripheral setup
@@ -96,6 +212,14 @@ fn main() -> ! {
     let clocks = rcc.cfgr.freeze(&mut flash.acr);
     let mut delay = Delay::new(cp.SYST, clocks);
 
+    // cycle counter used to time things for entropy
+    #[cfg(feature = "ntp")]
+    entropy::enable_cycle_counter(cp.DCB, cp.DWT);
+
+    // millisecond clock used for timeouts
+    let timer = Timer::tim2(dp.TIM2, &clocks, &mut rcc.apb1).start_count_down(1.khz());
+    clock::init(timer);
+
     // spi setup
     let mut gpioa = dp.GPIOA.split(&mut rcc.apb2);
     let sck = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
@@ -116,33 +240,148 @@ fn main() -> ! {
         &mut rcc.apb2,
     );
 
+    // holding the button (PB8 on the maple mini) during reset runs the self test
+    let mut gpiob = dp.GPIOB.split(&mut rcc.apb2);
+    let self_test_button = gpiob.pb8.into_pull_down_input(&mut gpiob.crh);
+
+    // ADC1 reads the light sensor and, for NTP and TLS, noise from the temperature sensor and VREFINT
+    #[cfg(any(feature = "ntp", feature = "light-sensor"))]
+    #[allow(unused_mut)]
+    let mut adc = stm32f1xx_hal::adc::Adc::adc1(dp.ADC1, &mut rcc.apb2, clocks);
+
+    // entropy for the SNTP nonce and TLS, mixed with the seed kept in the last page of flash
+    #[cfg(feature = "tls")]
+    entropy::init(
+        &mut adc,
+        &mut flash.writer(
+            stm32f1xx_hal::flash::SectorSize::Sz1K,
+            stm32f1xx_hal::flash::FlashSize::Sz128K,
+        ),
+    );
+    #[cfg(all(feature = "ntp", not(feature = "tls")))]
+    entropy::init(&mut adc);
+
+    // the client certificate for mutual TLS, if the panel has been given one
+    #[cfg(feature = "tls")]
+    identity::check();
+
+    // optional ambient light sensor on PA0
+    #[cfg(feature = "light-sensor")]
+    let light_sensor = light::LightSensor::new(adc, gpioa.pa0.into_analog(&mut gpioa.crl));
+
     // wait for things to settle
     delay.delay_ms(250_u16);
+    let delay = RefCell::new(delay);
+
     rprintln!("[INF] Done initialising");
 
     let spi = RefCell::new(spi);
     let mut w5500 = W5500::new(cs_ethernet);
-    let mut max7219 = MAX7219::new(&mut cs_max7219, 20);
-    let mut led_panel = LedPanel::new(&mut max7219, &spi);
+    let mut app = App::new(LedPanel::new(&mut cs_max7219, &spi));
+    let mut backoff = Backoff::new(device_seed());
+    let mut board = Hardware {
+        #[cfg(feature = "light-sensor")]
+        light_sensor,
+        #[cfg(feature = "tls")]
+        flash,
+        #[cfg(feature = "tls")]
+        trust_update: None,
+    };
+
+    if self_test_button.is_high().unwrap_or(false) {
+        app.panel_mut().start_self_test(clock::now_ms());
+        while app.panel().self_test_running() {
+            if let Err(error) = app.poll(&mut board) {
+                rprintln!("[ERR] {:?}", &error);
+                break;
+            }
+        }
+    }
+
+    // BearSSL's state, reused for every connection so that the TLS session can be resumed
+    #[cfg(feature = "tls")]
+    let mut tls_context = TlsContext::take().unwrap();
 
     loop {
-        let mut stream = TcpStream::new(&mut w5500, Socket::Socket0, &mut delay, &spi);
+        let stream = TcpStream::new(&mut w5500, Socket::Socket0, &delay, &spi);
+
+        let (failure, status) = match client_connect(
+            &mut app,
+            &mut board,
+            &mut backoff,
+            stream,
+            #[cfg(feature = "tls")]
+            tls_context.as_mut(),
+        ) {
+            Ok(()) => {
+                rprintln!("[INF] Connection closed");
+                (Failure::Session, Failure::Session.glyph())
+            }
+            Err(error) => {
+                rprintln!("[ERR] {:?}", &error);
+                (error.failure(), error.status())
+            }
+        };
 
-        match client_connect(&mut led_panel, &mut stream) {
-            Ok(()) => rprintln!("[INF] Connection closed"),
-            Err(error) => rprintln!("[ERR] {:?}", &error),
+        // the connection was up for a while so whatever goes wrong next is a fresh problem
+        backoff.disconnected(clock::now_ms());
+
+        // BearSSL is done with the trust anchors in flash now that the connection has closed
+        #[cfg(feature = "tls")]
+        {
+            if let Some(update) = board.trust_update.take() {
+                if update.save(&mut board.flash).is_ok() {
+                    rprintln!("[INF] Trust store {} saved", update.version());
+                }
+            }
         }
+
+        let delay_ms = backoff.next_delay_ms(failure);
+        rprintln!("[INF] Reconnecting in {} ms ({:?})", delay_ms, failure);
+        app.panel_mut().set_status(Some(status));
+
+        // keep the display going while we wait
+        let wait_start_ms = clock::now_ms();
+        while clock::elapsed_ms(wait_start_ms) < delay_ms {
+            if let Err(error) = app.poll(&mut board) {
+                rprintln!("[ERR] {:?}", &error);
+                delay
+                    .borrow_mut()
+                    .delay_ms(delay_ms.saturating_sub(clock::elapsed_ms(wait_start_ms)));
+                break;
+            }
+        }
+    }
+}
+
+// the 96 bit unique device id is used to seed the reconnect jitter so that panels do not retry in lockstep
+fn device_seed() -> u32 {
+    const UID_ADDRESS: usize = 0x1FFF_F7E8;
+    let uid = UID_ADDRESS as *const u32;
+    unsafe {
+        core::ptr::read_volatile(uid)
+            ^ core::ptr::read_volatile(uid.add(1))
+            ^ core::ptr::read_volatile(uid.add(2))
     }
 }
 
-fn client_connect(led_panel: &mut LedPanel, stream: &mut TcpStream) -> Result<(), LedDemoError> {
+fn client_connect(
+    app: &mut LedApp,
+    board: &mut Hardware,
+    backoff: &mut Backoff,
+    mut stream: TcpStream,
+    #[cfg(feature = "tls")] tls_context: Pin<&mut TlsContext>,
+) -> Result<(), LedDemoError> {
     rprintln!("[INF] Client connecting");
 
-    // remote connection
+    // remote connection, over TLS the host is also sent with SNI and checked against the
+    // certificate
     let host_ip = IpAddress::new(51, 140, 68, 75);
-    let host_port = 80;
     let host = "ninjametal.com";
-    let origin = "http://ninjametal.com";
+    #[cfg(not(feature = "tls"))]
+    let (host_port, origin) = (80, "http://ninjametal.com");
+    #[cfg(feature = "tls")]
+    let (host_port, origin) = (443, "https://ninjametal.com");
 
     // local connection
     // let host_ip = IpAddress::new(192, 168, 1, 149);
@@ -150,14 +389,49 @@ fn client_connect(led_panel: &mut LedPanel, stream: &mut TcpStream) -> Result<()
     // let host = "192.168.1.149";
     // let origin = "http://192.168.1.149";
 
-    // open tcp stream
+    // open tcp stream, fetching the time first if we need it
     stream.connect(&host_ip, host_port)?;
 
+    // the session of the last connection is offered to the server and replaced if this
+    // handshake succeeds
+    #[cfg(feature = "tls")]
+    let mut stream = SslStream::new(stream, tls_context, host).map_err(LedDemoError::Tls)?;
+
+    #[cfg(feature = "tls")]
+    {
+        let handshake_start_ms = clock::now_ms();
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT_MS));
+        stream.handshake().map_err(LedDemoError::Tls)?;
+
+        let kind = if stream.resumed() {
+            "TLS session resumed"
+        } else {
+            "Full TLS handshake"
+        };
+        app.report(format_args!(
+            "{} in {} ms",
+            kind,
+            clock::elapsed_ms(handshake_start_ms)
+        ));
+    }
+
+    websocket_connect(app, board, backoff, &mut stream, host, origin)
+}
+
+// websocket opening handshake followed by the session, over TCP or TLS
+fn websocket_connect<S: Transport<Error = StreamError>>(
+    app: &mut LedApp,
+    board: &mut Hardware,
+    backoff: &mut Backoff,
+    stream: &mut S,
+    host: &str,
+    origin: &str,
+) -> Result<(), LedDemoError> {
     let mut websocket = ws::WebSocketClient::new_client(EmptyRng::new());
     let mut read_buf = [0; 512];
     let mut read_cursor = 0;
     let mut write_buf = [0; 512];
-    let mut frame_buf = [0; 512];
+    let mut frame_buf = [0; protocol::MAX_BINARY_LEN];
     let mut framer = Framer::new(
         &mut read_buf,
         &mut read_cursor,
@@ -173,15 +447,22 @@ fn client_connect(led_panel: &mut LedPanel, stream: &mut TcpStream) -> Result<()
         additional_headers: None,
     };
 
+    rprintln!("[INF] Websocket sending opening handshake");
+
     // send websocket open handshake
-    framer.connect(stream, &websocket_options)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT_MS));
+    framer
+        .connect(stream, &websocket_options)
+        .map_err(LedDemoError::Handshake)?;
     rprintln!("[INF] Websocket opening handshake complete");
 
-    // read one message at a time and display it
-    while let Some(message) = framer.read_text(stream, &mut frame_buf)? {
-        rprintln!("[INF] Websocket received: {}", message);
-        led_panel.scroll_str(message)?;
-    }
+    app.panel_mut().set_status(None);
+    backoff.connected(clock::now_ms());
 
+    // from now on reads return immediately when there is no data so that the display can be
+    // updated in between and we can keep an eye on the connection
+    stream.set_read_timeout(Some(0));
+    let mut connection = WebSocket::new(framer, stream);
+    session::run(app, &mut connection, board, &mut frame_buf)?;
     Ok(())
 }
@@ -0,0 +1,628 @@
+use embedded_websocket::framer::Stream;
+
+use crate::{
+    bearssl::*,
+    entropy, identity,
+    tcp::{TcpError, TcpStream},
+    time::{self, TimeError},
+    transport::Transport,
+    trust::Trust,
+};
+use core::{
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr::{self, addr_of_mut},
+    slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use cty::size_t;
+use led_display_core::{
+    tls::{EngineError, TlsError},
+    trust::{Anchor, PinCheck, Pins, MAX_ANCHORS},
+    x509::PublicKey,
+};
+
+// Notes on safety and use of unsafe rust in this module.
+// This module uses the statically linked BearSsl library to perform all the TLS operations and
+// any FFI call is unsafe. BearSSL keeps pointers between its contexts (the engine points at the
+// X.509 engine, the trust anchors and the IO buffer) so they all live in one TlsContext that is
+// pinned and never moves. There is only one, taken at startup with TlsContext::take(), and each
+// SslStream borrows it for one connection and sets it up afresh. SslStream moves records between
+// the engine and the tcp stream itself so BearSSL never holds a pointer to the tcp stream.
+// The IO buffer is much smaller than the 16KB a TLS record can be because the client asks the
+// server for smaller records (TLS 1.2 maximum fragment length negotiation).
+
+// Note about the chain of trust
+// The trust anchors and pins come from trust.rs: either the root certificates in ../certs that
+// were built in or a trust store the server has sent since. Make sure the certificate of your
+// website chains to one of them (the built in ones are Let's Encrypt's ISRG Root X1 and X2).
+// BearSSL's minimal X.509 engine validates the chain and PinningX509 below checks the pins.
+
+// Note on client certificates
+// When the panel has been given an identity (see identity.rs) its certificate and EC private key
+// are handed to BearSSL for every connection, so that a server asking for a client certificate
+// can tell which panel this is. Both stay in flash, BearSSL only reads them.
+
+// Note on cryptography
+// BearSsl needs entropy to generate random numbers for the handshake. The board has no random number
+// generator so fresh entropy for every connection comes from the pool in entropy.rs.
+
+#[derive(Debug)]
+pub enum SslError {
+    Tcp(TcpError),
+    // empty, longer than MAX_SERVER_NAME_LEN or containing a null
+    InvalidServerName,
+    // the engine failed and the connection is unusable, see led-display-core/src/tls.rs
+    Engine(TlsError),
+    // the server closed the connection cleanly
+    Closed,
+    // the entropy pool is not ready, BearSSL is not seeded from it until it is
+    NoEntropy,
+}
+
+//const IO_BUF_LEN: usize = 4096;
+const IO_BUF_LEN: usize = 2048;
+
+// BearSSL keeps a copy of the server name of up to 255 characters for SNI and to check against
+// the certificate
+const MAX_SERVER_NAME_LEN: usize = 255;
+
+// BearSSL only reads through these pointers
+fn build_trust_anchor(anchor: &Anchor<'static>) -> br_x509_trust_anchor {
+    let dn = br_x500_name {
+        data: anchor.subject.as_ptr() as *mut u8,
+        len: anchor.subject.len() as size_t,
+    };
+
+    let pkey = match anchor.key {
+        PublicKey::Rsa { n, e } => br_x509_pkey {
+            key_type: BR_KEYTYPE_RSA as cty::c_uchar,
+            key: br_x509_pkey__bindgen_ty_1 {
+                rsa: br_rsa_public_key {
+                    n: n.as_ptr() as *mut u8,
+                    nlen: n.len() as size_t,
+                    e: e.as_ptr() as *mut u8,
+                    elen: e.len() as size_t,
+                },
+            },
+        },
+        PublicKey::Ec { curve, q } => br_x509_pkey {
+            key_type: BR_KEYTYPE_EC as cty::c_uchar,
+            key: br_x509_pkey__bindgen_ty_1 {
+                ec: br_ec_public_key {
+                    curve: curve as cty::c_int,
+                    q: q.as_ptr() as *mut u8,
+                    qlen: q.len() as size_t,
+                },
+            },
+        },
+    };
+
+    br_x509_trust_anchor {
+        dn,
+        // 0 trusts a self signed server certificate directly
+        flags: if anchor.ca { BR_X509_TA_CA } else { 0 },
+        pkey,
+    }
+}
+
+// BearSSL's minimal X.509 engine with the pins checked once it has validated the chain
+// BearSSL passes around a pointer to the vtable at the start of a context so repr(C) and the
+// field order matter here. The calls are forwarded to the minimal engine through its own vtable.
+#[repr(C)]
+struct PinningX509 {
+    vtable: *const br_x509_class,
+    minimal: br_x509_minimal_context,
+    check: PinCheck,
+}
+
+static PINNING_X509_CLASS: br_x509_class = br_x509_class {
+    context_size: core::mem::size_of::<PinningX509>(),
+    start_chain: Some(pinning_start_chain),
+    start_cert: Some(pinning_start_cert),
+    append: Some(pinning_append),
+    end_cert: Some(pinning_end_cert),
+    end_chain: Some(pinning_end_chain),
+    get_pkey: Some(pinning_get_pkey),
+};
+
+unsafe fn pinning<'a>(ctx: *mut *const br_x509_class) -> &'a mut PinningX509 {
+    &mut *(ctx as *mut PinningX509)
+}
+
+// the minimal engine as BearSSL would see it, its vtable is a static in BearSSL
+fn minimal_ctx(x509: &mut PinningX509) -> (*mut *const br_x509_class, &'static br_x509_class) {
+    let vtable = unsafe { &*x509.minimal.vtable };
+    (&mut x509.minimal.vtable as *mut _, vtable)
+}
+
+unsafe extern "C" fn pinning_start_chain(
+    ctx: *mut *const br_x509_class,
+    server_name: *const cty::c_char,
+) {
+    let x509 = pinning(ctx);
+    x509.check.start_chain();
+    let (minimal, vtable) = minimal_ctx(x509);
+    (vtable.start_chain.unwrap())(minimal, server_name);
+}
+
+unsafe extern "C" fn pinning_start_cert(ctx: *mut *const br_x509_class, length: u32) {
+    let x509 = pinning(ctx);
+    x509.check.start_certificate();
+    let (minimal, vtable) = minimal_ctx(x509);
+    (vtable.start_cert.unwrap())(minimal, length);
+}
+
+unsafe extern "C" fn pinning_append(
+    ctx: *mut *const br_x509_class,
+    buf: *const cty::c_uchar,
+    len: usize,
+) {
+    let x509 = pinning(ctx);
+    x509.check.append(slice::from_raw_parts(buf, len));
+    let (minimal, vtable) = minimal_ctx(x509);
+    (vtable.append.unwrap())(minimal, buf, len);
+}
+
+unsafe extern "C" fn pinning_end_cert(ctx: *mut *const br_x509_class) {
+    let (minimal, vtable) = minimal_ctx(pinning(ctx));
+    (vtable.end_cert.unwrap())(minimal);
+}
+
+unsafe extern "C" fn pinning_end_chain(ctx: *mut *const br_x509_class) -> cty::c_uint {
+    let x509 = pinning(ctx);
+    let (minimal, vtable) = minimal_ctx(x509);
+    let err = (vtable.end_chain.unwrap())(minimal);
+    if err != BR_ERR_OK {
+        return err;
+    }
+
+    // the chain is fine, now the server key has to match a pin (if there are any)
+    let pkey = (vtable.get_pkey.unwrap())(minimal, core::ptr::null_mut());
+    let matches = match pkey.as_ref().and_then(|pkey| public_key(pkey)) {
+        Some(key) => x509.check.matches(&key),
+        None => false,
+    };
+
+    if matches {
+        BR_ERR_OK
+    } else {
+        rprintln!("[ERR] The server certificate does not match any of the pins");
+        BR_ERR_X509_NOT_TRUSTED
+    }
+}
+
+unsafe extern "C" fn pinning_get_pkey(
+    ctx: *const *const br_x509_class,
+    usages: *mut cty::c_uint,
+) -> *const br_x509_pkey {
+    let (minimal, vtable) = minimal_ctx(pinning(ctx as *mut _));
+    (vtable.get_pkey.unwrap())(minimal, usages)
+}
+
+unsafe fn public_key(pkey: &br_x509_pkey) -> Option<PublicKey<'_>> {
+    match pkey.key_type as u32 {
+        BR_KEYTYPE_RSA => {
+            let rsa = pkey.key.rsa;
+            Some(PublicKey::Rsa {
+                n: slice::from_raw_parts(rsa.n, rsa.nlen),
+                e: slice::from_raw_parts(rsa.e, rsa.elen),
+            })
+        }
+        BR_KEYTYPE_EC => {
+            let ec = pkey.key.ec;
+            Some(PublicKey::Ec {
+                curve: ec.curve as u8,
+                q: slice::from_raw_parts(ec.q, ec.qlen),
+            })
+        }
+        _ => None,
+    }
+}
+
+// no mangle so that the linker can find this function which will be called from BearSSL
+#[no_mangle]
+extern "C" fn time(out: *mut crate::bearssl::__time_t) -> crate::bearssl::__time_t {
+    // the current time, certificates are checked against it for as long as the connection lasts
+    let now = time::unix_time().unwrap_or(0);
+
+    // like time() in C the result is also stored through the pointer, BearSSL passes null
+    if !out.is_null() {
+        unsafe { out.write(now) };
+    }
+
+    now
+}
+
+// since we are not linking the clib we need to implement this function ourselves
+#[no_mangle]
+extern "C" fn strlen(s: *const cty::c_char) -> isize {
+    let mut count = 0;
+    while (unsafe { *s.add(count) } != b'\0') {
+        count += 1;
+    }
+
+    count as isize
+}
+
+// What BearSSL needs to resume a TLS session with the server it was made with. Resuming skips
+// the certificate checks and the expensive public key operations of a full handshake.
+#[derive(Clone, Copy)]
+struct Session {
+    parameters: br_ssl_session_parameters,
+    server_name: [u8; MAX_SERVER_NAME_LEN + 1],
+}
+
+// BearSSL's state for one connection at a time, see the notes at the top
+pub struct TlsContext {
+    client: br_ssl_client_context,
+    x509: PinningX509,
+    trust_anchors: [br_x509_trust_anchor; MAX_ANCHORS],
+    client_certificate: br_x509_certificate,
+    client_key: br_ec_private_key,
+    io_buf: [u8; IO_BUF_LEN],
+    // the session of the last successful handshake, offered on the next connection
+    session: Option<Session>,
+    _pinned: PhantomPinned,
+}
+
+// The one TlsContext, it is too big to build on the stack and move so it is set up where it is in
+// .bss by TlsContext::take()
+static mut CONTEXT: MaybeUninit<TlsContext> = MaybeUninit::uninit();
+static CONTEXT_TAKEN: AtomicBool = AtomicBool::new(false);
+
+impl TlsContext {
+    // The context for the panel's connection, None if it has already been taken. It is kept in
+    // a static because it is too big for the stack and is reused for every connection.
+    pub fn take() -> Option<Pin<&'static mut TlsContext>> {
+        if CONTEXT_TAKEN.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        // CONTEXT_TAKEN makes this the only reference to CONTEXT there will ever be
+        let context = unsafe { &mut *addr_of_mut!(CONTEXT) };
+        // a static is never moved
+        let context = unsafe { Pin::new_unchecked(context) };
+        Some(Self::init(context))
+    }
+
+    // Sets up the context in place. The BearSSL structs start out as all zeroes, which is what
+    // BearSSL's own init functions start from, and reset() fills them in for each connection. The
+    // Rust fields are written without reading what was there.
+    fn init(context: Pin<&'static mut MaybeUninit<TlsContext>>) -> Pin<&'static mut TlsContext> {
+        // only fields are written, nothing is moved out
+        let context = unsafe { Pin::get_unchecked_mut(context) };
+        let raw = context.as_mut_ptr();
+        unsafe {
+            ptr::write_bytes(raw, 0, 1);
+            addr_of_mut!((*raw).x509.vtable).write(&PINNING_X509_CLASS);
+            addr_of_mut!((*raw).x509.check).write(PinCheck::new(Pins::default()));
+            addr_of_mut!((*raw).session).write(None);
+        }
+
+        // every field has been initialised and the context stays where it is
+        unsafe { Pin::new_unchecked(context.assume_init_mut()) }
+    }
+
+    // sets everything up for a new connection, returns the session offered to the server
+    fn reset(&mut self, server_name: &[u8; MAX_SERVER_NAME_LEN + 1]) -> Result<bool, SslError> {
+        let trust = Trust::load();
+        let mut trust_anchor_count = 0;
+        for (trust_anchor, anchor) in self.trust_anchors.iter_mut().zip(trust.anchors()) {
+            *trust_anchor = build_trust_anchor(&anchor);
+            trust_anchor_count += 1;
+        }
+        rprintln!("[INF] {} trust anchors", trust_anchor_count);
+
+        self.x509.vtable = &PINNING_X509_CLASS;
+        self.x509.check = PinCheck::new(trust.pins());
+
+        // this zeroes the client context, the X.509 engine and the session
+        unsafe {
+            br_ssl_client_init_full(
+                &mut self.client,
+                &mut self.x509.minimal,
+                self.trust_anchors.as_ptr(),
+                trust_anchor_count,
+            )
+        };
+
+        // the minimal engine is set up, put the pin check in front of it
+        self.client.eng.x509ctx = &mut self.x509.vtable;
+
+        // the panel's certificate, sent if the server asks for one
+        if let Some(identity) = identity::stored() {
+            self.client_certificate = br_x509_certificate {
+                data: identity.certificate.as_ptr() as *mut u8,
+                data_len: identity.certificate.len(),
+            };
+            self.client_key = br_ec_private_key {
+                curve: identity.curve as cty::c_int,
+                x: identity.key.as_ptr() as *mut u8,
+                xlen: identity.key.len(),
+            };
+
+            unsafe {
+                br_ssl_client_set_single_ec(
+                    &mut self.client,
+                    &self.client_certificate,
+                    1,
+                    &self.client_key,
+                    BR_KEYTYPE_SIGN,
+                    BR_KEYTYPE_EC, // the issuer's key type only matters for static ECDH
+                    br_ec_get_default(),
+                    br_ecdsa_sign_asn1_get_default(),
+                )
+            };
+        }
+
+        // BearSsl copies the entropy into its own random number generator
+        let entropy = entropy::output().ok_or(SslError::NoEntropy)?;
+        unsafe {
+            br_ssl_engine_inject_entropy(
+                &mut self.client.eng,
+                entropy.as_ptr() as *const cty::c_void,
+                entropy.len(),
+            )
+        };
+
+        unsafe {
+            br_ssl_engine_set_buffer(
+                &mut self.client.eng,
+                self.io_buf.as_mut_ptr() as *mut cty::c_void,
+                self.io_buf.len(),
+                0, // half duplex
+            )
+        };
+        // BR_ERR_BAD_PARAM if the buffer is too small
+        if self.client.eng.err != 0 {
+            return Err(self.error());
+        }
+
+        // a session is only offered to the server it was made with
+        // (br_ssl_engine_set_session_parameters() is inline in C so the copy is done here)
+        let resume = match self.session.take() {
+            Some(session) if session.server_name == *server_name => {
+                self.client.eng.session = session.parameters;
+                true
+            }
+            _ => false,
+        };
+
+        let reset = unsafe {
+            br_ssl_client_reset(&mut self.client, server_name.as_ptr(), resume as cty::c_int)
+        };
+        if reset == 0 {
+            return Err(self.error());
+        }
+
+        Ok(resume)
+    }
+
+    fn eng(&mut self) -> *mut br_ssl_engine_context {
+        &mut self.client.eng
+    }
+
+    fn state(&mut self) -> cty::c_uint {
+        unsafe { br_ssl_engine_current_state(self.eng()) }
+    }
+
+    // why the engine closed
+    fn error(&self) -> SslError {
+        match TlsError::from_code(self.client.eng.err) {
+            None => SslError::Closed,
+            Some(error) => {
+                rprintln!("[ERR] TLS failed: {}", error);
+                SslError::Engine(error)
+            }
+        }
+    }
+}
+
+pub struct SslStream<'a, 'c> {
+    stream: TcpStream<'a>,
+    context: Pin<&'c mut TlsContext>,
+    // the session saved from an earlier connection was offered to the server
+    offered_session: Option<br_ssl_session_parameters>,
+    server_name: [u8; MAX_SERVER_NAME_LEN + 1],
+}
+
+impl<'a, 'c> SslStream<'a, 'c> {
+    // Starts a TLS connection over stream, the handshake runs in handshake(). server_name is sent
+    // with SNI and must match the server certificate, it is the same host name the websocket
+    // connects to. The session from the last connection to the same server is offered to it.
+    pub fn new(
+        stream: TcpStream<'a>,
+        context: Pin<&'c mut TlsContext>,
+        server_name: &str,
+    ) -> Result<Self, SslError> {
+        if server_name.is_empty()
+            || server_name.len() > MAX_SERVER_NAME_LEN
+            || server_name.contains('\0')
+        {
+            return Err(SslError::InvalidServerName);
+        }
+
+        // null terminated for BearSSL
+        let mut terminated_name = [0; MAX_SERVER_NAME_LEN + 1];
+        terminated_name[..server_name.len()].copy_from_slice(server_name.as_bytes());
+
+        let mut ssl = Self {
+            stream,
+            context,
+            offered_session: None,
+            server_name: terminated_name,
+        };
+
+        let context = ssl.context();
+        if context.reset(&terminated_name)? {
+            let offered = context.client.eng.session;
+            ssl.offered_session = Some(offered);
+        }
+
+        Ok(ssl)
+    }
+
+    // Runs the TLS handshake now rather than on the first read or write of the websocket so that
+    // its failures are told apart, then saves the session for the next connection
+    pub fn handshake(&mut self) -> Result<(), SslError> {
+        loop {
+            let state = self.context().state();
+
+            // the engine takes application data once the handshake is done
+            if state & BR_SSL_SENDAPP != 0 {
+                break;
+            }
+
+            if state & BR_SSL_CLOSED != 0 {
+                return Err(self.context().error());
+            }
+
+            self.transfer_record(state)?;
+        }
+
+        let server_name = self.server_name;
+        let context = self.context();
+        let parameters = context.client.eng.session;
+        if parameters.session_id_len > 0 {
+            context.session = Some(Session {
+                parameters,
+                server_name,
+            });
+        }
+
+        Ok(())
+    }
+
+    // true if the server took up the session offered to it, call this after handshake()
+    pub fn resumed(&self) -> bool {
+        let offered = match self.offered_session {
+            Some(offered) => offered,
+            None => return false,
+        };
+
+        let current = &self.context.client.eng.session;
+        let len = offered.session_id_len as usize;
+        len > 0
+            && current.session_id_len == offered.session_id_len
+            && current.session_id[..len] == offered.session_id[..len]
+    }
+
+    // nothing is ever moved out of the context so it stays pinned
+    fn context(&mut self) -> &mut TlsContext {
+        unsafe { self.context.as_mut().get_unchecked_mut() }
+    }
+
+    // Moves one record between the engine and the tcp stream. Doing this here rather than
+    // letting br_sslio_* do it through callbacks means a read timeout is returned as an error and
+    // leaves the engine alone, so the connection can still be used afterwards.
+    fn transfer_record(&mut self, state: cty::c_uint) -> Result<(), SslError> {
+        let eng = self.context().eng();
+
+        if state & BR_SSL_SENDREC != 0 {
+            let mut len = 0;
+            let data = unsafe { br_ssl_engine_sendrec_buf(eng, &mut len) };
+            let buf = unsafe { slice::from_raw_parts(data, len) };
+            self.stream.write_all(buf).map_err(SslError::Tcp)?;
+            unsafe { br_ssl_engine_sendrec_ack(eng, len) };
+            Ok(())
+        } else if state & BR_SSL_RECVREC != 0 {
+            let mut len = 0;
+            let data = unsafe { br_ssl_engine_recvrec_buf(eng, &mut len) };
+            let buf = unsafe { slice::from_raw_parts_mut(data, len) };
+            let read_len = self.stream.read(buf).map_err(SslError::Tcp)?;
+            unsafe { br_ssl_engine_recvrec_ack(eng, read_len) };
+            Ok(())
+        } else {
+            // waiting on the application, which is not what the caller expected
+            Err(SslError::Engine(TlsError::Engine(EngineError::BadState)))
+        }
+    }
+}
+
+impl<'a, 'c> Transport for SslStream<'a, 'c> {
+    type Error = SslError;
+
+    fn set_read_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.stream.set_read_timeout(timeout_ms);
+    }
+
+    fn is_timeout(error: &SslError) -> bool {
+        matches!(error, SslError::Tcp(TcpError::Timeout))
+    }
+
+    // drops the underlying tcp connection without a TLS close_notify exchange
+    fn close(&mut self) -> Result<(), SslError> {
+        self.stream.close().map_err(SslError::Tcp)
+    }
+
+    fn sync_time(&mut self) -> Result<(), TimeError> {
+        self.stream.sync_time()
+    }
+}
+
+impl<'a, 'c> Stream<SslError> for SslStream<'a, 'c> {
+    // decrypted application data, reading records from the tcp stream until there is some
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, SslError> {
+        loop {
+            let state = self.context().state();
+
+            if state & BR_SSL_RECVAPP != 0 {
+                let eng = self.context().eng();
+                let mut len = 0;
+                let data = unsafe { br_ssl_engine_recvapp_buf(eng, &mut len) };
+                let len = len.min(buf.len());
+                buf[..len].copy_from_slice(unsafe { slice::from_raw_parts(data, len) });
+                unsafe { br_ssl_engine_recvapp_ack(eng, len) };
+                return Ok(len);
+            }
+
+            if state & BR_SSL_CLOSED != 0 {
+                return Err(self.context().error());
+            }
+
+            self.transfer_record(state)?;
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), SslError> {
+        let mut remaining = buf;
+        while !remaining.is_empty() {
+            let state = self.context().state();
+
+            if state & BR_SSL_SENDAPP != 0 {
+                let eng = self.context().eng();
+                let mut len = 0;
+                let data = unsafe { br_ssl_engine_sendapp_buf(eng, &mut len) };
+                let len = len.min(remaining.len());
+                unsafe { slice::from_raw_parts_mut(data, len) }.copy_from_slice(&remaining[..len]);
+                unsafe { br_ssl_engine_sendapp_ack(eng, len) };
+                remaining = &remaining[len..];
+                continue;
+            }
+
+            if state & BR_SSL_CLOSED != 0 {
+                return Err(self.context().error());
+            }
+
+            self.transfer_record(state)?;
+        }
+
+        // send it now rather than when the buffer fills up
+        unsafe { br_ssl_engine_flush(self.context().eng(), 0) };
+        loop {
+            let state = self.context().state();
+
+            if state & BR_SSL_CLOSED != 0 {
+                return Err(self.context().error());
+            }
+
+            if state & BR_SSL_SENDREC == 0 {
+                return Ok(());
+            }
+
+            self.transfer_record(state)?;
+        }
+    }
+}
@@ -1,19 +1,26 @@
-use crate::{
-    time::{set_time, TimeError},
-    SpiPhysical, W5500Error, W5500Physical,
-};
+#[cfg(feature = "ntp")]
+use crate::entropy;
+#[cfg(feature = "ntp")]
+use crate::time::{self, TimeError};
+use crate::{clock, transport::Transport, SpiPhysical, W5500Error, W5500Physical};
 use core::cell::RefCell;
 use cortex_m::prelude::_embedded_hal_blocking_delay_DelayMs;
 use embedded_websocket::framer::Stream;
 use stm32f1xx_hal::delay::Delay;
 use w5500::{IpAddress, MacAddress, Socket, SocketStatus};
 
+// used to fetch the time while Socket0 carries the websocket
+#[cfg(feature = "ntp")]
+const NTP_SOCKET: Socket = Socket::Socket1;
+
 #[derive(Debug)]
 pub enum TcpError {
     Io(W5500Error),
     Closed,
     SocketStatusNone,
+    #[cfg(feature = "ntp")]
     Time(TimeError),
+    Timeout,
 }
 
 impl From<W5500Error> for TcpError {
@@ -41,6 +48,7 @@ pub struct TcpStream<'a> {
     connection: Connection,
     delay: &'a RefCell<Delay>,
     spi: &'a RefCell<SpiPhysical>,
+    read_timeout_ms: Option<u32>,
 }
 
 impl<'a> TcpStream<'a> {
@@ -56,12 +64,11 @@ impl<'a> TcpStream<'a> {
             connection,
             delay,
             spi,
+            read_timeout_ms: None,
         }
     }
 
     pub fn connect(&mut self, host_ip: &IpAddress, host_port: u16) -> Result<(), TcpError> {
-        rprintln!("[INF] Connecting to {}:{}", host_ip, host_port);
-
         let spi = &mut *self.spi.borrow_mut();
         let delay = &mut *self.delay.borrow_mut();
         let w5500 = &mut self.w5500;
@@ -72,19 +79,53 @@ impl<'a> TcpStream<'a> {
         w5500.set_ip(spi, &IpAddress::new(192, 168, 1, 33))?;
         w5500.set_gateway(spi, &IpAddress::new(192, 168, 1, 1))?;
 
-        set_time(w5500, Socket::Socket0, delay, spi).map_err(TcpError::Time)?;
+        // only go back to the NTP server if we never got the time or it is getting old so that a
+        // recurring TCP or TLS error does not spam it
+        #[cfg(feature = "ntp")]
+        if !time::is_set() || time::resync_due() {
+            time::set_time(w5500, NTP_SOCKET, delay, spi).map_err(TcpError::Time)?;
+        }
 
+        rprintln!("[INF] Connecting to {}:{}", host_ip, host_port);
         w5500.set_protocol(spi, self.connection.socket, w5500::Protocol::TCP)?;
         w5500.dissconnect(spi, self.connection.socket)?;
         w5500.open_tcp(spi, self.connection.socket)?;
-        w5500.connect(spi, Socket::Socket0, host_ip, host_port)?;
+        w5500.connect(spi, self.connection.socket, host_ip, host_port)?;
 
         wait_for_is_connected(w5500, spi, &mut self.connection, delay)?;
+        #[cfg(feature = "ntp")]
+        entropy::add_network_timing();
         rprintln!("[INF] Client connected");
         Ok(())
     }
 }
 
+impl<'a> Transport for TcpStream<'a> {
+    type Error = TcpError;
+
+    fn set_read_timeout(&mut self, timeout_ms: Option<u32>) {
+        self.read_timeout_ms = timeout_ms;
+    }
+
+    fn is_timeout(error: &TcpError) -> bool {
+        matches!(error, TcpError::Timeout)
+    }
+
+    fn close(&mut self) -> Result<(), TcpError> {
+        rprintln!("[INF] Closing socket");
+        let spi = &mut *self.spi.borrow_mut();
+        self.w5500.dissconnect(spi, self.connection.socket)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "ntp")]
+    fn sync_time(&mut self) -> Result<(), TimeError> {
+        let spi = &mut *self.spi.borrow_mut();
+        let delay = &mut *self.delay.borrow_mut();
+        time::set_time(self.w5500, NTP_SOCKET, delay, spi)
+    }
+}
+
 fn wait_for_is_connected(
     w5500: &mut W5500Physical,
     spi: &mut SpiPhysical,
@@ -116,9 +157,9 @@ fn wait_for_is_connected(
 
 impl<'a> Stream<TcpError> for TcpStream<'a> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, TcpError> {
-        rprintln!("[INF] Read: Waiting for bytes");
         let spi = &mut *self.spi.borrow_mut();
         let delay = &mut *self.delay.borrow_mut();
+        let start_ms = clock::now_ms();
 
         loop {
             wait_for_is_connected(&mut self.w5500, spi, &mut self.connection, delay)?;
@@ -131,6 +172,12 @@ impl<'a> Stream<TcpError> for TcpStream<'a> {
                     return Ok(len);
                 }
                 None => {
+                    if let Some(timeout_ms) = self.read_timeout_ms {
+                        if clock::elapsed_ms(start_ms) >= timeout_ms {
+                            return Err(TcpError::Timeout);
+                        }
+                    }
+
                     delay.delay_ms(10_u16);
                 }
             };
@@ -0,0 +1,193 @@
+use crate::{clock, entropy, SpiPhysical, W5500Error, W5500Physical};
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use cortex_m::prelude::_embedded_hal_blocking_delay_DelayMs;
+use led_display_core::{
+    calendar::{DateTime, Dst, TimeZone},
+    sntp::{self, Client, QueryError, SntpError, Udp},
+    timekeeping::Timekeeper,
+};
+use stm32f1xx_hal::delay::Delay;
+use w5500::{IpAddress, Socket};
+
+// local time zone for the clock and brightness schedule, for example
+//   Central European Time: TimeZone { offset_secs: 3600, dst: Dst::Eu }
+//   US Eastern Time: TimeZone { offset_secs: -5 * 3600, dst: Dst::Us }
+const TIME_ZONE: TimeZone = TimeZone {
+    offset_secs: 0,
+    dst: Dst::Eu,
+};
+
+// The crystal driving the millisecond clock drifts by a few seconds a day so the time is
+// fetched again every few hours while connected
+const RESYNC_INTERVAL_MS: u32 = 6 * 60 * 60 * 1000;
+const RESYNC_RETRY_MS: u32 = 5 * 60 * 1000;
+
+// how long we wait for a reply from each NTP server
+const NTP_TIMEOUT_MS: u32 = 2_000;
+
+struct TimeState {
+    timekeeper: Timekeeper,
+    // the SNTP client remembers which server answered last, see led-display-core/src/sntp.rs
+    // It is made on the first sync and taken out while a query is running.
+    client: Option<Client<'static>>,
+}
+
+// The time between syncs comes from the millisecond clock, see
+// led-display-core/src/timekeeping.rs. It is behind a mutex because BearSSL asks for the time
+// from within its own code.
+static TIME: Mutex<RefCell<TimeState>> = Mutex::new(RefCell::new(TimeState {
+    timekeeper: Timekeeper::new(RESYNC_INTERVAL_MS, RESYNC_RETRY_MS),
+    client: None,
+}));
+
+fn with_state<T>(f: impl FnOnce(&mut TimeState, u32) -> T) -> T {
+    cortex_m::interrupt::free(|cs| f(&mut TIME.borrow(cs).borrow_mut(), clock::now_ms()))
+}
+
+fn with_time<T>(f: impl FnOnce(&mut Timekeeper, u32) -> T) -> T {
+    with_state(|state, tick| f(&mut state.timekeeper, tick))
+}
+
+// true once the time has been fetched from an NTP server at least once
+pub fn is_set() -> bool {
+    cortex_m::interrupt::free(|cs| TIME.borrow(cs).borrow().timekeeper.is_set())
+}
+
+// true when the time is getting old and we have not asked for it recently
+pub fn resync_due() -> bool {
+    with_time(|time, tick| time.resync_due(tick))
+}
+
+// seconds since 1970, None if the time has never been fetched
+pub fn unix_time() -> Option<i64> {
+    with_time(|time, tick| time.now_ms(tick)).map(|now_ms| now_ms.div_euclid(1000))
+}
+
+// the current local date and time, None if the time has never been fetched
+pub fn local_time() -> Option<DateTime> {
+    unix_time().map(|now| TIME_ZONE.local(now))
+}
+
+#[derive(Debug)]
+pub enum TimeError {
+    Io(W5500Error),
+    // no server gave a valid reply, this is why the last one failed
+    Sntp(SntpError),
+}
+
+impl From<QueryError<W5500Error>> for TimeError {
+    fn from(err: QueryError<W5500Error>) -> TimeError {
+        match err {
+            QueryError::Io(err) => TimeError::Io(err),
+            QueryError::Sntp(err) => TimeError::Sntp(err),
+        }
+    }
+}
+
+impl From<W5500Error> for TimeError {
+    fn from(err: W5500Error) -> TimeError {
+        TimeError::Io(err)
+    }
+}
+
+// NTP servers to ask in turn, there is no DNS so these are addresses (pool.ntp.org, Google and
+// Cloudflare at the time of writing)
+const NTP_SERVERS: [[u8; 4]; 4] = [
+    [212, 71, 255, 35],
+    [216, 239, 35, 0],
+    [162, 159, 200, 1],
+    [216, 239, 35, 4],
+];
+
+// how many requests a sync makes before giving up, spread over the servers
+const NTP_ATTEMPTS: usize = 4;
+
+// the W5500 socket set aside for NTP
+struct NtpSocket<'a> {
+    w5500: &'a mut W5500Physical,
+    socket: Socket,
+    delay: &'a mut Delay,
+    spi: &'a mut SpiPhysical,
+}
+
+impl<'a> Udp for NtpSocket<'a> {
+    type Error = W5500Error;
+
+    fn send(&mut self, server: [u8; 4], packet: &[u8]) -> Result<(), W5500Error> {
+        let [a, b, c, d] = server;
+        let host = IpAddress::new(a, b, c, d);
+        self.w5500
+            .send_udp(self.spi, self.socket, 0, &host, sntp::PORT, packet)
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<([u8; 4], usize)>, W5500Error> {
+        match self.w5500.try_receive_udp(self.spi, self.socket, buf)? {
+            Some((ip, port, len)) if port == sntp::PORT => Ok(Some((ip.address, len))),
+            Some(_) => Ok(None),
+            None => {
+                self.delay.delay_ms(10_u16);
+                Ok(None)
+            }
+        }
+    }
+
+    fn now_ms(&mut self) -> u32 {
+        clock::now_ms()
+    }
+}
+
+// NOTE: this should only be called AFTER the w5500 has been correctly set up
+pub fn set_time(
+    w5500: &mut W5500Physical,
+    socket: Socket,
+    delay: &mut Delay,
+    spi: &mut SpiPhysical,
+) -> Result<(), TimeError> {
+    // note that even though the MapleMini has a Real Time Clock built in we cannot rely on it
+    // because we do not know if there will be constant power to keep the clock running and
+    // the MapleMini Clone is missing the 32768 hz crystal that makes the Rtc tick every second
+    // so we don't know how long a tick takes. If you set the frequency to 625hz then you get something
+    // close to a second although there is drift.
+    // Instead the time fetched from an NTP server is kept going by the millisecond clock between
+    // syncs (see TIME above) and fetched again every few hours to correct the drift.
+
+    // add a delay here so that we don't spam the NTP server if our chip keeps restarting
+    delay.delay_ms(250_u16);
+
+    // the query waits on the network so the client is taken out rather than held in a critical
+    // section, and put back whatever the outcome
+    let (base_ms, client) = with_state(|state, tick| {
+        state.timekeeper.on_attempt(tick);
+        (state.timekeeper.query_base_ms(tick), state.client.take())
+    });
+    // the pool is set up at boot, before the first query, so the clock is only a fallback
+    let mut client = client.unwrap_or_else(|| {
+        let seed = entropy::nonce().unwrap_or_else(clock::now_ms);
+        Client::new(&NTP_SERVERS, NTP_TIMEOUT_MS, NTP_ATTEMPTS, seed)
+    });
+
+    let result = w5500
+        .set_protocol(spi, socket, w5500::Protocol::UDP)
+        .map_err(TimeError::from)
+        .and_then(|()| {
+            let mut udp = NtpSocket {
+                w5500,
+                socket,
+                delay,
+                spi,
+            };
+            client.query(&mut udp, base_ms).map_err(TimeError::from)
+        });
+
+    with_state(|state, tick| {
+        state.client = Some(client);
+        result.map(|sample| state.timekeeper.on_sample(&sample, tick))
+    })?;
+
+    rprintln!(
+        "[INF] Fetched unix system time from NTP server: {}",
+        unix_time().unwrap_or(0)
+    );
+    Ok(())
+}
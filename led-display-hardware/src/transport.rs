@@ -0,0 +1,22 @@
+use core::fmt::Debug;
+use embedded_websocket::framer::Stream;
+
+// What the websocket needs from the connection underneath, a TcpStream or with the tls feature
+// an SslStream wrapping one. Reads and writes go through embedded-websocket's Stream trait.
+
+pub trait Transport: Stream<<Self as Transport>::Error> {
+    type Error: Debug;
+
+    // reads block forever by default, set a timeout to make them fail with an error for which
+    // is_timeout is true instead
+    fn set_read_timeout(&mut self, timeout_ms: Option<u32>);
+
+    fn is_timeout(error: &<Self as Transport>::Error) -> bool;
+
+    // drops the connection, the server may already be gone
+    fn close(&mut self) -> Result<(), <Self as Transport>::Error>;
+
+    // fetches the time from the NTP server again, the connection stays up
+    #[cfg(feature = "ntp")]
+    fn sync_time(&mut self) -> Result<(), crate::time::TimeError>;
+}
@@ -0,0 +1,166 @@
+use crate::bearssl::{br_ec_get_default, br_ec_public_key, br_ecdsa_vrfy_asn1_get_default};
+use led_display_core::{
+    trust::{Anchor, Pin, Pins, Summary, TrustError, TrustStore, MAX_LEN, PUBLIC_KEY_LEN},
+    x509::{self, PublicKey},
+};
+use stm32f1xx_hal::flash::{FlashSize, Parts, SectorSize};
+
+// Where SslStream gets its trust anchors and pins, see led-display-core/src/trust.rs
+// The built in ones are generated by build.rs from the certificates in ../certs. A trust store
+// sent by the server replaces them once its signature checks out against UPDATE_KEY, the public
+// key the firmware is built with (LED_DISPLAY_TRUST_KEY), so without a key they cannot be
+// replaced. A store also has to be newer than the one already in flash. It is written to flash
+// once the connection it came over has closed.
+// The trust store is kept in the page of flash below the entropy seed (see memory-tls.x) and is
+// used from the next connection on.
+
+// ANCHORS, PINS and UPDATE_KEY
+include!(concat!(env!("OUT_DIR"), "/trust_anchors.rs"));
+
+// offset of the trust store from the start of flash, it is only written when the server sends one
+const STORE_OFFSET: u32 = 126 * 1024;
+const FLASH_ADDRESS: usize = 0x0800_0000;
+
+// the trust store is saved after its length (u16), erased flash gives a length of 0xFFFF
+const LEN_LEN: usize = 2;
+
+// the anchors and pins for a connection
+pub struct Trust {
+    store: Option<TrustStore<'static>>,
+}
+
+impl Trust {
+    pub fn load() -> Self {
+        Self { store: stored() }
+    }
+
+    pub fn anchors(&self) -> impl Iterator<Item = Anchor<'static>> {
+        let built_in: &[Anchor] = match self.store {
+            Some(_) => &[],
+            None => &ANCHORS,
+        };
+
+        self.store
+            .into_iter()
+            .flat_map(|store| store.anchors())
+            .chain(built_in.iter().copied())
+    }
+
+    pub fn pins(&self) -> Pins {
+        match self.store {
+            Some(store) => store.pins(),
+            None => Pins::new(&PINS),
+        }
+    }
+}
+
+// A trust store sent by the server that has been checked and is waiting to be saved. BearSSL's
+// trust anchors point into the page of flash holding the current store for as long as the
+// connection lasts, so the page is only rewritten once the connection has closed, see save().
+pub struct Update {
+    version: u32,
+    len: usize,
+    data: [u8; MAX_LEN],
+}
+
+impl Update {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // Saves the trust store in flash for the next connection. Call this only while no TLS
+    // connection is open, TlsContext::reset() reads the anchors again before the next handshake.
+    pub fn save(&self, flash: &mut Parts) -> Result<(), TrustError> {
+        // flash is written a half word at a time so the length is rounded up
+        let mut page = [0xFF; LEN_LEN + MAX_LEN];
+        page[..LEN_LEN].copy_from_slice(&(self.len as u16).to_le_bytes());
+        page[LEN_LEN..LEN_LEN + self.len].copy_from_slice(&self.data[..self.len]);
+        let len = (LEN_LEN + self.len + 1) & !1;
+
+        let mut writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz128K);
+        writer
+            .erase(STORE_OFFSET, 1024)
+            .and_then(|()| writer.write(STORE_OFFSET, &page[..len]))
+            .map_err(|error| {
+                rprintln!("[ERR] Could not save the trust store: {:?}", error);
+                TrustError::Storage
+            })
+    }
+}
+
+// Checks a trust store sent by the server, it has to be newer than both the store in flash and
+// the one waiting to be saved (pending) if there is one
+pub fn check(data: &[u8], pending: Option<&Update>) -> Result<(Update, Summary), TrustError> {
+    let key = UPDATE_KEY.as_ref().ok_or(TrustError::Unsupported)?;
+    let store = TrustStore::parse(data, |hash, signature| verify(key, hash, signature))?;
+    let newest = stored()
+        .map(|current| current.version())
+        .into_iter()
+        .chain(pending.map(Update::version))
+        .max();
+    if let Some(newest) = newest {
+        if store.version() <= newest {
+            return Err(TrustError::Outdated);
+        }
+    }
+
+    // parse() has checked the length
+    let mut update = Update {
+        version: store.version(),
+        len: data.len(),
+        data: [0; MAX_LEN],
+    };
+    update.data[..data.len()].copy_from_slice(data);
+    Ok((update, store.summary()))
+}
+
+// the trust store in flash if there is one and it is signed with UPDATE_KEY
+fn stored() -> Option<TrustStore<'static>> {
+    let key = UPDATE_KEY.as_ref()?;
+
+    // flash is memory mapped and only changes in update(), after the handshake that used it
+    let address = FLASH_ADDRESS + STORE_OFFSET as usize;
+    let page: &'static [u8] =
+        unsafe { core::slice::from_raw_parts(address as *const u8, LEN_LEN + MAX_LEN) };
+    let len = u16::from_le_bytes([page[0], page[1]]) as usize;
+    if len > MAX_LEN {
+        return None;
+    }
+
+    let data = &page[LEN_LEN..LEN_LEN + len];
+    match TrustStore::parse(data, |hash, signature| verify(key, hash, signature)) {
+        Ok(store) => Some(store),
+        Err(error) => {
+            rprintln!("[WRN] Ignoring the trust store in flash: {:?}", error);
+            None
+        }
+    }
+}
+
+// checks an ECDSA signature (ASN.1) of a SHA-256 hash against the P-256 key
+fn verify(key: &[u8; PUBLIC_KEY_LEN], hash: &[u8; 32], signature: &[u8]) -> bool {
+    // BearSSL only reads the key, the pointer is not const because the struct is shared with
+    // keys it fills in itself
+    let public_key = br_ec_public_key {
+        curve: x509::SECP256R1 as cty::c_int,
+        q: key.as_ptr() as *mut cty::c_uchar,
+        qlen: key.len(),
+    };
+
+    // the hash, key and signature outlive the call and BearSSL keeps no pointers to them
+    unsafe {
+        match br_ecdsa_vrfy_asn1_get_default() {
+            Some(vrfy) => {
+                vrfy(
+                    br_ec_get_default(),
+                    hash.as_ptr() as *const cty::c_void,
+                    hash.len(),
+                    &public_key,
+                    signature.as_ptr() as *const cty::c_void,
+                    signature.len(),
+                ) == 1
+            }
+            None => false,
+        }
+    }
+}
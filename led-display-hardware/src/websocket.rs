@@ -0,0 +1,68 @@
+use crate::transport::Transport;
+use embedded_websocket::{
+    framer::{Framer, FramerError, ReadResult},
+    Client, EmptyRng, WebSocketCloseStatusCode, WebSocketSendMessageType,
+};
+use led_display_core::session::{Connection, Received};
+
+// The websocket to the server as seen by the session loop in led-display-core
+// The opening handshake must already be done and the stream should have a read timeout of 0 so
+// that reads return straight away when there is nothing to read.
+
+pub struct WebSocket<'a, S> {
+    framer: Framer<'a, EmptyRng, Client>,
+    stream: &'a mut S,
+}
+
+impl<'a, S> WebSocket<'a, S> {
+    pub fn new(framer: Framer<'a, EmptyRng, Client>, stream: &'a mut S) -> Self {
+        Self { framer, stream }
+    }
+}
+
+impl<'a, S: Transport> Connection for WebSocket<'a, S> {
+    type Error = FramerError<S::Error>;
+
+    fn read<'b>(&mut self, buf: &'b mut [u8]) -> Result<Received<'b>, Self::Error> {
+        match self.framer.read(self.stream, buf) {
+            Ok(ReadResult::Text(text)) => Ok(Received::Text(text)),
+            Ok(ReadResult::Binary(data)) => Ok(Received::Binary(data)),
+            Ok(ReadResult::Pong(_)) => Ok(Received::Pong),
+            Ok(ReadResult::Closed) => Ok(Received::Closed),
+            Err(FramerError::Io(error)) if S::is_timeout(&error) => Ok(Received::Nothing),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn send_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        self.framer.write(
+            self.stream,
+            WebSocketSendMessageType::Text,
+            true,
+            text.as_bytes(),
+        )
+    }
+
+    fn send_ping(&mut self) -> Result<(), Self::Error> {
+        self.framer
+            .write(self.stream, WebSocketSendMessageType::Ping, true, &[])
+    }
+
+    fn close(&mut self) -> Result<(), Self::Error> {
+        // best effort, the server is probably not there anymore
+        let _ = self
+            .framer
+            .close(self.stream, WebSocketCloseStatusCode::NormalClosure, None);
+        self.stream.close().map_err(FramerError::Io)
+    }
+
+    // keep the clock right, the panel stands still for as long as this takes
+    #[cfg(feature = "ntp")]
+    fn on_quiet(&mut self) {
+        if crate::time::resync_due() {
+            if let Err(error) = self.stream.sync_time() {
+                rprintln!("[WRN] Could not fetch the time: {:?}", error);
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use image::{gif, AnimationDecoder, DynamicImage, FilterType, GenericImageView, ImageFormat};
+use std::fmt;
+use std::io::Cursor;
+
+/// Size of the led panels in pixels (20 daisy chained 8x8 modules)
+const PANEL_WIDTH: u32 = 160;
+const PANEL_HEIGHT: u32 = 8;
+
+/// Largest bitmap the panels can hold, must match MAX_BITMAP_LEN in the firmware
+const MAX_BITMAP_LEN: usize = 512;
+
+/// Binary frame layout understood by the panels (see protocol.rs in the firmware)
+const BITMAP: u8 = 1;
+const TIMED: u8 = 0x01;
+const ZONED: u8 = 0x02;
+/// Longest zone name, must match zone::MAX_NAME_LEN in the firmware
+const MAX_ZONE_NAME_LEN: usize = 8;
+
+#[derive(Debug)]
+pub enum BitmapError {
+    Image(image::ImageError),
+    NoFrames,
+    TooLarge { len: usize, max_frames: usize },
+    ZoneName(String),
+}
+
+impl fmt::Display for BitmapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BitmapError::Image(err) => write!(f, "unable to read image: {}", err),
+            BitmapError::NoFrames => write!(f, "image has no frames"),
+            BitmapError::TooLarge { len, max_frames } => write!(
+                f,
+                "bitmap is {} bytes, the panels can hold {} bytes ({} frames at this width)",
+                len, MAX_BITMAP_LEN, max_frames
+            ),
+            BitmapError::ZoneName(name) => write!(
+                f,
+                "zone name {:?} must be between 1 and {} bytes",
+                name, MAX_ZONE_NAME_LEN
+            ),
+        }
+    }
+}
+
+impl From<image::ImageError> for BitmapError {
+    fn from(err: image::ImageError) -> Self {
+        BitmapError::Image(err)
+    }
+}
+
+/// Converts a PNG or GIF (or any other format the image crate reads) into a binary frame for
+/// the led panels. Images are scaled down to fit the panel and dark pixels are lit.
+/// Animated GIFs keep their frame timings. The bitmap goes to `zone` on the panels, or the
+/// default zone when there is none.
+pub fn encode(data: &[u8], zone: Option<&str>) -> Result<Vec<u8>, BitmapError> {
+    if let Some(name) = zone {
+        if name.is_empty() || name.len() > MAX_ZONE_NAME_LEN {
+            return Err(BitmapError::ZoneName(name.to_string()));
+        }
+    }
+
+    let (images, delays) = match image::guess_format(data)? {
+        ImageFormat::GIF => {
+            let frames = gif::Decoder::new(Cursor::new(data))?
+                .into_frames()
+                .collect_frames()?;
+            let delays = frames
+                .iter()
+                .map(|frame| frame.delay().to_integer())
+                .collect();
+            let images = frames
+                .into_iter()
+                .map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+                .collect();
+            (images, Some(delays))
+        }
+        _ => (vec![image::load_from_memory(data)?], None),
+    };
+
+    encode_frames(&images, delays.as_ref().map(Vec::as_slice), zone)
+}
+
+fn encode_frames(
+    images: &[DynamicImage],
+    delays: Option<&[u16]>,
+    zone: Option<&str>,
+) -> Result<Vec<u8>, BitmapError> {
+    let first = images.first().ok_or(BitmapError::NoFrames)?;
+    let (width, _) = fit(first).dimensions();
+    let width = width as usize;
+    let frame_len = width + if delays.is_some() { 2 } else { 0 };
+
+    let len = frame_len * images.len();
+    let max_frames = (MAX_BITMAP_LEN / frame_len).min(u8::MAX as usize);
+    if images.len() > max_frames {
+        return Err(BitmapError::TooLarge { len, max_frames });
+    }
+
+    let mut flags = if delays.is_some() { TIMED } else { 0 };
+    if zone.is_some() {
+        flags |= ZONED;
+    }
+
+    let mut frame = vec![BITMAP, flags, width as u8, images.len() as u8];
+    if let Some(name) = zone {
+        frame.push(name.len() as u8);
+        frame.extend_from_slice(name.as_bytes());
+    }
+    for (index, image) in images.iter().enumerate() {
+        if let Some(delays) = delays {
+            frame.extend_from_slice(&delays[index].to_le_bytes());
+        }
+
+        frame.extend(columns(&fit(image), width));
+    }
+
+    Ok(frame)
+}
+
+/// Scales the image down (keeping its aspect ratio) if it does not fit on the panel
+fn fit(image: &DynamicImage) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    if width <= PANEL_WIDTH && height <= PANEL_HEIGHT {
+        image.clone()
+    } else {
+        image.resize(PANEL_WIDTH, PANEL_HEIGHT, FilterType::Triangle)
+    }
+}
+
+/// One byte per column with bit 0 at the top, dark opaque pixels are lit
+fn columns(image: &DynamicImage, width: usize) -> Vec<u8> {
+    let pixels = image.to_luma_alpha();
+    (0..width as u32)
+        .map(|x| {
+            (0..pixels.height().min(PANEL_HEIGHT)).fold(0, |bits, y| {
+                let pixel = if x < pixels.width() {
+                    pixels.get_pixel(x, y).0
+                } else {
+                    [255, 0]
+                };
+
+                let [luma, alpha] = pixel;
+                if alpha >= 128 && luma < 128 {
+                    bits | 1 << y
+                } else {
+                    bits
+                }
+            })
+        })
+        .collect()
+}
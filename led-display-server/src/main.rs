@@ -7,32 +7,220 @@ use actix_broker::BrokerIssue;
 use actix_files::Files;
 use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
+use std::env;
+use std::io;
 use std::time::{Duration, Instant};
 
+mod bitmap;
+mod operator;
+mod panels;
 mod server;
+use operator::Operator;
+use panels::{PanelError, Panels};
 use server::*;
 
 /// How often heartbeat pings are sent (should be less than CLIENT_TIMEOUT)
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+/// First byte of a binary frame holding a trust store, must match TRUST_STORE in the firmware
+const TRUST_STORE: u8 = 2;
+/// Largest trust store the panels can hold, must match trust::MAX_LEN in the firmware
+const MAX_TRUST_STORE_LEN: usize = 1022;
+/// Longest command forwarded to the panels, the same limit as chat messages
+const MAX_COMMAND_LEN: usize = 256;
+/// The websocket the panels connect to (`/ws/ledpanel`), when panels are configured it needs a
+/// panel's client certificate
+const PANEL_ROUTE: &str = "ledpanel";
 
+/// Opens a websocket, panels presenting a client certificate are named after it. Browsers connect
+/// without one and chat as before, a certificate that is sent has to be a panel's though.
 fn ws_route(
     room: web::Path<String>,
     req: HttpRequest,
     stream: web::Payload,
+    panels: web::Data<Option<Panels>>,
 ) -> Result<HttpResponse, Error> {
     info!("Route: ws/{}", room);
-    ws::start(WsSession::new(room.to_string()), &req, stream)
+    let panel = match panels.get_ref() {
+        Some(panels) => match panels.verify(&req) {
+            Ok(Some(panel)) => {
+                info!("Route: ws/{}, panel {}", room, panel);
+                Some(panel)
+            }
+            Ok(None) if room.as_str() == PANEL_ROUTE => {
+                info!("Route: ws/{}, no client certificate", room);
+                let err = PanelError::NoCertificate;
+                return Ok(HttpResponse::Forbidden().body(err.to_string()));
+            }
+            Ok(None) => None,
+            Err(err) => {
+                info!("Route: ws/{}, {}", room, err);
+                return Ok(HttpResponse::Forbidden().body(err.to_string()));
+            }
+        },
+        None => None,
+    };
+
+    // Without panel certificates there is no telling panels apart from anyone else, so reports
+    // are taken from every session and the panels are the ones on the panel websocket
+    let session = match (panels.get_ref(), panel) {
+        (Some(_), Some(panel)) => WsSession::new(panel, true, true),
+        (Some(_), None) => WsSession::new(room.to_string(), false, false),
+        (None, _) => WsSession::new(room.to_string(), room.as_str() == PANEL_ROUTE, true),
+    };
+
+    ws::start(session, &req, stream)
+}
+
+/// The panels that may identify themselves with a client certificate, PANEL_CA is the PEM file
+/// of the certificate authority that issues them, PANEL_DEVICES the list of panels and
+/// PANEL_PROXY the address of the TLS proxy that checks the certificates (127.0.0.1 by default)
+fn load_panels() -> Result<Option<Panels>, PanelError> {
+    let (ca, devices) = match (env::var("PANEL_CA"), env::var("PANEL_DEVICES")) {
+        (Ok(ca), Ok(devices)) => (ca, devices),
+        _ => {
+            info!("PANEL_CA and PANEL_DEVICES not set, panel client certificates are ignored");
+            return Ok(None);
+        }
+    };
+
+    let proxy = env::var("PANEL_PROXY").unwrap_or_else(|_| "127.0.0.1".to_string());
+    Panels::load(&ca, &devices, &proxy).map(Some)
+}
+
+/// Forwards a command (e.g. `speed 40`) to the led panels in the room, operators only
+/// e.g. `curl -H "Authorization: Bearer $OPERATOR_TOKEN" --data 'speed 40' http://localhost:8663/panel/rustdudes/command`
+/// Panels treat text starting with '#' as a command rather than a message to display.
+fn command_route(
+    room: web::Path<String>,
+    req: HttpRequest,
+    body: String,
+    operator: web::Data<Operator>,
+) -> HttpResponse {
+    if let Err(err) = operator.check(&req) {
+        info!("Route: panel/{}/command, {}", room, err);
+        return HttpResponse::Unauthorized().body(err.to_string());
+    }
+
+    let command = body.trim();
+    if command.is_empty() || command.len() > MAX_COMMAND_LEN || command.contains('\n') {
+        info!("Route: panel/{}/command, rejected {:?}", room, command);
+        return HttpResponse::BadRequest().body(format!(
+            "a command must be one line of between 1 and {} bytes",
+            MAX_COMMAND_LEN
+        ));
+    }
+
+    info!("Route: panel/{}/command, sending {:?}", room, command);
+    WsServer::from_registry().do_send(SendMessage(room.to_string(), 0, format!("#{}", command)));
+    HttpResponse::Ok().finish()
+}
+
+/// Converts an uploaded image (PNG or GIF) into a bitmap and sends it to the led panels in the
+/// room, operators only
+/// e.g. `curl -H "Authorization: Bearer $OPERATOR_TOKEN" --data-binary @logo.png http://localhost:8663/panel/rustdudes/bitmap`
+fn bitmap_route(
+    room: web::Path<String>,
+    req: HttpRequest,
+    body: web::Bytes,
+    operator: web::Data<Operator>,
+) -> HttpResponse {
+    send_bitmap(&room, None, &req, &body, &operator)
+}
+
+/// The same for one zone of the panels, e.g. `/panel/rustdudes/bitmap/clock`
+fn zone_bitmap_route(
+    path: web::Path<(String, String)>,
+    req: HttpRequest,
+    body: web::Bytes,
+    operator: web::Data<Operator>,
+) -> HttpResponse {
+    let (room, zone) = path.into_inner();
+    send_bitmap(&room, Some(&zone), &req, &body, &operator)
+}
+
+fn send_bitmap(
+    room: &str,
+    zone: Option<&str>,
+    req: &HttpRequest,
+    body: &[u8],
+    operator: &Operator,
+) -> HttpResponse {
+    if let Err(err) = operator.check(req) {
+        info!("Route: panel/{}/bitmap, {}", room, err);
+        return HttpResponse::Unauthorized().body(err.to_string());
+    }
+
+    match bitmap::encode(body, zone) {
+        Ok(frame) => {
+            info!("Route: panel/{}/bitmap, sending {} bytes", room, frame.len());
+            WsServer::from_registry().do_send(SendBinary(room.to_string(), frame));
+            HttpResponse::Ok().finish()
+        }
+        Err(err) => {
+            info!("Route: panel/{}/bitmap, {}", room, err);
+            HttpResponse::BadRequest().body(err.to_string())
+        }
+    }
+}
+
+/// Sends a signed trust store (made with led-display-certgen) to the led panels in the room,
+/// operators only
+/// e.g. `curl -H "Authorization: Bearer $OPERATOR_TOKEN" --data-binary @trust.bin http://localhost:8663/panel/rustdudes/truststore`
+/// The panels check the signature and report back whether they took it.
+fn trust_store_route(
+    room: web::Path<String>,
+    req: HttpRequest,
+    body: web::Bytes,
+    operator: web::Data<Operator>,
+) -> HttpResponse {
+    if let Err(err) = operator.check(&req) {
+        info!("Route: panel/{}/truststore, {}", room, err);
+        return HttpResponse::Unauthorized().body(err.to_string());
+    }
+
+    if body.is_empty() || body.len() > MAX_TRUST_STORE_LEN {
+        info!("Route: panel/{}/truststore, rejected {} bytes", room, body.len());
+        return HttpResponse::BadRequest().body(format!(
+            "a trust store must be between 1 and {} bytes",
+            MAX_TRUST_STORE_LEN
+        ));
+    }
+
+    info!("Route: panel/{}/truststore, sending {} bytes", room, body.len());
+    let mut frame = vec![TRUST_STORE];
+    frame.extend_from_slice(&body);
+    WsServer::from_registry().do_send(SendBinary(room.to_string(), frame));
+    HttpResponse::Ok().finish()
 }
 
 fn main() -> std::io::Result<()> {
     let sys = actix::System::new("ninjametal");
     simple_logger::init_with_level(log::Level::Info).unwrap();
+    let panels = load_panels().map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unable to load the panels: {}", err),
+        )
+    })?;
+    let panels = web::Data::new(panels);
+    let operator = web::Data::new(Operator::load());
 
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         App::new()
+            .register_data(panels.clone())
+            .register_data(operator.clone())
             .service(web::resource("/ws/{room}").route(web::get().to(ws_route)))
+            .service(web::resource("/panel/{room}/command").route(web::post().to(command_route)))
+            .service(web::resource("/panel/{room}/bitmap").route(web::post().to(bitmap_route)))
+            .service(
+                web::resource("/panel/{room}/bitmap/{zone}")
+                    .route(web::post().to(zone_bitmap_route)),
+            )
+            .service(
+                web::resource("/panel/{room}/truststore").route(web::post().to(trust_store_route)),
+            )
             .service(Files::new("/", "wwwroot/").index_file("index.html"))
     })
     //.bind("127.0.0.1:8663")
@@ -48,15 +236,22 @@ struct WsSession {
     id: usize,
     room: String,
     name: Option<String>,
+    /// a led panel, it shows any text sent to it so it is not sent reports or replies
+    panel: bool,
+    /// whether `/report` is taken from this session, only from panels identified by their client
+    /// certificate when they are configured (see Panels::identify)
+    reports: bool,
     hb: Instant,
 }
 
 impl WsSession {
-    fn new(name: String) -> WsSession {
+    fn new(name: String, panel: bool, reports: bool) -> WsSession {
         WsSession {
             id: 0, // will get an id once they have joined a room
             room: "rustdudes".to_string(),
             name: Some(name),
+            panel,
+            reports,
             hb: Instant::now(),
         }
     }
@@ -157,7 +352,15 @@ impl Handler<ChatMessage> for WsSession {
     type Result = ();
 
     fn handle(&mut self, msg: ChatMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        match msg {
+            ChatMessage::Text(text) => ctx.text(text),
+            ChatMessage::Binary(data) => ctx.binary(data),
+            ChatMessage::Report(text) => {
+                if !self.panel {
+                    ctx.text(text)
+                }
+            }
+        }
     }
 }
 
@@ -185,6 +388,21 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for WsSession {
                                 ctx.text("!!! room name is required");
                             }
                         }
+                        Some("/report") => {
+                            // sent by the panels, e.g. the result of a self test, and shown to
+                            // the people in the room. Nothing is sent back, a panel would show it.
+                            let report = command.next().unwrap_or_default();
+                            let name = self.name.clone().unwrap_or_else(|| "anon".to_string());
+                            if self.reports {
+                                info!("Report from {} in room {}: {}", name, self.room, report);
+                                WsServer::from_registry().do_send(SendReport(
+                                    self.room.clone(),
+                                    format!("report from {}: {}", name, report),
+                                ));
+                            } else {
+                                info!("Ignoring a report from {}, not a panel", name);
+                            }
+                        }
                         Some("/name") => {
                             if let Some(name) = command.next() {
                                 self.name = Some(name.to_owned());
@@ -0,0 +1,71 @@
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::HttpRequest;
+use std::env;
+use std::fmt;
+
+/// Who may drive the panels directly: send them commands, bitmaps and trust stores
+///
+/// Operators present the token in `OPERATOR_TOKEN` as `Authorization: Bearer <token>`
+/// (`curl -H "Authorization: Bearer $OPERATOR_TOKEN" ...`). Without `OPERATOR_TOKEN` nobody is an
+/// operator and those routes are refused.
+pub struct Operator {
+    token: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum OperatorError {
+    NotConfigured,
+    Missing,
+    Wrong,
+}
+
+impl fmt::Display for OperatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OperatorError::NotConfigured => write!(f, "OPERATOR_TOKEN is not set on the server"),
+            OperatorError::Missing => write!(f, "an operator token is required"),
+            OperatorError::Wrong => write!(f, "the operator token is wrong"),
+        }
+    }
+}
+
+impl Operator {
+    pub fn load() -> Operator {
+        let token = env::var("OPERATOR_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        if token.is_none() {
+            info!("OPERATOR_TOKEN not set, panel commands, bitmaps and trust stores are refused");
+        }
+
+        Operator { token }
+    }
+
+    /// Whether the request comes from an operator
+    pub fn check(&self, req: &HttpRequest) -> Result<(), OperatorError> {
+        let token = self.token.as_ref().ok_or(OperatorError::NotConfigured)?;
+        let presented = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| {
+                let mut parts = header.splitn(2, ' ');
+                match (parts.next(), parts.next()) {
+                    (Some("Bearer"), Some(token)) => Some(token.trim()),
+                    _ => None,
+                }
+            })
+            .ok_or(OperatorError::Missing)?;
+
+        if constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+            Ok(())
+        } else {
+            Err(OperatorError::Wrong)
+        }
+    }
+}
+
+/// Compares without stopping at the first difference so the time taken gives nothing away
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
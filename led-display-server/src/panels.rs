@@ -0,0 +1,186 @@
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Header the TLS proxy in front of the server puts the client certificate in, URL encoded PEM
+/// (nginx: `proxy_set_header X-Client-Cert $ssl_client_escaped_cert;`). Anyone can set it so it
+/// is only read on connections from the proxy's address and ignored on all others.
+pub const CLIENT_CERT_HEADER: &str = "X-Client-Cert";
+
+/// Signature algorithms accepted on panel certificates
+static ALGORITHMS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+];
+
+#[derive(Debug)]
+pub enum PanelError {
+    Io(String, std::io::Error),
+    Pem(String),
+    DeviceList(String),
+    Certificate(webpki::Error),
+    Clock,
+    Unknown(String),
+    Proxy(String),
+    NoCertificate,
+}
+
+impl fmt::Display for PanelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PanelError::Io(path, err) => write!(f, "{}: {}", path, err),
+            PanelError::Pem(message) => write!(f, "invalid PEM: {}", message),
+            PanelError::DeviceList(message) => write!(f, "invalid device list: {}", message),
+            PanelError::Certificate(err) => write!(f, "client certificate rejected: {:?}", err),
+            PanelError::Clock => write!(f, "the system clock is not set"),
+            PanelError::Unknown(hash) => write!(f, "no panel has the certificate cert:{}", hash),
+            PanelError::Proxy(address) => write!(f, "invalid proxy address: {}", address),
+            PanelError::NoCertificate => write!(f, "a panel client certificate is required"),
+        }
+    }
+}
+
+impl From<webpki::Error> for PanelError {
+    fn from(err: webpki::Error) -> Self {
+        PanelError::Certificate(err)
+    }
+}
+
+/// The panels allowed to connect and the certificate authority that issued their certificates
+///
+/// Panels prove who they are with a client certificate (mutual TLS). The certificate must be
+/// valid, issued by the panel CA and listed in the device list, which maps the SHA-256 of each
+/// certificate to the panel's name, one per line as printed by `led-display-certgen hashes`:
+/// `cert:<sha256> lobby`. Blank lines and lines starting with `#` are ignored.
+///
+/// The certificates are checked by the TLS proxy at `proxy` and passed on in
+/// `CLIENT_CERT_HEADER`, see `verify()`.
+pub struct Panels {
+    ca: Vec<Vec<u8>>,
+    names: HashMap<String, String>,
+    proxy: IpAddr,
+}
+
+impl Panels {
+    pub fn load(ca_path: &str, devices_path: &str, proxy: &str) -> Result<Panels, PanelError> {
+        let proxy = proxy
+            .parse()
+            .map_err(|_| PanelError::Proxy(proxy.to_string()))?;
+
+        let read = |path: &str| {
+            fs::read_to_string(Path::new(path)).map_err(|err| PanelError::Io(path.to_string(), err))
+        };
+
+        let ca = decode_pem(&read(ca_path)?)?;
+        if ca.is_empty() {
+            return Err(PanelError::Pem(format!("no certificates in {}", ca_path)));
+        }
+
+        let mut names = HashMap::new();
+        for line in read(devices_path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(hash), Some(name)) if hash.starts_with("cert:") && hash.len() == 69 => {
+                    names.insert(hash[5..].to_lowercase(), name.to_string());
+                }
+                _ => return Err(PanelError::DeviceList(line.to_string())),
+            }
+        }
+
+        Ok(Panels { ca, names, proxy })
+    }
+
+    /// The name of the panel making the request, from the client certificate the proxy passed on.
+    /// None when there is no certificate, including when the request did not come through the
+    /// proxy (whatever is in the header then is ignored), an error when it is not a panel's.
+    pub fn verify(&self, req: &HttpRequest) -> Result<Option<String>, PanelError> {
+        let header = match req.headers().get(CLIENT_CERT_HEADER) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
+        let peer = req.peer_addr();
+        if peer.map(|peer| peer.ip()) != Some(self.proxy) {
+            info!(
+                "Ignoring {} from {:?}, it is not the panel proxy",
+                CLIENT_CERT_HEADER, peer
+            );
+            return Ok(None);
+        }
+
+        self.identify(header.to_str().unwrap_or("")).map(Some)
+    }
+
+    /// The name of the panel the client certificate belongs to, `header` is the value of
+    /// `CLIENT_CERT_HEADER`
+    pub fn identify(&self, header: &str) -> Result<String, PanelError> {
+        let pem = percent_encoding::percent_decode(header.as_bytes())
+            .decode_utf8()
+            .map_err(|err| PanelError::Pem(err.to_string()))?;
+        let der = decode_pem(&pem)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| PanelError::Pem("no certificate".to_string()))?;
+
+        let anchors = self
+            .ca
+            .iter()
+            .map(|ca| {
+                webpki::trust_anchor_util::cert_der_as_trust_anchor(untrusted::Input::from(&ca[..]))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let time = webpki::Time::try_from(SystemTime::now()).map_err(|_| PanelError::Clock)?;
+        webpki::EndEntityCert::from(untrusted::Input::from(&der[..]))?
+            .verify_is_valid_tls_client_cert(
+                ALGORITHMS,
+                &webpki::TLSClientTrustAnchors(&anchors),
+                &[],
+                time,
+            )?;
+
+        let hash: String = Sha256::digest(&der)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.names
+            .get(&hash)
+            .cloned()
+            .ok_or(PanelError::Unknown(hash))
+    }
+}
+
+/// Every certificate in PEM text, as DER
+fn decode_pem(text: &str) -> Result<Vec<Vec<u8>>, PanelError> {
+    const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+    const END: &str = "-----END CERTIFICATE-----";
+
+    let mut certificates = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(BEGIN) {
+        let body = &rest[start + BEGIN.len()..];
+        let end = body
+            .find(END)
+            .ok_or_else(|| PanelError::Pem("missing END CERTIFICATE".to_string()))?;
+        let base64: String = body[..end].split_whitespace().collect();
+        let der = base64::decode(&base64).map_err(|err| PanelError::Pem(err.to_string()))?;
+        certificates.push(der);
+        rest = &body[end + END.len()..];
+    }
+
+    Ok(certificates)
+}
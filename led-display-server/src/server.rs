@@ -6,7 +6,13 @@ use std::collections::HashMap;
 use std::mem;
 
 #[derive(Clone, Message)]
-pub struct ChatMessage(pub String);
+pub enum ChatMessage {
+    Text(String),
+    /// bitmaps for the led panels, browsers ignore these
+    Binary(Vec<u8>),
+    /// what a panel reported, for the people in the room rather than the other panels
+    Report(String),
+}
 
 #[derive(Clone, Message)]
 #[rtype(result = "usize")]
@@ -22,6 +28,14 @@ pub struct ListRooms;
 #[derive(Clone, Message)]
 pub struct SendMessage(pub String, pub usize, pub String);
 
+/// Sends a binary frame to everyone in the room
+#[derive(Clone, Message)]
+pub struct SendBinary(pub String, pub Vec<u8>);
+
+/// Sends a panel's report to the room, see ChatMessage::Report
+#[derive(Clone, Message)]
+pub struct SendReport(pub String, pub String);
+
 type Client = Recipient<ChatMessage>;
 type Room = HashMap<usize, Client>;
 #[derive(Default)]
@@ -57,9 +71,13 @@ impl WsServer {
     }
 
     fn send_chat_message(&mut self, room_name: &str, msg: &str, _src: usize) -> Option<()> {
+        self.send_to_room(room_name, ChatMessage::Text(msg.to_owned()))
+    }
+
+    fn send_to_room(&mut self, room_name: &str, msg: ChatMessage) -> Option<()> {
         let mut room = self.take_room(room_name)?;
         for (id, client) in room.drain() {
-            if client.do_send(ChatMessage(msg.to_owned())).is_ok() {
+            if client.do_send(msg.clone()).is_ok() {
                 self.add_client_to_room(room_name, Some(id), client);
             }
         }
@@ -113,5 +131,23 @@ impl Handler<SendMessage> for WsServer {
     }
 }
 
+impl Handler<SendBinary> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendBinary, _ctx: &mut Self::Context) {
+        let SendBinary(room_name, data) = msg;
+        self.send_to_room(&room_name, ChatMessage::Binary(data));
+    }
+}
+
+impl Handler<SendReport> for WsServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendReport, _ctx: &mut Self::Context) {
+        let SendReport(room_name, report) = msg;
+        self.send_to_room(&room_name, ChatMessage::Report(report));
+    }
+}
+
 impl SystemService for WsServer {}
 impl Supervised for WsServer {}
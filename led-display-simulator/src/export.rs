@@ -0,0 +1,155 @@
+use crate::{max7219::Panel, screen::Output};
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+// Saves the frames shown on the panel as an animated GIF or a directory of PNG files
+
+// every led is a square of LED_SIZE pixels with a gap of SCALE - LED_SIZE around it
+const SCALE: usize = 4;
+const LED_SIZE: usize = 3;
+
+const BACKGROUND: u8 = 0;
+const UNLIT: u8 = 1;
+// followed by one colour for every MAX7219 intensity (0-15)
+const LIT: u8 = 2;
+
+// the shortest delay most GIF viewers respect, in hundredths of a second
+const MIN_GIF_DELAY: u16 = 2;
+
+// a frame as palette indices, one byte per pixel
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+pub fn image_width(panel_width: usize) -> usize {
+    panel_width * SCALE
+}
+
+// rgb triples for the indices used by render()
+pub fn palette() -> Vec<u8> {
+    let mut palette = vec![0x10, 0x10, 0x10, 0x30, 0x10, 0x10];
+    for intensity in 0..16 {
+        palette.extend_from_slice(&[0x60 + intensity * 0x09, intensity * 0x03, intensity * 0x02]);
+    }
+    palette
+}
+
+pub fn render(panel: &Panel) -> Image {
+    let width = image_width(panel.width());
+    let height = 8 * SCALE;
+    let mut pixels = vec![BACKGROUND; width * height];
+    let lit = LIT + panel.intensity();
+
+    for y in 0..8 {
+        for x in 0..panel.width() {
+            let colour = if panel.is_lit(x, y) { lit } else { UNLIT };
+            for row in 0..LED_SIZE {
+                let start = (y * SCALE + row) * width + x * SCALE;
+                pixels[start..start + LED_SIZE].fill(colour);
+            }
+        }
+    }
+
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn to_io_error<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::other(err)
+}
+
+pub struct Gif {
+    encoder: gif::Encoder<BufWriter<File>>,
+    // the last frame is only written once we know how long it was shown for
+    pending: Option<(Image, u32)>,
+}
+
+impl Gif {
+    pub fn create(path: &Path, panel_width: usize) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let width = image_width(panel_width) as u16;
+        let height = (8 * SCALE) as u16;
+        let mut encoder =
+            gif::Encoder::new(file, width, height, &palette()).map_err(to_io_error)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(to_io_error)?;
+
+        Ok(Self {
+            encoder,
+            pending: None,
+        })
+    }
+
+    fn write_pending(&mut self, now_ms: u32) -> io::Result<()> {
+        let (image, shown_ms) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let delay = (now_ms.saturating_sub(shown_ms) / 10).min(u16::MAX as u32) as u16;
+        let frame = gif::Frame {
+            width: image.width as u16,
+            height: image.height as u16,
+            buffer: Cow::Borrowed(&image.pixels),
+            delay: delay.max(MIN_GIF_DELAY),
+            ..gif::Frame::default()
+        };
+
+        self.encoder.write_frame(&frame).map_err(to_io_error)
+    }
+}
+
+impl Output for Gif {
+    fn frame(&mut self, panel: &Panel, now_ms: u32) -> io::Result<()> {
+        self.write_pending(now_ms)?;
+        self.pending = Some((render(panel), now_ms));
+        Ok(())
+    }
+
+    fn finish(&mut self, now_ms: u32) -> io::Result<()> {
+        self.write_pending(now_ms)
+    }
+}
+
+// one file per frame named after the number of milliseconds since the simulator started
+pub struct PngFrames {
+    dir: PathBuf,
+}
+
+impl PngFrames {
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.into() })
+    }
+}
+
+impl Output for PngFrames {
+    fn frame(&mut self, panel: &Panel, now_ms: u32) -> io::Result<()> {
+        let image = render(panel);
+        let file = File::create(self.dir.join(format!("frame-{:08}.png", now_ms)))?;
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            image.width as u32,
+            image.height as u32,
+        );
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(palette());
+
+        let mut writer = encoder.write_header().map_err(to_io_error)?;
+        writer.write_image_data(&image.pixels).map_err(to_io_error)
+    }
+}
@@ -0,0 +1,244 @@
+use led_display_core::{
+    app::{App, Board},
+    calendar::{DateTime, Dst, TimeZone},
+    display::{LedPanel, NUM_DEVICES},
+    protocol,
+    reconnect::{Backoff, Failure},
+    session::{self, SessionError},
+};
+use std::{
+    cell::RefCell,
+    convert::Infallible,
+    env, fmt,
+    path::PathBuf,
+    process, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// log lines go to stderr so that they do not get in the way of the panel drawn on stdout
+macro_rules! rprintln {
+    ($($arg:tt)*) => {
+        eprintln!($($arg)*)
+    };
+}
+
+mod export;
+mod max7219;
+mod screen;
+mod terminal;
+mod websocket;
+
+use export::{Gif, PngFrames};
+use max7219::{Chain, ChipSelect, Spi};
+use screen::{Output, Screen};
+use terminal::Terminal;
+use websocket::WebSocketError;
+
+// a led-display-server running on this machine, see its main.rs for the address it binds to
+const DEFAULT_URL: &str = "ws://127.0.0.1:8663/ws/ledpanel";
+
+// local time zone for the clock and brightness schedule, see led-display-hardware/src/time.rs
+const TIME_ZONE: TimeZone = TimeZone {
+    offset_secs: 0,
+    dst: Dst::Eu,
+};
+
+const USAGE: &str = "usage: led-display-simulator [--url <ws://host:port/ws/room>] [--gif <file>] \
+                     [--png <dir>] [--seconds <n>] [--headless]";
+
+enum SimulatorError {
+    Connect(WebSocketError),
+    Session(SessionError<WebSocketError, Infallible, Infallible>),
+}
+
+impl SimulatorError {
+    // classify the error so that the main loop can pick a suitable backoff
+    fn failure(&self) -> Failure {
+        match self {
+            SimulatorError::Connect(err) if matches!(**err, tungstenite::Error::Io(_)) => {
+                Failure::Tcp
+            }
+            SimulatorError::Connect(_) => Failure::Handshake,
+            SimulatorError::Session(_) => Failure::Session,
+        }
+    }
+}
+
+impl fmt::Display for SimulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulatorError::Connect(err) => write!(f, "Could not connect: {}", err),
+            SimulatorError::Session(err) => write!(f, "Session failed: {:?}", err),
+        }
+    }
+}
+
+impl From<SessionError<WebSocketError, Infallible, Infallible>> for SimulatorError {
+    fn from(err: SessionError<WebSocketError, Infallible, Infallible>) -> SimulatorError {
+        SimulatorError::Session(err)
+    }
+}
+
+struct Options {
+    url: String,
+    gif: Option<PathBuf>,
+    png: Option<PathBuf>,
+    // stop after this long, e.g. when recording or in CI
+    seconds: Option<u64>,
+    // do not draw the panel in the terminal
+    headless: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            url: DEFAULT_URL.into(),
+            gif: None,
+            png: None,
+            seconds: None,
+            headless: false,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--url" => options.url = value()?,
+                "--gif" => options.gif = Some(value()?.into()),
+                "--png" => options.png = Some(value()?.into()),
+                "--seconds" => {
+                    let seconds = value()?;
+                    let seconds = seconds
+                        .parse()
+                        .map_err(|_| format!("invalid number of seconds: {}", seconds))?;
+                    options.seconds = Some(seconds);
+                }
+                "--headless" => options.headless = true,
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+// the PC standing in for the board
+struct Desktop {
+    start: Instant,
+}
+
+impl Board for Desktop {
+    fn now_ms(&self) -> u32 {
+        self.start.elapsed().as_millis() as u32
+    }
+
+    fn local_time(&self) -> Option<DateTime> {
+        let unix_time = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        Some(TIME_ZONE.local(unix_time as i64))
+    }
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    led_display_core::log::set_logger(|args| eprintln!("{}", args));
+
+    let start = Instant::now();
+    let chain = Chain::shared(NUM_DEVICES);
+    let screen = match open_outputs(&options) {
+        Ok(outputs) => Screen::start(chain.clone(), outputs, start),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    };
+
+    run(&options, &chain, start);
+
+    if let Err(err) = screen.stop() {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn open_outputs(options: &Options) -> std::io::Result<Vec<Box<dyn Output>>> {
+    let mut outputs: Vec<Box<dyn Output>> = Vec::new();
+    if !options.headless {
+        outputs.push(Box::new(Terminal::new()));
+    }
+
+    if let Some(path) = &options.gif {
+        outputs.push(Box::new(Gif::create(path, NUM_DEVICES * 8)?));
+    }
+
+    if let Some(dir) = &options.png {
+        outputs.push(Box::new(PngFrames::create(dir)?));
+    }
+
+    Ok(outputs)
+}
+
+// the main loop of the firmware, returns once --seconds have passed
+fn run(options: &Options, chain: &max7219::SharedChain, start: Instant) {
+    let stop_at = options
+        .seconds
+        .map(|seconds| start + Duration::from_secs(seconds));
+    let stopped = || stop_at.is_some_and(|stop_at| Instant::now() >= stop_at);
+
+    let spi = RefCell::new(Spi(chain.clone()));
+    let mut cs = ChipSelect(chain.clone());
+    let mut app = App::new(LedPanel::new(&mut cs, &spi));
+    let mut board = Desktop { start };
+    let mut backoff = Backoff::new(process::id());
+
+    while !stopped() {
+        let failure =
+            match client_connect(&mut app, &mut board, &mut backoff, &options.url, stop_at) {
+                Ok(()) => {
+                    rprintln!("[INF] Connection closed");
+                    Failure::Session
+                }
+                Err(error) => {
+                    rprintln!("[ERR] {}", &error);
+                    error.failure()
+                }
+            };
+
+        // the connection was up for a while so whatever goes wrong next is a fresh problem
+        backoff.disconnected(board.now_ms());
+
+        let delay_ms = backoff.next_delay_ms(failure);
+        rprintln!("[INF] Reconnecting in {} ms ({:?})", delay_ms, failure);
+        app.panel_mut().set_status(Some(failure.glyph()));
+
+        // keep the display going while we wait
+        let wait_start = Instant::now();
+        while wait_start.elapsed() < Duration::from_millis(delay_ms as u64) && !stopped() {
+            if let Err(error) = app.poll(&mut board) {
+                rprintln!("[ERR] {:?}", &error);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn client_connect(
+    app: &mut App<Spi, ChipSelect>,
+    board: &mut Desktop,
+    backoff: &mut Backoff,
+    url: &str,
+    stop_at: Option<Instant>,
+) -> Result<(), SimulatorError> {
+    let mut connection = websocket::connect(url, stop_at).map_err(SimulatorError::Connect)?;
+    app.panel_mut().set_status(None);
+    backoff.connected(board.now_ms());
+
+    let mut frame_buf = [0; protocol::MAX_BINARY_LEN];
+    session::run(app, &mut connection, board, &mut frame_buf)?;
+    Ok(())
+}
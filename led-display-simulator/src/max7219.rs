@@ -0,0 +1,221 @@
+use embedded_hal::{blocking::spi::Write, digital::v2::OutputPin};
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+// Simulated daisy chain of MAX7219 led matrix modules
+// Every 16 bit word clocked in while CS is low pushes the words already in the chain one device
+// further along and each device latches the word it holds when CS goes high. The first word of a
+// transaction therefore lands in the device furthest from the microcontroller, which the driver
+// in led-display-core treats as the leftmost one. A transaction with fewer words than devices
+// leaves the furthest devices to latch whatever they held before, just like the real thing.
+
+const NO_OP: u8 = 0x00;
+const ROW_0: u8 = 0x01;
+const ROW_7: u8 = 0x08;
+const INTENSITY: u8 = 0x0A;
+const SHUTDOWN: u8 = 0x0C;
+const DISPLAY_TEST: u8 = 0x0F;
+
+// what one module shows, the most significant bit of a row is its leftmost led
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Device {
+    pub rows: [u8; 8],
+    pub intensity: u8,
+    // false while in shutdown mode, which is how the devices power up
+    pub on: bool,
+    pub display_test: bool,
+}
+
+// the latched state of every device, device 0 is the leftmost
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panel {
+    devices: Vec<Device>,
+}
+
+pub struct Chain {
+    panel: Panel,
+    // the word held by each device, in the same order as the panel
+    shift: Vec<[u8; 2]>,
+    // first byte of a word that is still being clocked in
+    pending: Option<u8>,
+    changed: bool,
+}
+
+// the SPI bus and CS pin share the chain, the display thread takes snapshots of it
+pub type SharedChain = Arc<Mutex<Chain>>;
+
+pub struct Spi(pub SharedChain);
+
+pub struct ChipSelect(pub SharedChain);
+
+impl Panel {
+    pub fn width(&self) -> usize {
+        self.devices.len() * 8
+    }
+
+    pub fn intensity(&self) -> u8 {
+        self.devices.first().map_or(0, |device| device.intensity)
+    }
+
+    // x from the left, y from the top (0-7)
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        let device = &self.devices[x / 8];
+        if !device.on {
+            return false;
+        }
+
+        device.display_test || device.rows[y] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+impl Chain {
+    pub fn new(num_devices: usize) -> Self {
+        Self {
+            panel: Panel {
+                devices: vec![Device::default(); num_devices],
+            },
+            shift: vec![[NO_OP, 0]; num_devices],
+            pending: None,
+            changed: true,
+        }
+    }
+
+    pub fn shared(num_devices: usize) -> SharedChain {
+        Arc::new(Mutex::new(Self::new(num_devices)))
+    }
+
+    #[cfg(test)]
+    pub fn panel(&self) -> &Panel {
+        &self.panel
+    }
+
+    // a copy of the panel if anything was latched since the last call
+    pub fn take_changed(&mut self) -> Option<Panel> {
+        if !self.changed {
+            return None;
+        }
+
+        self.changed = false;
+        Some(self.panel.clone())
+    }
+
+    fn clock_in(&mut self, byte: u8) {
+        match self.pending.take() {
+            None => self.pending = Some(byte),
+            Some(register) => {
+                self.shift.remove(0);
+                self.shift.push([register, byte]);
+            }
+        }
+    }
+
+    fn latch(&mut self) {
+        self.pending = None;
+        for (device, [register, data]) in self.panel.devices.iter_mut().zip(&self.shift) {
+            let before = *device;
+            match *register {
+                ROW_0..=ROW_7 => device.rows[(register - ROW_0) as usize] = *data,
+                INTENSITY => device.intensity = data & 0x0F,
+                SHUTDOWN => device.on = data & 1 != 0,
+                DISPLAY_TEST => device.display_test = data & 1 != 0,
+                // no-op, decode mode and scan limit do not change what the panel looks like
+                _ => {}
+            }
+
+            self.changed |= *device != before;
+        }
+    }
+}
+
+impl Write<u8> for Spi {
+    type Error = Infallible;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        let mut chain = self.0.lock().unwrap();
+        for byte in words {
+            chain.clock_in(*byte);
+        }
+
+        Ok(())
+    }
+}
+
+impl OutputPin for ChipSelect {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.lock().unwrap().pending = None;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.lock().unwrap().latch();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(chain: &SharedChain, words: &[[u8; 2]]) {
+        let mut cs = ChipSelect(chain.clone());
+        let mut spi = Spi(chain.clone());
+        cs.set_low().unwrap();
+        for word in words {
+            spi.write(word).unwrap();
+        }
+        cs.set_high().unwrap();
+    }
+
+    #[test]
+    fn first_word_lands_in_leftmost_device() {
+        let chain = Chain::shared(3);
+        transaction(&chain, &[[SHUTDOWN, 1]; 3]);
+        transaction(&chain, &[[ROW_0, 0x80], [ROW_0, 0x00], [ROW_0, 0x01]]);
+
+        let chain = chain.lock().unwrap();
+        let lit: Vec<usize> = (0..24).filter(|x| chain.panel().is_lit(*x, 0)).collect();
+        assert_eq!(lit, [0, 23]);
+    }
+
+    #[test]
+    fn short_transaction_only_reaches_nearest_devices() {
+        let chain = Chain::shared(3);
+        transaction(&chain, &[[SHUTDOWN, 1]; 3]);
+        transaction(&chain, &[[ROW_7, 0xFF]]);
+
+        let chain = chain.lock().unwrap();
+        assert!(!chain.panel().is_lit(0, 7));
+        assert!(chain.panel().is_lit(16, 7));
+    }
+
+    #[test]
+    fn shutdown_and_display_test() {
+        let chain = Chain::shared(2);
+        transaction(&chain, &[[DISPLAY_TEST, 1]; 2]);
+        assert!(!chain.lock().unwrap().panel().is_lit(5, 5));
+
+        transaction(&chain, &[[SHUTDOWN, 1]; 2]);
+        assert!(chain.lock().unwrap().panel().is_lit(5, 5));
+
+        transaction(&chain, &[[INTENSITY, 0x17]; 2]);
+        assert_eq!(chain.lock().unwrap().panel().intensity(), 7);
+    }
+
+    #[test]
+    fn changes_are_reported_once() {
+        let chain = Chain::shared(1);
+        let mut locked = chain.lock().unwrap();
+        assert!(locked.take_changed().is_some());
+        assert!(locked.take_changed().is_none());
+        drop(locked);
+
+        transaction(&chain, &[[SHUTDOWN, 1]]);
+        assert!(chain.lock().unwrap().take_changed().is_some());
+        transaction(&chain, &[[SHUTDOWN, 1]]);
+        assert!(chain.lock().unwrap().take_changed().is_none());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::max7219::{Panel, SharedChain};
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// Shows the simulated panel
+// The leds stay lit between SPI transactions so the panel is looked at from a thread of its own
+// a few dozen times a second, much like a camera pointed at the real thing. Only frames that
+// differ from the last one are passed on.
+
+// how often the panel is looked at
+const FRAME_INTERVAL_MS: u64 = 20;
+
+// somewhere to send the frames, the terminal or a file
+pub trait Output: Send {
+    // now_ms is the time since the simulator started
+    fn frame(&mut self, panel: &Panel, now_ms: u32) -> io::Result<()>;
+
+    // called once the simulator stops
+    fn finish(&mut self, _now_ms: u32) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Screen {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl Screen {
+    pub fn start(chain: SharedChain, mut outputs: Vec<Box<dyn Output>>, start: Instant) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopping = stop.clone();
+        let thread = thread::spawn(move || loop {
+            let now_ms = start.elapsed().as_millis() as u32;
+            let changed = chain.lock().unwrap().take_changed();
+            if let Some(panel) = changed {
+                for output in outputs.iter_mut() {
+                    output.frame(&panel, now_ms)?;
+                }
+            }
+
+            if stopping.load(Ordering::Relaxed) {
+                for output in outputs.iter_mut() {
+                    output.finish(now_ms)?;
+                }
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_millis(FRAME_INTERVAL_MS));
+        });
+
+        Self { stop, thread }
+    }
+
+    // takes one last look at the panel and closes the outputs
+    pub fn stop(self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("screen thread panicked")))
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{max7219::Panel, screen::Output};
+use std::io::{self, Write};
+
+// Draws the panel in the terminal with block characters, two rows of leds to a line
+// Each frame is drawn over the last one so log lines (written to stderr) get in the way, send
+// them somewhere else with `2> simulator.log`.
+
+const LINES: usize = 4 + 2;
+
+const RED: &str = "\x1b[31m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+// the MAX7219 intensity below which the leds are drawn dimmed
+const DIM_BELOW: u8 = 6;
+
+pub struct Terminal {
+    drawn: bool,
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Self { drawn: false }
+    }
+}
+
+impl Output for Terminal {
+    fn frame(&mut self, panel: &Panel, _now_ms: u32) -> io::Result<()> {
+        let width = panel.width();
+        let mut text = String::new();
+        if self.drawn {
+            // back up to the top of the last frame
+            text.push_str(&format!("\x1b[{}A", LINES));
+        }
+
+        text.push('┌');
+        text.extend(std::iter::repeat_n('─', width));
+        text.push_str("┐\n");
+
+        let colour = if panel.intensity() < DIM_BELOW {
+            format!("{}{}", RED, DIM)
+        } else {
+            RED.to_string()
+        };
+
+        for y in (0..8).step_by(2) {
+            text.push('│');
+            text.push_str(&colour);
+            for x in 0..width {
+                text.push(match (panel.is_lit(x, y), panel.is_lit(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            text.push_str(RESET);
+            text.push_str("│\n");
+        }
+
+        text.push('└');
+        text.extend(std::iter::repeat_n('─', width));
+        text.push_str("┘\n");
+
+        let mut stdout = io::stdout();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+        self.drawn = true;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use led_display_core::session::{Connection, Received};
+use std::{
+    io,
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+use tungstenite::{error::CapacityError, handshake::HandshakeError, Message};
+
+// The websocket to the server over a real TCP socket, the simulator's version of the W5500
+// stream and embedded-websocket framer in the firmware
+
+// how long we wait for the server during the websocket opening handshake
+const HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+
+// how long a read waits for data before the panel gets a turn, the firmware polls the W5500
+// every few milliseconds too
+const READ_TIMEOUT_MS: u64 = 1;
+
+// tungstenite errors are large so they are boxed to keep results small
+pub type WebSocketError = Box<tungstenite::Error>;
+
+pub struct WebSocket {
+    socket: tungstenite::WebSocket<TcpStream>,
+    // the session is ended here so that the simulator can stop by itself
+    close_at: Option<Instant>,
+}
+
+// opens the websocket at a url like ws://127.0.0.1:8663/ws/ledpanel
+pub fn connect(url: &str, close_at: Option<Instant>) -> Result<WebSocket, WebSocketError> {
+    let address = url
+        .strip_prefix("ws://")
+        .and_then(|rest| rest.split('/').next())
+        .filter(|address| !address.is_empty())
+        .ok_or(tungstenite::Error::Url(
+            tungstenite::error::UrlError::UnsupportedUrlScheme,
+        ))?;
+
+    rprintln!("[INF] Connecting to {}", address);
+    let stream = TcpStream::connect(address).map_err(tungstenite::Error::Io)?;
+    stream
+        .set_read_timeout(Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MS)))
+        .map_err(tungstenite::Error::Io)?;
+
+    let (socket, _) = tungstenite::client(url, stream).map_err(|err| match err {
+        HandshakeError::Failure(err) => err,
+        HandshakeError::Interrupted(_) => tungstenite::Error::Io(io::ErrorKind::TimedOut.into()),
+    })?;
+    rprintln!("[INF] Websocket opening handshake complete");
+
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS)))
+        .map_err(tungstenite::Error::Io)?;
+    Ok(WebSocket { socket, close_at })
+}
+
+// the session loop reads frames into a buffer of its own like the firmware does
+fn copy_into<'b>(buf: &'b mut [u8], data: &[u8]) -> Result<&'b [u8], WebSocketError> {
+    if data.len() > buf.len() {
+        let err = CapacityError::MessageTooLong {
+            size: data.len(),
+            max_size: buf.len(),
+        };
+        return Err(Box::new(tungstenite::Error::Capacity(err)));
+    }
+
+    buf[..data.len()].copy_from_slice(data);
+    Ok(&buf[..data.len()])
+}
+
+impl Connection for WebSocket {
+    type Error = WebSocketError;
+
+    fn read<'b>(&mut self, buf: &'b mut [u8]) -> Result<Received<'b>, Self::Error> {
+        if let Some(close_at) = self.close_at {
+            if Instant::now() >= close_at {
+                self.close()?;
+                return Ok(Received::Closed);
+            }
+        }
+
+        match self.socket.read() {
+            Ok(Message::Text(text)) => {
+                let text = copy_into(buf, text.as_bytes())?;
+                // copied from a str so it is still valid utf-8
+                Ok(Received::Text(
+                    std::str::from_utf8(text).unwrap_or_default(),
+                ))
+            }
+            Ok(Message::Binary(data)) => Ok(Received::Binary(copy_into(buf, &data)?)),
+            Ok(Message::Pong(_)) => Ok(Received::Pong),
+            Ok(Message::Close(_)) => Ok(Received::Closed),
+            // tungstenite answers pings by itself
+            Ok(Message::Ping(_)) | Ok(Message::Frame(_)) => Ok(Received::Nothing),
+            Err(tungstenite::Error::Io(err))
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(Received::Nothing)
+            }
+            Err(tungstenite::Error::ConnectionClosed) => Ok(Received::Closed),
+            Err(err) => Err(Box::new(err)),
+        }
+    }
+
+    fn send_text(&mut self, text: &str) -> Result<(), Self::Error> {
+        Ok(self.socket.send(Message::Text(text.into()))?)
+    }
+
+    fn send_ping(&mut self) -> Result<(), Self::Error> {
+        Ok(self.socket.send(Message::Ping(Vec::new()))?)
+    }
+
+    fn close(&mut self) -> Result<(), Self::Error> {
+        // best effort, the server is probably not there anymore
+        let _ = self.socket.close(None);
+        let _ = self.socket.flush();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::max7219::{Chain, ChipSelect, Spi};
+    use led_display_core::{
+        app::{App, Board},
+        display::{LedPanel, NUM_DEVICES},
+        protocol, session,
+    };
+    use std::{cell::RefCell, net::TcpListener, thread};
+
+    struct TestBoard {
+        start: Instant,
+    }
+
+    impl Board for TestBoard {
+        fn now_ms(&self) -> u32 {
+            self.start.elapsed().as_millis() as u32
+        }
+    }
+
+    // a server that sends the frames, waits a while for them to be shown and hangs up
+    fn serve(frames: Vec<Message>, show_ms: u64) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/ws/test", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            for frame in frames {
+                socket.send(frame).unwrap();
+            }
+            thread::sleep(Duration::from_millis(show_ms));
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+        });
+        url
+    }
+
+    fn lit_columns(chain: &crate::max7219::SharedChain) -> Vec<usize> {
+        let panel = chain.lock().unwrap().take_changed().unwrap();
+        (0..panel.width())
+            .filter(|x| (0..8).any(|y| panel.is_lit(*x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn message_from_server_is_shown() {
+        let url = serve(
+            vec![
+                Message::Text("#fx static Hello".into()),
+                Message::Binary(vec![9]),
+            ],
+            500,
+        );
+
+        let chain = Chain::shared(NUM_DEVICES);
+        let spi = RefCell::new(Spi(chain.clone()));
+        let mut cs = ChipSelect(chain.clone());
+        let mut app = App::new(LedPanel::new(&mut cs, &spi));
+        let mut board = TestBoard {
+            start: Instant::now(),
+        };
+        let mut frame_buf = [0; protocol::MAX_BINARY_LEN];
+
+        let mut connection = connect(&url, None).unwrap();
+        session::run(&mut app, &mut connection, &mut board, &mut frame_buf).unwrap();
+
+        // static text is centred
+        let lit = lit_columns(&chain);
+        assert!(!lit.is_empty());
+        let width = NUM_DEVICES * 8;
+        let (left, right) = (lit[0], width - 1 - lit[lit.len() - 1]);
+        assert!((left as i32 - right as i32).abs() <= 1, "{:?}", lit);
+    }
+
+    #[test]
+    fn session_ends_when_asked_to() {
+        let url = serve(vec![], 5_000);
+        let chain = Chain::shared(NUM_DEVICES);
+        let spi = RefCell::new(Spi(chain.clone()));
+        let mut cs = ChipSelect(chain.clone());
+        let mut app = App::new(LedPanel::new(&mut cs, &spi));
+        let start = Instant::now();
+        let mut board = TestBoard { start };
+        let mut frame_buf = [0; protocol::MAX_BINARY_LEN];
+
+        let close_at = start + Duration::from_millis(200);
+        let mut connection = connect(&url, Some(close_at)).unwrap();
+        session::run(&mut app, &mut connection, &mut board, &mut frame_buf).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn bad_url() {
+        assert!(connect("http://127.0.0.1:1/ws/test", None).is_err());
+    }
+}
@@ -2,6 +2,6 @@ REM serialitm can be found here: https://github.com/ninjasource/serialitm.git
 cd ..\serialitm
 start "serialitm" cmd.exe /k "cargo run com3"
 
-cd ..\led-display-websocket-demo\led-display-hardware-ssl
+cd ..\led-display-websocket-demo\led-display-hardware
 start "openocd" cmd.exe /k "openocd"
-start "demo hardware" cmd.exe /k "cargo run"
+start "demo hardware" cmd.exe /k "cargo run --features tls"